@@ -2,9 +2,15 @@ fn main() {
     let pkg = std::env::var("CARGO_PKG_NAME").unwrap();
 
     eprintln!("{pkg}");
-    let name = pkg.strip_prefix("wl-usi-").unwrap();
+    // Extension subsystems that are specific to winter-lily (like `wl-native-subsys`) keep the `wl-` prefix in their soname
+    let name = pkg
+        .strip_prefix("wl-usi-")
+        .or_else(|| pkg.strip_suffix("-subsys"))
+        .unwrap();
     println!("cargo::rerun-if-changed=../build-usi-lib.rs");
 
+    gen_sysno();
+
     println!("cargo::rustc-link-lib=dylib=wl_ld_lilium");
     println!("cargo::rustc-link-arg=-soname");
     println!("cargo::rustc-link-arg=libusi-{name}.so");
@@ -15,3 +21,218 @@ fn main() {
     };
     println!("cargo::rustc-link-search=native={link_target_dir}");
 }
+
+/// Generates `$OUT_DIR/sysno.rs` and `$OUT_DIR/knum.rs` from the `.knum` files of the subsystem.
+///
+/// For each `fn Name(...) -> T = N;` declaration, this emits `pub const SYS_Name: usize = N;` into `sysno.rs`,
+/// and the names of all declared syscalls are collected in `DECLARED_SYSCALLS` for `def_subsystem!`.
+/// `N` may also be the path of a constant (such as the syscall numbers of the standard subsystems in `lilium-sys`).
+///
+/// `knum.rs` contains a module for each `.knum` file, with its constants, and the structs whose fields are all integers (or arrays of integers), so that they are only defined once.
+/// Other structs (such as `SysInfoRequest` options) are defined by hand.
+fn gen_sysno() {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+
+    let mut knum_files = std::fs::read_dir(&manifest_dir)
+        .unwrap()
+        .map(|ent| ent.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "knum"))
+        .collect::<Vec<_>>();
+    knum_files.sort();
+
+    let mut syscalls = Vec::new();
+    let mut items = String::new();
+
+    for path in &knum_files {
+        println!("cargo::rerun-if-changed={}", path.display());
+        let src = std::fs::read_to_string(path).unwrap();
+
+        let module = path.file_stem().unwrap().to_str().unwrap();
+        items += &format!("pub mod {module} {{\n");
+
+        for stmt in knum_statements(path, &src) {
+            let (docs, decl) = split_docs(&stmt);
+            if let Some(decl) = decl.strip_prefix("fn ") {
+                let (Some((name, _)), Some((_, num))) =
+                    (decl.split_once('('), decl.rsplit_once('='))
+                else {
+                    panic!("{}: Invalid syscall declaration `{stmt}`", path.display());
+                };
+                syscalls.push((name.trim().to_string(), num.trim().to_string()));
+            } else if let Some(decl) = decl.strip_prefix("pub ") {
+                items += &format!("{docs}pub const {decl};\n");
+            } else if let Some(decl) = decl.strip_prefix("struct ") {
+                items += &gen_struct(docs, decl).unwrap_or_default();
+            }
+        }
+
+        items += "}\n";
+    }
+
+    let mut out = String::new();
+    for (name, num) in &syscalls {
+        if num.starts_with(|c: char| c.is_ascii_digit()) {
+            out += &format!("pub const SYS_{name}: usize = {num};\n");
+        } else {
+            // The constant may already be a `usize`
+            out += &format!(
+                "#[allow(clippy::unnecessary_cast)]\npub const SYS_{name}: usize = {num} as usize;\n"
+            );
+        }
+    }
+    out += "pub const DECLARED_SYSCALLS: &[&str] = &[";
+    for (name, _) in &syscalls {
+        out += &format!("\"{name}\", ");
+    }
+    out += "];\n";
+
+    std::fs::write(format!("{out_dir}/sysno.rs"), out).unwrap();
+    std::fs::write(format!("{out_dir}/knum.rs"), items).unwrap();
+}
+
+/// Generates a `#[repr(C)]` and `Pod` definition of the knum struct `decl` (without the leading `struct`).
+///
+/// Returns `None` if the struct has a field that isn't an integer or an array of integers, or is a `SysInfoRequest` option
+fn gen_struct(docs: &str, decl: &str) -> Option<String> {
+    // The closing brace isn't part of the statement
+    let (name, body) = decl.split_once('{')?;
+    let name = name.trim();
+
+    if name.contains(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        return None;
+    }
+
+    let mut out = format!(
+        "{docs}#[repr(C)]\n#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]\npub struct {name} {{\n"
+    );
+
+    for field in split_top_level(body, ',') {
+        let (field_docs, field) = split_docs(field);
+        if field.is_empty() {
+            continue;
+        }
+        let (field_name, ty) = field.split_once(':')?;
+        let ty = ty.trim();
+        if !is_integer_type(ty) {
+            return None;
+        }
+        out += &format!("    {field_docs}pub {}: {ty},\n", field_name.trim());
+    }
+
+    out += "}\n";
+    Some(out)
+}
+
+fn is_integer_type(ty: &str) -> bool {
+    const INTEGERS: &[&str] = &[
+        "u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize",
+    ];
+
+    match ty.strip_prefix('[').and_then(|ty| ty.strip_suffix(']')) {
+        Some(array) => array.split_once(';').is_some_and(|(elem, len)| {
+            is_integer_type(elem.trim()) && len.trim().parse::<usize>().is_ok()
+        }),
+        None => INTEGERS.contains(&ty),
+    }
+}
+
+/// Splits the leading `#[doc = "..."]` attributes (produced from doc comments by [`knum_statements`]) from `stmt`
+fn split_docs(stmt: &str) -> (&str, &str) {
+    let mut rest = stmt.trim_start();
+
+    while let Some(lit) = rest.strip_prefix("#[doc = \"") {
+        let mut escaped = false;
+        let end = lit
+            .char_indices()
+            .find(|&(_, c)| match c {
+                _ if escaped => {
+                    escaped = false;
+                    false
+                }
+                '\\' => {
+                    escaped = true;
+                    false
+                }
+                c => c == '"',
+            })
+            .map(|(idx, _)| idx)
+            .unwrap();
+        rest = lit[end + 1..].strip_prefix(']').unwrap().trim_start();
+    }
+
+    let docs = &stmt[..stmt.len() - rest.len()];
+    (docs, rest.trim())
+}
+
+/// Splits `src` at each `sep` outside of brackets and string literals
+fn split_top_level(src: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (idx, c) in src.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_str => escaped = true,
+            '"' => in_str = !in_str,
+            _ if in_str => {}
+            '(' | '[' | '{' | '<' => depth += 1,
+            ')' | ']' | '}' | '>' => depth = depth.saturating_sub(1),
+            c if c == sep && depth == 0 => {
+                parts.push(&src[start..idx]);
+                start = idx + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&src[start..]);
+    parts
+}
+
+/// Splits knum source into top-level statements, with comments removed, and doc comments turned into `#[doc = "..."]` attributes.
+///
+/// A statement ends at a `;` or at the closing brace of a body, outside of any brackets
+fn knum_statements(path: &std::path::Path, src: &str) -> Vec<String> {
+    let mut stmts = Vec::new();
+    let mut cur = String::new();
+    let mut depth = 0usize;
+
+    for line in src.lines() {
+        if let Some(doc) = line.trim_start().strip_prefix("///") {
+            cur += &format!("#[doc = {doc:?}] ");
+            continue;
+        }
+        let line = line.split_once("//").map_or(line, |(code, _)| code);
+        for c in line.chars().chain([' ']) {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => {
+                    let Some(d) = depth.checked_sub(1) else {
+                        panic!("{}: Unbalanced `{c}`", path.display());
+                    };
+                    depth = d;
+                    if c == '}' && depth == 0 {
+                        stmts.push(core::mem::take(&mut cur).trim().to_string());
+                        continue;
+                    }
+                }
+                ';' if depth == 0 => {
+                    stmts.push(core::mem::take(&mut cur).trim().to_string());
+                    continue;
+                }
+                _ => {}
+            }
+            cur.push(c);
+        }
+    }
+
+    if depth != 0 {
+        panic!("{}: Unclosed bracket at end of file", path.display());
+    }
+
+    stmts
+}
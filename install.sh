@@ -306,4 +306,16 @@ for subsys in $(cat ${whereami}/subsysnames)
 do
     install_lib "${_host_libdir}/libwl-usi-$subsys.so" "$CARGO_TARGET_DIR/$TARGET_RUST/release/libwl_usi_$subsys.so" || exit $?
     libdir="$_libdir" host_libdir="$_host_libdir" usilib="$subsys" install_template "${_libdir}/libusi-$subsys.so" "${whereami}/install/scripts/libusi-X.so.in" libdir host_libdir usilib || exit $?
-done
\ No newline at end of file
+done
+
+_subsys_conf="$(mktemp)"
+cat "${whereami}/install/subsystems.conf" > "$_subsys_conf" || exit $?
+for subsys in $(cat ${whereami}/subsysnames)
+do
+    grep -q "^${subsys}[[:space:]]" "$_subsys_conf" || echo "${subsys} dynamic eager" >> "$_subsys_conf"
+done
+install_other "${_sysconfdir}/winter-lily/subsystems.conf" "$_subsys_conf" || exit $?
+[ $_save_temps -ne 1 ] && unlink "$_subsys_conf"
+
+install_lib "${_host_libdir}/libwl-native-subsys.so" "$CARGO_TARGET_DIR/$TARGET_RUST/release/libwl_native_subsys.so" || exit $?
+host_libdir="$_host_libdir" install_template "${_libdir}/libusi-wl-native.so" "${whereami}/install/scripts/libusi-wl-native.so.in" host_libdir || exit $?
\ No newline at end of file
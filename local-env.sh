@@ -4,4 +4,5 @@ unset WL_SYSROOT
 for subsys in $(cat subsysnames)
 do
     export WL_SUBSYS_${subsys}="$(pwd)/target/${TARGET_RUST}/${TARGET_PATH}/libwl_usi_${subsys}.so"
-done
\ No newline at end of file
+done
+export WL_SUBSYS_native="$(pwd)/target/${TARGET_RUST}/${TARGET_PATH}/libwl_native_subsys.so"
\ No newline at end of file
@@ -1,24 +1,18 @@
 #![no_std]
 #![feature(never_type)]
-use wl_impl::{
-    erase, helpers::insert_elems, syscall_handler::register_subsys,
-    syscall_helpers::SysCallTyErased, wl_init_subsystem_name, InitSubsystemTy,
-};
+use wl_impl::def_subsystem;
 
-static SYSCALLS: [Option<SysCallTyErased>; 4096] = insert_elems([None; 4096], []);
+/// Syscall numbers generated from the `.knum` files of the subsystem
+#[allow(non_upper_case_globals)]
+mod sysno {
+    include!(concat!(env!("OUT_DIR"), "/sysno.rs"));
+}
 
-static INFO: SubsysInfo = SubsysInfo {
+def_subsystem! {
     name: todo!("<Name of subsys here>"),
-    uuid: parse_uuid(todo!("<UUID of Subsys here>")),
-    subsys_version: 0,
-    max_sysno: 128,
-};
-
-#[unsafe(export_name = wl_init_subsystem_name!())]
-unsafe extern "C" fn init_subsystem() {
-    unsafe {
-        register_subsys(!0, &SYSCALLS, &INFO);
-    }
+    uuid: todo!("<UUID of Subsys here>"),
+    number: dynamic,
+    version: (0, 1, 0),
+    declared: sysno::DECLARED_SYSCALLS,
+    syscalls: [],
 }
-
-const _: InitSubsystemTy = init_subsystem;
@@ -0,0 +1,174 @@
+//! Minimal bindings to the Landlock LSM, shared by the loader (which applies the sandbox profile) and winter-lily (which tightens it at runtime).
+
+use linux_syscall::{
+    Result as _, SYS_close, SYS_landlock_add_rule, SYS_landlock_create_ruleset,
+    SYS_landlock_restrict_self, SYS_prctl, syscall,
+};
+
+pub use linux_errno::Error;
+
+pub const LANDLOCK_ACCESS_FS_EXECUTE: u64 = 1 << 0;
+pub const LANDLOCK_ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+pub const LANDLOCK_ACCESS_FS_READ_FILE: u64 = 1 << 2;
+pub const LANDLOCK_ACCESS_FS_READ_DIR: u64 = 1 << 3;
+pub const LANDLOCK_ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+pub const LANDLOCK_ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+pub const LANDLOCK_ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+pub const LANDLOCK_ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+pub const LANDLOCK_ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+pub const LANDLOCK_ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+pub const LANDLOCK_ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+pub const LANDLOCK_ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+pub const LANDLOCK_ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+/// Since ABI 2
+pub const LANDLOCK_ACCESS_FS_REFER: u64 = 1 << 13;
+/// Since ABI 3
+pub const LANDLOCK_ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+/// Since ABI 5
+pub const LANDLOCK_ACCESS_FS_IOCTL_DEV: u64 = 1 << 15;
+
+/// Since ABI 4
+pub const LANDLOCK_ACCESS_NET_BIND_TCP: u64 = 1 << 0;
+/// Since ABI 4
+pub const LANDLOCK_ACCESS_NET_CONNECT_TCP: u64 = 1 << 1;
+
+/// Accesses needed to read and execute files, and list directories
+pub const ACCESS_FS_READ: u64 =
+    LANDLOCK_ACCESS_FS_EXECUTE | LANDLOCK_ACCESS_FS_READ_FILE | LANDLOCK_ACCESS_FS_READ_DIR;
+
+/// Accesses that can be granted on a file that isn't a directory
+pub const ACCESS_FS_FILE: u64 = LANDLOCK_ACCESS_FS_EXECUTE
+    | LANDLOCK_ACCESS_FS_WRITE_FILE
+    | LANDLOCK_ACCESS_FS_READ_FILE
+    | LANDLOCK_ACCESS_FS_TRUNCATE
+    | LANDLOCK_ACCESS_FS_IOCTL_DEV;
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+
+const PR_SET_NO_NEW_PRIVS: u32 = 38;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+    handled_access_net: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// Returns the Landlock ABI version supported by the kernel, or `None` if Landlock isn't available
+pub fn abi_version() -> Option<u32> {
+    let res = unsafe {
+        syscall!(
+            SYS_landlock_create_ruleset,
+            core::ptr::null::<RulesetAttr>(),
+            0usize,
+            LANDLOCK_CREATE_RULESET_VERSION
+        )
+    };
+    res.check().ok()?;
+
+    Some(res.as_usize_unchecked() as u32)
+}
+
+/// All filesystem accesses that can be restricted with the Landlock ABI `abi`
+pub const fn fs_access_for_abi(abi: u32) -> u64 {
+    match abi {
+        0 => 0,
+        1 => (LANDLOCK_ACCESS_FS_MAKE_SYM << 1) - 1,
+        2 => (LANDLOCK_ACCESS_FS_REFER << 1) - 1,
+        3 | 4 => (LANDLOCK_ACCESS_FS_TRUNCATE << 1) - 1,
+        _ => (LANDLOCK_ACCESS_FS_IOCTL_DEV << 1) - 1,
+    }
+}
+
+/// All network accesses that can be restricted with the Landlock ABI `abi`
+pub const fn net_access_for_abi(abi: u32) -> u64 {
+    if abi >= 4 {
+        LANDLOCK_ACCESS_NET_BIND_TCP | LANDLOCK_ACCESS_NET_CONNECT_TCP
+    } else {
+        0
+    }
+}
+
+/// A Landlock ruleset that is being built.
+///
+/// Accesses that are handled by the ruleset are denied once it is applied, except where allowed by a rule.
+pub struct Ruleset {
+    fd: i32,
+    handled_fs: u64,
+}
+
+impl Ruleset {
+    /// Creates a ruleset that handles `handled_fs` and `handled_net`, which must be supported by the current ABI
+    pub fn new(handled_fs: u64, handled_net: u64) -> Result<Self, Error> {
+        let attr = RulesetAttr {
+            handled_access_fs: handled_fs,
+            handled_access_net: handled_net,
+        };
+
+        let res = unsafe {
+            syscall!(
+                SYS_landlock_create_ruleset,
+                &raw const attr,
+                size_of::<RulesetAttr>(),
+                0u32
+            )
+        };
+        res.check()?;
+
+        Ok(Self {
+            fd: res.as_usize_unchecked() as i32,
+            handled_fs,
+        })
+    }
+
+    /// Allows `access` beneath the file or directory open on `parent_fd`.
+    ///
+    /// Accesses that aren't handled by the ruleset are ignored, and if `is_dir` is `false`, so are accesses that only apply to directories.
+    pub fn allow_path(&mut self, parent_fd: i32, access: u64, is_dir: bool) -> Result<(), Error> {
+        let mut allowed_access = access & self.handled_fs;
+        if !is_dir {
+            allowed_access &= ACCESS_FS_FILE;
+        }
+
+        if allowed_access == 0 {
+            return Ok(());
+        }
+
+        let attr = PathBeneathAttr {
+            allowed_access,
+            parent_fd,
+        };
+
+        let res = unsafe {
+            syscall!(
+                SYS_landlock_add_rule,
+                self.fd,
+                LANDLOCK_RULE_PATH_BENEATH,
+                &raw const attr,
+                0u32
+            )
+        };
+        res.check()
+    }
+
+    /// Applies the ruleset to the calling thread, which is inherited by threads and processes it creates afterwards.
+    ///
+    /// This sets `no_new_privs`, which is required for unprivileged threads to restrict themselves.
+    pub fn restrict_self(self) -> Result<(), Error> {
+        unsafe { syscall!(SYS_prctl, PR_SET_NO_NEW_PRIVS, 1u32, 0u32, 0u32, 0u32) }.check()?;
+
+        unsafe { syscall!(SYS_landlock_restrict_self, self.fd, 0u32) }.check()
+    }
+}
+
+impl Drop for Ruleset {
+    fn drop(&mut self) {
+        let _ = unsafe { syscall!(SYS_close, self.fd) };
+    }
+}
@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(try_trait_v2, try_trait_v2_residual, allocator_api, alloc_layout_extra)]
 
 use core::{
@@ -472,3 +472,48 @@ pub mod rand;
 pub mod sync;
 
 pub mod detect;
+
+pub mod landlock;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::OnceLock;
+
+    // Mirrors how the loader initializes lazy subsystems: one cell per subsystem, where an init may load another subsystem
+    static SUBSYS: [OnceLock<()>; 2] = [const { OnceLock::new() }; 2];
+    static INITS: [AtomicUsize; 2] = [const { AtomicUsize::new(0) }; 2];
+
+    fn load(num: usize) {
+        SUBSYS[num].get_or_init(|| {
+            INITS[num].fetch_add(1, Ordering::Relaxed);
+            if num == 0 {
+                load(1);
+            }
+        });
+    }
+
+    #[test]
+    fn nested_init_of_two_lazy_subsystems() {
+        let threads: Vec<_> = (0..8)
+            .map(|i| std::thread::spawn(move || load(i % 2)))
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(SUBSYS.iter().all(|cell| cell.get().is_some()));
+        assert_eq!(INITS[0].load(Ordering::Relaxed), 1);
+        assert_eq!(INITS[1].load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn set_marks_initialized() {
+        let cell = OnceLock::new();
+        assert_eq!(cell.set(1), Ok(()));
+        assert_eq!(cell.set(2), Err(2));
+        assert_eq!(cell.get_or_init(|| 3), &1);
+    }
+}
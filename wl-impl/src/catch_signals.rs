@@ -33,11 +33,15 @@ pub fn sig_to_except(signo: u32) -> Option<Uuid> {
 #[unsafe(no_mangle)]
 unsafe extern "C" fn __sa_handler_seh_impl(signo: u32, siginfo: *mut siginfo_t, uctx: *mut c_void) {
     if signo == linux_raw_sys::general::SIGSYS {
+        let mcontext = unsafe { &raw mut (*uctx.cast::<ucontext_t>()).uc_mcontext };
         unsafe {
-            invoke_syscall_uctx(&raw mut (*uctx.cast::<ucontext_t>()).uc_mcontext);
+            invoke_syscall_uctx(mcontext);
         }
+        #[cfg(target_arch = "x86_64")]
+        crate::rewrite::try_rewrite_site(unsafe { (*mcontext).gregs[crate::libc::REG_RIP] });
         return;
     } else if signo == linux_raw_sys::general::SIGCHLD {
+        crate::handle_base::reap_orphans();
         return;
     }
 
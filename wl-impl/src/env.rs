@@ -1,11 +1,18 @@
-use crate::ministd::HashMap;
+use core::{
+    cell::Cell,
+    ffi::{CStr, c_char},
+};
+
+use crate::ministd::{HashMap, RwLock};
 
 use memchr::memchr;
 
 use alloc::{
+    ffi::CString,
     string::{String, ToString},
     vec::Vec,
 };
+use wl_helpers::LazyLock;
 
 const KEY_TERM: u8 = 0xFE;
 const ENTRY_TERM: u8 = 0xFF;
@@ -79,6 +86,12 @@ impl<K: Into<String> + AsRef<str>, V: AsRef<str>> FromIterator<(K, V)> for EnvMa
     }
 }
 
+impl Default for EnvMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K: AsRef<str>, V: AsRef<str>> Extend<(K, V)> for EnvMap {
     fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
         for (key, val) in iter {
@@ -88,9 +101,39 @@ impl<K: AsRef<str>, V: AsRef<str>> Extend<(K, V)> for EnvMap {
 }
 
 impl EnvMap {
+    pub fn new() -> Self {
+        Self {
+            env: Vec::new(),
+            env_map: HashMap::new(),
+            freelist: Vec::new(),
+        }
+    }
+
     pub fn iter(&self) -> Iter {
         Iter(&self.env)
     }
+
+    /// Returns the entry that starts at byte offset `pos` in the map (or the first entry after it, if that entry is free),
+    ///  together with the offset of the next entry.
+    ///
+    /// This allows iteration to be resumed between calls, as long as the map isn't modified in the meantime.
+    /// Returns `None` if there are no more entries, or if `pos` is not the start of an entry.
+    pub fn entry_at(&self, pos: usize) -> Option<(&str, &str, usize)> {
+        let mut off = 0;
+        while off < pos {
+            let (len, _) = self.env[off..].split_first_chunk()?;
+            off += usize::from_ne_bytes(*len);
+        }
+
+        if off != pos {
+            return None;
+        }
+
+        let mut iter = Iter(&self.env[pos..]);
+        let (key, val) = iter.next()?;
+
+        Some((key, val, self.env.len() - iter.0.len()))
+    }
     pub fn var(&self, key: &str) -> Option<&str> {
         if let Some(&var) = self.env_map.get(key) {
             let ptr = &self.env[(var + core::mem::size_of::<usize>())..];
@@ -109,6 +152,7 @@ impl EnvMap {
         if let Some(var) = self.env_map.remove(key) {
             let ptr = &mut self.env[(var + core::mem::size_of::<usize>())..];
             ptr[..2].copy_from_slice(&EMPTY);
+            self.freelist.push(var);
         }
     }
 
@@ -141,7 +185,7 @@ impl EnvMap {
                 .map(|v| usize::from_ne_bytes(v))
                 .expect("There is supposed to be a prefix here");
 
-            if tlen <= elen {
+            if elen <= tlen {
                 if let Some((key_owned, old_off)) = self.env_map.remove_entry(key) {
                     let old_ptr = &mut self.env[(old_off + core::mem::size_of::<usize>())..];
                     old_ptr[..2].copy_from_slice(&EMPTY);
@@ -152,7 +196,7 @@ impl EnvMap {
                     self.env_map.insert(key.to_string(), elem);
                 }
 
-                let ptr = &mut self.env[..elem];
+                let ptr = &mut self.env[elem..];
 
                 let off = core::mem::size_of::<usize>() + key.len();
                 ptr[core::mem::size_of::<usize>()..off].copy_from_slice(key.as_bytes());
@@ -190,24 +234,267 @@ impl<'a> Iterator for Iter<'a> {
     type Item = (&'a str, &'a str);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (len, rest) = self
-            .0
-            .split_first_chunk()
-            .map(|(a, b)| (usize::from_ne_bytes(*a), b))?;
+        loop {
+            let (len, rest) = self
+                .0
+                .split_first_chunk()
+                .map(|(a, b)| (usize::from_ne_bytes(*a), b))?;
+
+            self.0 = &self.0[len..];
+
+            let off = memchr(KEY_TERM, rest).expect("There's supposed to be a terminator here");
+
+            // Free entries have an empty key
+            if off == 0 {
+                continue;
+            }
 
-        self.0 = &self.0[len..];
+            let (key, rest) = rest.split_at(off);
+
+            let end = memchr(ENTRY_TERM, rest).expect("There's supposed to be a terminator here");
+
+            let val = &rest[1..end];
+
+            return Some((
+                core::str::from_utf8(key).unwrap(),
+                core::str::from_utf8(val).unwrap(),
+            ));
+        }
+    }
+}
+
+unsafe extern "C" {
+    safe static __environ: Cell<*const *const c_char>;
+}
+
+/// Prefixes of host environment variables that are hidden from Lilium programs.
+///
+/// These variables configure the host dynamic linker and winter-lily itself.
+/// They are still passed to child processes (so that winter-lily is configured the same way for them),
+///  but Lilium programs can neither see nor modify them.
+pub const HIDDEN_ENV_PREFIXES: [&str; 2] = ["LD_", "WL_"];
+
+/// Checks if `key` names a host environment variable that is hidden from Lilium programs
+pub fn is_hidden_var(key: &str) -> bool {
+    HIDDEN_ENV_PREFIXES
+        .iter()
+        .any(|prefix| key.starts_with(prefix))
+}
+
+/// Checks if `key` and `val` can be stored in the host environment.
+pub fn is_valid_var(key: &str, val: &str) -> bool {
+    !key.is_empty() && !key.contains(['=', '\0']) && !val.contains('\0')
+}
+
+fn host_environ() -> impl Iterator<Item = &'static CStr> {
+    let mut envp = __environ.get();
+    core::iter::from_fn(move || {
+        if envp.is_null() {
+            return None;
+        }
+        // SAFETY: `__environ` is a null-terminated array of C strings, set up by the loader
+        let var = unsafe { *envp };
+        if var.is_null() {
+            return None;
+        }
+        envp = unsafe { envp.add(1) };
+        Some(unsafe { CStr::from_ptr(var) })
+    })
+}
+
+fn split_var(var: &CStr) -> Option<(&str, &str)> {
+    var.to_str().ok()?.split_once('=')
+}
+
+/// Reads the host environment variable `key`, including variables that are hidden from Lilium programs.
+///
+/// This is the environment the process was started with, and isn't affected by changes made by the Lilium program.
+pub fn host_var(key: &str) -> Option<&'static str> {
+    host_environ()
+        .filter_map(split_var)
+        .find(|(k, _)| *k == key)
+        .map(|(_, val)| val)
+}
 
-        let off = memchr(KEY_TERM, rest).expect("There's supposed to be a terminator here");
+static ENVIRONMENT: LazyLock<RwLock<EnvMap>> = LazyLock::new(|| {
+    let mut env = EnvMap::new();
+
+    for (key, val) in host_environ().filter_map(split_var) {
+        if !is_hidden_var(key) && is_valid_var(key, val) {
+            env.set_var(key, val);
+        }
+    }
+
+    RwLock::new(env)
+});
+
+/// The environment of the Lilium program, initialized from the host environment without hidden variables
+pub fn environment() -> &'static RwLock<EnvMap> {
+    &ENVIRONMENT
+}
+
+/// Builds the host environment for a child process: the hidden host variables, followed by the environment of the Lilium program.
+///
+/// Host variables that aren't valid UTF-8 are hidden from Lilium programs, so they are passed through as well.
+pub fn child_environ() -> Vec<CString> {
+    let mut vars = Vec::new();
+
+    for var in host_environ() {
+        let passthrough = match split_var(var) {
+            Some((key, val)) => is_hidden_var(key) || !is_valid_var(key, val),
+            None => true,
+        };
+        if passthrough {
+            vars.push(var.to_owned());
+        }
+    }
+
+    for (key, val) in environment().read().iter() {
+        let mut var = Vec::with_capacity(key.len() + val.len() + 1);
+        var.extend_from_slice(key.as_bytes());
+        var.push(b'=');
+        var.extend_from_slice(val.as_bytes());
+        // `EnvMap` only contains variables accepted by `is_valid_var`
+        vars.push(CString::new(var).unwrap());
+    }
+
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::EnvMap;
+    use crate::helpers::rand::Gen;
+    use crate::ministd::Mutex;
+    use crate::rand::GLOBAL_SEED;
+
+    fn new_map() -> EnvMap {
+        // `HashMap` seeds its hasher from the global seed, which is normally set up by `__wl_impl_setup_process`
+        let _ = GLOBAL_SEED.set(Mutex::new(Gen::seed([0; 16])));
+        EnvMap::new()
+    }
+
+    fn entries(map: &EnvMap) -> Vec<(&str, &str)> {
+        map.iter().collect()
+    }
+
+    #[test]
+    fn set_and_get() {
+        let mut map = new_map();
+        map.set_var("FOO", "bar");
+        map.set_var("BAZ", "qux");
+
+        assert_eq!(map.var("FOO"), Some("bar"));
+        assert_eq!(map.var("BAZ"), Some("qux"));
+        assert_eq!(map.var("QUUX"), None);
+    }
+
+    #[test]
+    fn overwrite_in_place() {
+        let mut map = new_map();
+        map.set_var("FOO", "long value");
+        let len = map.env.len();
+
+        map.set_var("FOO", "short");
+
+        assert_eq!(map.var("FOO"), Some("short"));
+        assert_eq!(map.env.len(), len);
+        assert_eq!(entries(&map), [("FOO", "short")]);
+    }
+
+    #[test]
+    fn remove_then_reinsert_reuses_slot() {
+        let mut map = new_map();
+        map.set_var("FOO", "bar");
+        map.set_var("BAZ", "qux");
+        let len = map.env.len();
+
+        map.remove_var("FOO");
+        assert_eq!(map.var("FOO"), None);
+
+        map.set_var("FOO", "bar");
+        assert_eq!(map.var("FOO"), Some("bar"));
+        assert_eq!(map.env.len(), len);
+        assert!(map.freelist.is_empty());
+    }
+
+    #[test]
+    fn insert_reuses_freed_slot_of_other_key() {
+        let mut map = new_map();
+        map.set_var("FOO", "bar");
+        map.set_var("BAZ", "qux");
+        let len = map.env.len();
+
+        map.remove_var("FOO");
+        map.set_var("ABC", "xyz");
+
+        assert_eq!(map.var("ABC"), Some("xyz"));
+        assert_eq!(map.var("BAZ"), Some("qux"));
+        assert_eq!(map.var("FOO"), None);
+        assert_eq!(map.env.len(), len);
+        assert_eq!(entries(&map), [("ABC", "xyz"), ("BAZ", "qux")]);
+    }
+
+    #[test]
+    fn growing_value_frees_old_slot() {
+        let mut map = new_map();
+        map.set_var("FOO", "bar");
+        map.set_var("BAZ", "qux");
+
+        map.set_var("FOO", "a much longer value");
+        assert_eq!(map.var("FOO"), Some("a much longer value"));
+        assert_eq!(map.freelist.len(), 1);
+
+        let len = map.env.len();
+        map.set_var("X", "y");
+        assert_eq!(map.var("X"), Some("y"));
+        assert_eq!(map.env.len(), len);
+        assert!(map.freelist.is_empty());
+    }
+
+    #[test]
+    fn removing_missing_var_does_nothing() {
+        let mut map = new_map();
+        map.set_var("FOO", "bar");
+        map.remove_var("BAZ");
+
+        assert!(map.freelist.is_empty());
+        assert_eq!(entries(&map), [("FOO", "bar")]);
+    }
+
+    #[test]
+    fn iter_skips_freed_entries() {
+        let mut map = new_map();
+        map.set_var("A", "1");
+        map.set_var("B", "2");
+        map.set_var("C", "3");
+
+        map.remove_var("A");
+        map.remove_var("C");
+
+        assert_eq!(entries(&map), [("B", "2")]);
+
+        map.remove_var("B");
+        assert_eq!(entries(&map), []);
+    }
 
-        let (key, rest) = rest.split_at(off);
+    #[test]
+    fn entry_at_skips_freed_entries() {
+        let mut map = new_map();
+        map.set_var("A", "1");
+        map.set_var("B", "2");
+        map.set_var("C", "3");
+        map.remove_var("A");
 
-        let end = memchr(ENTRY_TERM, rest).expect("There's supposed to be a terminator here");
+        let (key, val, next) = map.entry_at(0).unwrap();
+        assert_eq!((key, val), ("B", "2"));
 
-        let val = &rest[1..end];
+        let (key, val, next) = map.entry_at(next).unwrap();
+        assert_eq!((key, val), ("C", "3"));
 
-        Some((
-            core::str::from_utf8(key).unwrap(),
-            core::str::from_utf8(val).unwrap(),
-        ))
+        assert!(map.entry_at(next).is_none());
+        assert!(map.entry_at(1).is_none());
     }
 }
@@ -2,6 +2,7 @@ use core::{
     cell::{Cell, UnsafeCell},
     ffi::c_long,
     num::{NonZero, NonZeroUsize},
+    sync::atomic::{AtomicI32, Ordering},
 };
 
 use alloc::sync::Arc;
@@ -10,20 +11,48 @@ use indexmap::IndexSet;
 use lilium_sys::{
     result::Result,
     sys::{
-        handle::{self as sys, HANDLE_TYPE_IO, HandlePtr},
-        kstr::KSlice,
+        handle::{self as sys, HANDLE_TYPE_PROC, HandlePtr},
+        kstr::{KCSlice, KSlice},
         result::SysResult,
     },
 };
 use linux_raw_sys::general::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
-use rustix::fd::BorrowedFd;
+use rustix::{
+    fd::{AsRawFd, BorrowedFd, IntoRawFd},
+    fs::{FileType, Mode, OFlags},
+    process::{Pid, WaitId, WaitIdOptions, waitid},
+};
 use wl_interface_map::{GetInitHandlesTy, wl_get_init_handles_name};
 
 use core::ffi::c_void;
 
 use crate::eprintln;
+use crate::helpers::{ErrorContext, rustix_error_to_lilium};
 use crate::libc::close;
 
+/// The handle can be read from
+pub const HANDLE_RIGHT_READ: u32 = 0x01;
+/// The handle can be written to
+pub const HANDLE_RIGHT_WRITE: u32 = 0x02;
+/// The position of the handle can be changed.
+///
+/// Reserved, since seeking isn't implemented yet.
+pub const HANDLE_RIGHT_SEEK: u32 = 0x04;
+/// The object referred to by the handle can be mapped into memory.
+///
+/// Reserved, since mapping a handle isn't implemented yet.
+pub const HANDLE_RIGHT_MAP: u32 = 0x08;
+/// The object referred to by the handle can be executed.
+///
+/// Reserved, since executing through a handle isn't implemented yet.
+pub const HANDLE_RIGHT_EXECUTE: u32 = 0x10;
+/// The handle can be sent to another process
+pub const HANDLE_RIGHT_TRANSFER: u32 = 0x20;
+/// The handle can be closed by the program
+pub const HANDLE_RIGHT_CLOSE: u32 = 0x40;
+
+pub const HANDLE_RIGHTS_ALL: u32 = 0x7F;
+
 #[repr(C, align(32))]
 #[derive(bytemuck::Zeroable)]
 pub struct Handle {
@@ -31,6 +60,10 @@ pub struct Handle {
     pub blob1: *mut c_void,
     pub blob2: *mut c_void,
     pub fd: c_long,
+    /// The `HANDLE_RIGHT_*` operations that are allowed on the handle.
+    ///
+    /// Rights can only be removed from a handle, never added.
+    pub rights: u32,
 }
 
 const NHANDLES: usize = 512;
@@ -84,10 +117,9 @@ impl Handle {
     }
     pub unsafe fn try_deref<'a>(ptr: HandlePtr<Handle>) -> Result<&'a mut Handle> {
         let ptr: *mut Handle = unsafe { core::mem::transmute(ptr) };
-        if HANDLE_ARRAY
-            .as_ptr_range()
-            .contains(&(ptr.cast_const().cast()))
-            && ptr.is_aligned()
+        let range = HANDLE_ARRAY.as_ptr_range();
+        if range.contains(&(ptr.cast_const().cast()))
+            && (ptr.addr() - range.start.addr()) % core::mem::size_of::<Handle>() == 0
         {
             let v = ptr.cast::<usize>();
             if unsafe { core::ptr::read(v) } != 0 {
@@ -122,6 +154,74 @@ impl Handle {
         }
     }
 
+    /// Duplicates the handle, duplicating the fds it owns.
+    ///
+    /// The result is not inserted into the handle table.
+    pub fn try_clone(&self) -> Result<Handle> {
+        let dup = |fd: BorrowedFd| {
+            rustix::io::fcntl_dupfd_cloexec(fd, 0)
+                .map(|fd| fd.into_raw_fd())
+                .map_err(|e| rustix_error_to_lilium(e, ErrorContext::General))
+        };
+
+        let fd = match self.borrow_fd() {
+            Some(fd) => dup(fd)? as c_long,
+            None => self.fd,
+        };
+
+        let blob2 = match self.borrow_fd2() {
+            Some(fd2) => match dup(fd2) {
+                Ok(fd2) => core::ptr::without_provenance_mut(-(fd2 as isize) as usize),
+                Err(e) => {
+                    if fd >= 0 {
+                        let _ = unsafe { close(fd as i32) };
+                    }
+                    return Err(e);
+                }
+            },
+            None => self.blob2,
+        };
+
+        Ok(Handle {
+            ty: self.ty,
+            blob1: self.blob1,
+            blob2,
+            fd,
+            rights: self.rights,
+        })
+    }
+
+    /// Closes the handle, releasing the resources it holds according to its type.
+    ///
+    /// Process handles reap the process if it has already exited. If it is a child that is still running,
+    ///  it is reaped by the `SIGCHLD` handler once it exits (see [`reap_orphans`]).
+    /// All other handles close the fds they own.
+    pub fn release(&mut self) {
+        if self.ty == HANDLE_TYPE_PROC as usize {
+            if let Some(fd) = self.borrow_fd() {
+                let reap = || {
+                    waitid(
+                        WaitId::PidFd(fd),
+                        WaitIdOptions::EXITED | WaitIdOptions::NOHANG,
+                    )
+                };
+
+                // `Ok(None)` means that the process is a child that is still running
+                if let Ok(None) = reap()
+                    && let Some(slot) = add_orphan(self.blob2.addr() as i32)
+                {
+                    // The child may have exited before it was recorded, in which case no `SIGCHLD` is left to reap it
+                    if !matches!(reap(), Ok(None)) {
+                        ORPHANS[slot].store(0, Ordering::Relaxed);
+                    }
+                }
+            }
+            self.close(false)
+        } else {
+            self.close(true)
+        }
+    }
+
     pub fn check_type(&self, ty: usize, mask: usize) -> Result<()> {
         if (self.ty & !mask) == ty {
             Ok(())
@@ -129,34 +229,204 @@ impl Handle {
             Err(lilium_sys::result::Error::InvalidHandle)
         }
     }
+
+    /// Checks that the handle has every right in `rights`, returning [`Error::Permission`][lilium_sys::result::Error::Permission] otherwise
+    pub fn check_rights(&self, rights: u32) -> Result<()> {
+        if (self.rights & rights) == rights {
+            Ok(())
+        } else {
+            Err(lilium_sys::result::Error::Permission)
+        }
+    }
 }
 
-#[unsafe(export_name = wl_get_init_handles_name!())]
-unsafe extern "C" fn get_init_handles(kslice: &mut KSlice<HandlePtr<sys::Handle>>) {
-    // TODO: take full init handle array
-    let stdin = Handle {
-        ty: HANDLE_TYPE_IO as usize,
-        blob1: core::ptr::null_mut(),
-        blob2: core::ptr::null_mut(),
-        fd: STDIN_FILENO as i64,
-    };
-    let stdout = Handle {
-        ty: HANDLE_TYPE_IO as usize,
-        blob1: core::ptr::null_mut(),
-        blob2: core::ptr::null_mut(),
-        fd: STDOUT_FILENO as i64,
+const MAX_ORPHANS: usize = 64;
+
+/// Children whose process handles were released while they were still running, which are reaped by [`reap_orphans`].
+///
+/// If there are more than [`MAX_ORPHANS`] such children at once, the rest remain zombies after they exit.
+static ORPHANS: [AtomicI32; MAX_ORPHANS] = [const { AtomicI32::new(0) }; MAX_ORPHANS];
+
+fn add_orphan(pid: i32) -> Option<usize> {
+    if pid <= 0 {
+        return None;
+    }
+
+    ORPHANS.iter().position(|slot| {
+        slot.compare_exchange(0, pid, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    })
+}
+
+/// Reaps the children in [`ORPHANS`] that have exited. Called from the `SIGCHLD` handler.
+pub(crate) fn reap_orphans() {
+    for slot in &ORPHANS {
+        let pid = slot.load(Ordering::Relaxed);
+        let Some(pid) = Pid::from_raw(pid) else {
+            continue;
+        };
+
+        match waitid(
+            WaitId::Pid(pid),
+            WaitIdOptions::EXITED | WaitIdOptions::NOHANG,
+        ) {
+            Ok(None) => {}
+            _ => slot.store(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Returns the pid of the process that the pidfd `fd` refers to, or `None` if `fd` is not a pidfd.
+///
+/// The pid is 0 if the process has already been reaped, or is outside of the pid namespace of this process.
+fn pidfd_pid(fd: BorrowedFd) -> Option<usize> {
+    let path = alloc::format!("/proc/self/fdinfo/{}", fd.as_raw_fd());
+    let info = rustix::fs::open(
+        path.as_str(),
+        OFlags::RDONLY | OFlags::CLOEXEC,
+        Mode::empty(),
+    )
+    .ok()?;
+
+    let mut buf = [0u8; 512];
+    let mut len = 0;
+    while len < buf.len() {
+        match rustix::io::read(&info, &mut buf[len..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => len += n,
+        }
+    }
+
+    // Only pidfds have a `Pid` field
+    let pid = buf[..len]
+        .split(|&b| b == b'\n')
+        .find_map(|line| line.strip_prefix(b"Pid:"))?;
+    let pid = core::str::from_utf8(pid)
+        .ok()?
+        .trim()
+        .parse::<isize>()
+        .ok()?;
+
+    Some(pid.max(0) as usize)
+}
+
+/// Creates a handle for the fd `fd`, which was received from outside of the process (such as an inherited fd).
+///
+/// The type and rights of the handle are determined from the host file, so they don't depend on anything the sender claims.
+/// Returns `None` if `fd` is not open.
+pub fn handle_for_fd(fd: i32) -> Option<Handle> {
+    let bfd = unsafe { BorrowedFd::borrow_raw(fd) };
+
+    let stat = rustix::fs::fstat(bfd).ok()?;
+    let flags = rustix::fs::fcntl_getfl(bfd).ok()?;
+
+    if let Some(pid) = pidfd_pid(bfd) {
+        return Some(Handle {
+            ty: HANDLE_TYPE_PROC as usize,
+            blob1: core::ptr::null_mut(),
+            blob2: core::ptr::without_provenance_mut(pid),
+            fd: fd as c_long,
+            rights: HANDLE_RIGHTS_ALL,
+        });
+    }
+
+    let file_ty = FileType::from_raw_mode(stat.st_mode);
+
+    let ty = match file_ty {
+        FileType::RegularFile | FileType::Directory => sys::HANDLE_SUBTYPE_IO_FILE,
+        FileType::Fifo => sys::HANDLE_SUBTYPE_IO_PIPE,
+        FileType::Socket => sys::HANDLE_SUBTYPE_IO_SOCKET,
+        FileType::CharacterDevice if rustix::termios::isatty(bfd) => {
+            sys::HANDLE_SUBTYPE_IO_TERMINAL
+        }
+        _ => sys::HANDLE_SUBTYPE_IO_DEV,
     };
-    let stderr = Handle {
-        ty: HANDLE_TYPE_IO as usize,
+
+    let mut rights = HANDLE_RIGHT_TRANSFER | HANDLE_RIGHT_CLOSE;
+
+    if !flags.contains(OFlags::PATH) {
+        let access = flags & OFlags::RWMODE;
+        let seekable = file_ty == FileType::RegularFile;
+
+        if access == OFlags::RDONLY || access == OFlags::RDWR {
+            rights |= HANDLE_RIGHT_READ;
+            if seekable {
+                rights |= HANDLE_RIGHT_SEEK | HANDLE_RIGHT_MAP | HANDLE_RIGHT_EXECUTE;
+            }
+        }
+        if access == OFlags::WRONLY || access == OFlags::RDWR {
+            rights |= HANDLE_RIGHT_WRITE;
+            if seekable {
+                rights |= HANDLE_RIGHT_SEEK;
+            }
+        }
+    }
+
+    Some(Handle {
+        ty: ty as usize,
         blob1: core::ptr::null_mut(),
         blob2: core::ptr::null_mut(),
-        fd: STDERR_FILENO as i64,
-    };
+        fd: fd as c_long,
+        rights,
+    })
+}
+
+#[unsafe(export_name = wl_get_init_handles_name!())]
+unsafe extern "C" fn get_init_handles(
+    kslice: &mut KSlice<HandlePtr<sys::Handle>>,
+    extra_fds: KCSlice<i32>,
+) {
+    let extra_fds = unsafe { extra_fds.as_slice() };
     let sl = unsafe { kslice.as_slice_mut() };
-    sl[0] = insert_handle(stdin).unwrap().cast();
-    sl[1] = insert_handle(stdout).unwrap().cast();
-    sl[2] = insert_handle(stderr).unwrap().cast();
-    kslice.len = 3;
+
+    // Set by `CreateProcess`, so that a handle doesn't regain rights that were removed in the parent
+    let parent_rights = crate::env::host_var("WL_INIT_HANDLE_RIGHTS");
+
+    // Init handle `n` is always at index `n`, so a position without a usable fd is left as a null handle
+    let mut len = 0;
+
+    for (n, fd) in [STDIN_FILENO, STDOUT_FILENO, STDERR_FILENO]
+        .iter()
+        .map(|fd| *fd as i32)
+        .chain(extra_fds.iter().copied())
+        .enumerate()
+    {
+        if n == sl.len() {
+            eprintln!("Too many init handles, ignoring file descriptor {fd} and later");
+            break;
+        }
+
+        sl[n] = HandlePtr::null();
+        len = n + 1;
+
+        // Each fd can only be owned by one handle
+        if n >= 3 && (fd < 3 || extra_fds[..(n - 3)].contains(&fd)) {
+            continue;
+        }
+
+        let Some(mut hdl) = handle_for_fd(fd) else {
+            if n >= 3 {
+                eprintln!("Init handle file descriptor {fd} is not open");
+            }
+            continue;
+        };
+
+        if n >= 3 {
+            // Extra fds are only passed to children that are given the handle explicitly
+            let _ = rustix::io::fcntl_setfd(
+                unsafe { BorrowedFd::borrow_raw(fd) },
+                rustix::io::FdFlags::CLOEXEC,
+            );
+        }
+
+        if let Some(rights) = parent_rights.and_then(|list| list.split(':').nth(n)) {
+            hdl.rights &= u32::from_str_radix(rights, 16).unwrap_or(0);
+        }
+
+        sl[n] = insert_handle(hdl).unwrap().cast();
+    }
+
+    kslice.len = len;
 }
 
 const _: GetInitHandlesTy = get_init_handles;
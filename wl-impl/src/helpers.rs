@@ -1,3 +1,4 @@
+use core::cell::Cell;
 use core::ops::Range;
 use core::{
     array::from_fn, cell::UnsafeCell, cmp::Ordering, ffi::c_void, iter::FusedIterator,
@@ -14,7 +15,12 @@ use lilium_sys::{
     },
 };
 use linux_errno::{
-    EBADF, EDQUOT, EFAULT, EINTR, EINVAL, EMFILE, ENFILE, ENODEV, ENOENT, ENOMEM, ENOSYS, EPERM,
+    E2BIG, EACCES, EADDRINUSE, EADDRNOTAVAIL, EAFNOSUPPORT, EAGAIN, EALREADY, EBADF, EBUSY,
+    ECANCELED, ECHILD, ECONNABORTED, ECONNREFUSED, ECONNRESET, EDQUOT, EEXIST, EFAULT, EFBIG,
+    EHOSTUNREACH, EINPROGRESS, EINTR, EINVAL, EISDIR, EMFILE, EMLINK, EMSGSIZE, ENAMETOOLONG,
+    ENETUNREACH, ENFILE, ENOBUFS, ENODEV, ENOENT, ENOMEM, ENOSPC, ENOSYS, ENOTCONN, ENOTDIR,
+    ENOTEMPTY, ENOTSUP, ENOTTY, ENXIO, EPERM, EPIPE, EPROTONOSUPPORT, EROFS, ESHUTDOWN, ESPIPE,
+    ESRCH, ETIMEDOUT, ETXTBSY, EXDEV,
 };
 use linux_raw_sys::general::{SIGKILL, SIGQUIT, sigaction, sigset_t};
 use linux_syscall::{SYS_getpid, SYS_kill, SYS_rt_sigaction, syscall};
@@ -643,23 +649,120 @@ pub const fn const_parse_u32(v: &str, radix: u32) -> u32 {
     val
 }
 
+pub const fn const_str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut i = 0;
+
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+
+    true
+}
+
 pub use wl_helpers::*;
 
 use crate::eprintln;
 use crate::libc::__memcpy_explicit;
 
+/// The winter-lily error returned for Linux errors that have no corresponding Lilium error (error 8:0, `ERROR_UNKNOWN_LINUX`)
+pub const ERROR_UNKNOWN_LINUX: lilium_sys::sys::result::SysResult = -0x800;
+
+/// The kind of operation that produced a Linux error.
+///
+/// Some errors mean different things depending on the operation, so they are mapped according to the context.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ErrorContext {
+    General,
+    /// Opening, creating, or resolving paths to files
+    File,
+    /// Creating or controlling processes
+    Process,
+    /// Creating or modifying memory mappings
+    Memory,
+    /// Sockets and other network operations
+    Net,
+}
+
+#[thread_local]
+static LAST_UNMAPPED_ERRNO: Cell<u16> = Cell::new(0);
+
+/// Returns the last Linux errno on the current thread that was not mapped to a Lilium error, or `0` if there hasn't been one
+pub fn last_unmapped_errno() -> u16 {
+    LAST_UNMAPPED_ERRNO.get()
+}
+
 pub fn linux_error_to_lilium(errno: linux_errno::Error) -> lilium_sys::result::Error {
-    match errno {
-        EINTR => lilium_sys::result::Error::Interrupted,
-        EPERM => lilium_sys::result::Error::Permission,
-        ENOMEM => lilium_sys::result::Error::InsufficientMemory,
-        EINVAL => lilium_sys::result::Error::InvalidOperation,
-        EFAULT => lilium_sys::result::Error::InvalidMemory,
-        ENOSYS => lilium_sys::result::Error::UnsupportedKernelFunction,
-        ENOENT => lilium_sys::result::Error::DoesNotExist,
-        ENODEV => lilium_sys::result::Error::DoesNotExist,
-        EMFILE | ENFILE | EDQUOT => lilium_sys::result::Error::ResourceLimitExhausted,
-        EBADF => lilium_sys::result::Error::InvalidHandle,
-        _ => lilium_sys::result::Error::from_code(-0x800).unwrap_err(), // Error 8:0 in winter-lily is the winter-lily unknown error
+    linux_error_to_lilium_in(errno, ErrorContext::General)
+}
+
+/// Maps a Linux error produced by an operation of the kind given by `ctx` to a Lilium error.
+///
+/// Errors that have no corresponding Lilium error are reported as [`ERROR_UNKNOWN_LINUX`], and are recorded for [`last_unmapped_errno`].
+pub fn linux_error_to_lilium_in(
+    errno: linux_errno::Error,
+    ctx: ErrorContext,
+) -> lilium_sys::result::Error {
+    use lilium_sys::result::Error;
+    match (errno, ctx) {
+        (EINTR | ECANCELED, _) => Error::Interrupted,
+        (EPERM | EACCES | EROFS, _) => Error::Permission,
+        (ENOMEM | ENOBUFS, _) => Error::InsufficientMemory,
+        (EFAULT, _) => Error::InvalidMemory,
+        (EINVAL, ErrorContext::Memory) => Error::InvalidMemory,
+        (EINVAL, _) => Error::InvalidOperation,
+        (ENOSYS, _) => Error::UnsupportedKernelFunction,
+        (ENOENT | ENODEV | ENXIO, _) => Error::DoesNotExist,
+        // A component of the path is not a directory, so the file doesn't exist
+        (ENOTDIR, ErrorContext::File | ErrorContext::Process) => Error::DoesNotExist,
+        (ENOTDIR, _) => Error::InvalidOperation,
+        (EISDIR, _) => Error::UnsupportedOperation,
+        (EEXIST, ErrorContext::Memory) => Error::InvalidMemory,
+        (EEXIST, _) => Error::AlreadyExists,
+        (ENOTEMPTY, _) => Error::InvalidState,
+        // `fork`/`clone` and `mlock` use `EAGAIN` for resource limits rather than blocking
+        (EAGAIN, ErrorContext::Process | ErrorContext::Memory) => Error::ResourceLimitExhausted,
+        (EAGAIN | EINPROGRESS | EALREADY, _) => Error::WouldBlock,
+        (EBUSY | ETXTBSY | EADDRINUSE, _) => Error::Busy,
+        (ETIMEDOUT, _) => Error::Timeout,
+        (ENOSPC | EFBIG, _) => Error::DeviceFull,
+        (EMFILE | ENFILE | EDQUOT | EMLINK | E2BIG | ENAMETOOLONG, _) => {
+            Error::ResourceLimitExhausted
+        }
+        (EMSGSIZE, _) => Error::InsufficientLength,
+        (EBADF, _) => Error::InvalidHandle,
+        (ESRCH | ECHILD, ErrorContext::Process) => Error::InvalidHandle,
+        (EPIPE | ENOTCONN | ECONNRESET | ECONNABORTED | ESHUTDOWN, _) => Error::InvalidState,
+        // Nothing is listening at (or can be reached at) the address
+        (
+            ECONNREFUSED | EADDRNOTAVAIL | ENETUNREACH | EHOSTUNREACH,
+            ErrorContext::Net | ErrorContext::General,
+        ) => Error::DoesNotExist,
+        (EXDEV | ENOTSUP | ENOTTY | ESPIPE | EAFNOSUPPORT | EPROTONOSUPPORT, _) => {
+            Error::UnsupportedOperation
+        }
+        (errno, _) => {
+            LAST_UNMAPPED_ERRNO.set(errno.get());
+            Error::from_code(ERROR_UNKNOWN_LINUX).unwrap_err()
+        }
+    }
+}
+
+/// Maps an error returned by `rustix` the same way as [`linux_error_to_lilium_in`]
+pub fn rustix_error_to_lilium(
+    err: rustix::io::Errno,
+    ctx: ErrorContext,
+) -> lilium_sys::result::Error {
+    match linux_errno::Error::new(err.raw_os_error() as u16) {
+        Some(errno) => linux_error_to_lilium_in(errno, ctx),
+        None => lilium_sys::result::Error::from_code(ERROR_UNKNOWN_LINUX).unwrap_err(),
     }
 }
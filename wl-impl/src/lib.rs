@@ -12,7 +12,7 @@
     once_cell_try,
     isolate_most_least_significant_one
 )]
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 extern crate alloc;
 
@@ -22,7 +22,9 @@ pub mod eh;
 pub mod env;
 pub mod handle_base;
 pub mod helpers;
+pub mod proc_info;
 pub mod syscall_helpers;
+pub mod user_ptr;
 
 pub mod catch_signals;
 
@@ -36,8 +38,17 @@ pub mod libc;
 
 pub mod rand;
 
+pub mod sandbox;
+
 pub mod thread;
 
+pub mod trace;
+
+pub mod vdso;
+
+#[cfg(target_arch = "x86_64")]
+pub mod rewrite;
+
 #[cfg(not(target_os = "linux"))]
 compile_error!("We only support linux for now");
 
@@ -59,11 +70,18 @@ unsafe extern "C" fn __wl_impl_setup_process(
     wl_load_size: usize,
     mode: FilterMode,
     rand_init: [u8; 16],
+    process_info: &ProcessInfo,
 ) {
     unsafe {
         __install_sa_handler();
     }
     let _ = GLOBAL_SEED.set(Mutex::new(Gen::seed(rand_init)));
+    unsafe {
+        proc_info::init_proc_info(process_info);
+    }
+    unsafe {
+        vdso::init_vdso_data();
+    }
     match mode {
         #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
         FilterMode::Prctl => {
@@ -87,6 +105,20 @@ unsafe extern "C" fn __wl_impl_setup_process(
     }
 }
 
+/// Runs `f` with syscall interception suspended on the current thread.
+///
+/// This allows native code outside of the winter-lily load region to perform linux syscalls directly.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn with_native_syscalls<R>(f: impl FnOnce() -> R) -> R {
+    let prev = SYS_INTERCEPT_STOP.swap(
+        libc::SYSCALL_DISPATCH_FILTER_ALLOW as i8,
+        core::sync::atomic::Ordering::SeqCst,
+    );
+    let ret = f();
+    SYS_INTERCEPT_STOP.store(prev, core::sync::atomic::Ordering::SeqCst);
+    ret
+}
+
 // Statically check that `wl_impl_setup_process` has the right type
 const _: SetupProcessTy = __wl_impl_setup_process;
 
@@ -118,4 +150,6 @@ pub mod consts {
         ((VERSION_MAJOR as u64) << 40) | ((VERSION_MINOR as u64) << 20) | (VERSION_PATCH as u64);
 }
 
+// Tests run with the standard library, which provides its own panic handler
+#[cfg(not(test))]
 mod panic;
@@ -5,6 +5,8 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 pub use linux_errno::*;
 
+pub use linux_raw_sys::ioctl::TIOCSCTTY;
+pub use linux_raw_sys::prctl::{PR_CAPBSET_DROP, PR_CAPBSET_READ};
 pub use linux_raw_sys::system::new_utsname;
 pub use linux_syscall::Result as Check;
 
@@ -39,16 +41,37 @@ def_syscall! {
     fn munmap(addr: *mut c_void, length: usize) -> ();
     fn mremap(old_addr: *mut c_void, old_len: usize, new_len: usize, flags: c_uint) -> *mut c_void;
     fn mprotect(addr: *mut c_void, len: usize, prot: c_uint) -> ();
+    fn pkey_alloc(flags: c_uint, access_rights: c_uint) -> c_int;
+    fn pkey_mprotect(addr: *mut c_void, len: usize, prot: c_uint, pkey: c_int) -> ();
     fn write(fd: i32, data: *const c_void, len: usize) -> usize;
     fn read(fd: i32, buf: *mut c_void, len: usize) -> usize;
+    fn pwrite64(fd: i32, data: *const c_void, len: usize, off: __kernel_loff_t) -> usize;
+    fn openat(dirfd: i32, path: *const c_char, flags: c_uint, mode: c_uint) -> c_int;
     fn exit(v: i32) -> !;
     fn exit_group(v: i32) -> !;
     fn getpid() -> __kernel_pid_t;
+    fn gettid() -> __kernel_pid_t;
     fn pidfd_open(pid: __kernel_pid_t, flags: c_uint) -> i32;
-
-    fn fork() -> i32;
+    fn getgroups(size: c_int, list: *mut __kernel_gid_t) -> c_int;
+    fn setresuid(ruid: __kernel_uid_t, euid: __kernel_uid_t, suid: __kernel_uid_t) -> ();
+    fn setresgid(rgid: __kernel_gid_t, egid: __kernel_gid_t, sgid: __kernel_gid_t) -> ();
+    fn prctl(option: c_int, arg2: c_ulong, arg3: c_ulong, arg4: c_ulong, arg5: c_ulong) -> c_int;
+
+    fn clone3(args: *mut clone_args, size: usize) -> i32;
+    fn rt_sigprocmask(how: c_int, set: *const u64, oldset: *mut u64, sigsetsize: usize) -> ();
+    fn dup3(oldfd: i32, newfd: i32, flags: c_uint) -> c_int;
+    fn setsid() -> __kernel_pid_t;
+    fn ioctl(fd: i32, request: c_uint, arg: c_ulong) -> c_int;
     fn execve(pathname: *const c_char, argv: *const *const c_char, envp: *const *const c_char) -> !;
 
+    fn bind(fd: i32, addr: *const c_void, addrlen: u32) -> ();
+    fn connect(fd: i32, addr: *const c_void, addrlen: u32) -> ();
+    fn accept4(fd: i32, addr: *mut c_void, addrlen: *mut u32, flags: c_uint) -> c_int;
+    fn sendto(fd: i32, buf: *const c_void, len: usize, flags: c_uint, addr: *const c_void, addrlen: u32) -> usize;
+    fn recvfrom(fd: i32, buf: *mut c_void, len: usize, flags: c_uint, addr: *mut c_void, addrlen: *mut u32) -> usize;
+    fn getsockname(fd: i32, addr: *mut c_void, addrlen: *mut u32) -> ();
+    fn getpeername(fd: i32, addr: *mut c_void, addrlen: *mut u32) -> ();
+
     fn uname(uts: *mut new_utsname) -> ();
 }
 
@@ -96,6 +119,51 @@ impl FromSysVal for ! {
     }
 }
 
+/// Calls `clone3` with `args`, which must give the child its own stack.
+///
+/// The child calls `entry(arg)` on that stack, and never returns from this function.
+/// Returns the pid of the child in the parent.
+///
+/// # Safety
+/// `args` must be valid for `clone3`, and `entry` must be sound to run in the child, given the flags in `args`.
+/// In particular, with `CLONE_VM`, the child shares memory with the parent.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn clone3_with_entry(
+    args: *mut clone_args,
+    size: usize,
+    entry: unsafe extern "C" fn(*mut c_void) -> !,
+    arg: *mut c_void,
+) -> Result<i32> {
+    let res: usize;
+    unsafe {
+        core::arch::asm! {
+            "syscall",
+            "test rax, rax",
+            "jnz 2f",
+            // Only the child gets here, running on its new stack, so nothing from the parent's frame can be used
+            "xor ebp, ebp",
+            "and rsp, -16",
+            "mov rdi, r12",
+            "call r13",
+            "ud2",
+            "2:",
+            inlateout("rax") linux_syscall::SYS_clone3 as usize => res,
+            in("rdi") args,
+            in("rsi") size,
+            in("r12") arg,
+            in("r13") entry,
+            lateout("rcx") _,
+            lateout("r11") _,
+            options(nostack),
+        }
+    }
+
+    match res as isize {
+        err @ -4095..0 => Err(Error::new((-err) as u16).unwrap()),
+        pid => Ok(pid as i32),
+    }
+}
+
 mod libc_defs;
 
 pub use libc_defs::*;
@@ -108,6 +176,7 @@ pub fn siginfo(
 
 unsafe extern "C" {
     pub safe fn __rtld_get_thread_ptr() -> *mut c_void;
+    pub fn __rtld_wl_load_subsystem_by_number(subsys: usize) -> bool;
 }
 
 pub const PR_SET_SYSCALL_USER_DISPATCH: usize = 59;
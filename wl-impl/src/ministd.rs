@@ -15,7 +15,10 @@ pub struct RandomState {
 
 impl RandomState {
     pub fn new() -> Self {
-        todo!()
+        Self {
+            k0: crate::rand::fast_rand(),
+            k1: crate::rand::fast_rand(),
+        }
     }
 }
 
@@ -0,0 +1,79 @@
+//! Information about the current program, provided by the loader in [`__wl_impl_setup_process`][crate::SetupProcessTy].
+
+use core::ffi::CStr;
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{ProcessInfo, helpers::OnceLock};
+
+struct ProcInfo {
+    exec_path: String,
+    args: Vec<String>,
+}
+
+static PROC_INFO: OnceLock<ProcInfo> = OnceLock::new();
+
+fn lossy_string(st: &CStr) -> String {
+    String::from_utf8_lossy_owned(st.to_bytes().to_vec())
+}
+
+/// Resolves the absolute path of the binary open on `execfd`
+fn resolve_execfd(execfd: i32) -> Option<String> {
+    if execfd < 0 {
+        return None;
+    }
+    let path = alloc::format!("/proc/self/fd/{execfd}");
+    let mut buf = [0u8; 4096];
+    let len = rustix::fs::readlinkat_raw(rustix::fs::CWD, &*path, &mut buf[..]).ok()?;
+
+    // A full buffer may have been truncated
+    if len == buf.len() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy_owned(buf[..len].to_vec()))
+}
+
+/// Copies the information in `info`.
+///
+/// # Safety
+/// `info` must be the valid [`ProcessInfo`] passed by the loader.
+pub(crate) unsafe fn init_proc_info(info: &ProcessInfo) {
+    let args = if info.argv.is_null() {
+        Vec::new()
+    } else {
+        unsafe { core::slice::from_raw_parts(info.argv, info.argc) }
+            .iter()
+            .map(|&arg| lossy_string(unsafe { CStr::from_ptr(arg) }))
+            .collect()
+    };
+
+    let exec_path = resolve_execfd(info.execfd)
+        .or_else(|| {
+            (!info.exec_path.is_null())
+                .then(|| lossy_string(unsafe { CStr::from_ptr(info.exec_path) }))
+        })
+        .unwrap_or_default();
+
+    let _ = PROC_INFO.set(ProcInfo { exec_path, args });
+}
+
+fn proc_info() -> &'static ProcInfo {
+    PROC_INFO
+        .get()
+        .expect("Must have called `__wl_impl_setup_process` before accessing process information")
+}
+
+/// The path of the program binary, which is absolute unless it could not be resolved.
+///
+/// This is empty if the loader could not determine the path.
+pub fn exec_path() -> &'static str {
+    &proc_info().exec_path
+}
+
+/// The arguments passed to the program, including `argv[0]` (which reflects `--argv0` if it was given to the loader).
+///
+/// Arguments that are not valid UTF-8 have invalid sequences replaced by `U+FFFD`.
+pub fn args() -> &'static [String] {
+    &proc_info().args
+}
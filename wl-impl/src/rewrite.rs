@@ -0,0 +1,293 @@
+//! Rewrites Lilium `syscall` instructions into direct calls into [`__handle_syscall`], avoiding the `SIGSYS` round-trip.
+//!
+//! This uses the same technique as zpoline: the 2-byte `syscall` instruction is replaced by the 2-byte `call rax`,
+//! and a trampoline is mapped at address 0 that consists of a `nop` sled covering every Lilium syscall number,
+//! followed by a jump to [`__wl_fast_syscall_entry`].
+//!
+//! Because the bytes `0F 05` can appear inside of other instructions, scanning code for them isn't reliable without a full disassembler.
+//! Instead, a syscall site is rewritten the first time it traps, since the trap reports the exact address of the instruction.
+//! Sites that can't be rewritten continue to use the signal path.
+//!
+//! A `call rax` with a number outside of the `nop` sled would jump into unmapped memory, rather than failing with `UNSUPPORTED_KERNEL_FUNCTION`,
+//! and the return address pushed by the `call` overwrites the red zone of the code making the syscall.
+//! So a site is only rewritten if it is part of a syscall stub that decodes, from its start, as a straight-line `mov` of a constant syscall number
+//! covered by the sled, then the `syscall`, then a `ret`. Such a stub can only be entered at its start, so `rax` always holds that constant at the `syscall`,
+//! and the stub doesn't keep anything in its red zone.
+
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering};
+
+use linux_raw_sys::general::{
+    AT_FDCWD, MAP_ANONYMOUS, MAP_FIXED_NOREPLACE, MAP_PRIVATE, O_CLOEXEC, O_RDWR,
+    PKEY_DISABLE_ACCESS, PROT_EXEC, PROT_READ,
+};
+use wl_interface_map::{EnableSyscallRewritingTy, wl_enable_syscall_rewriting_name};
+
+use crate::helpers::read_checked;
+use crate::libc::{close, mmap, mprotect, munmap, openat, pkey_alloc, pkey_mprotect, pwrite64};
+use crate::syscall_handler::__handle_syscall;
+
+/// The number of bytes covered by the `nop` sled, one for each possible Lilium syscall number
+const SLED_SIZE: usize = 64 << 12;
+
+const TRAMPOLINE_SIZE: usize = (SLED_SIZE + 16 + 4095) & !4095;
+
+const SYSCALL_INSN: [u8; 2] = [0x0F, 0x05];
+const CALL_RAX_INSN: [u8; 2] = [0xFF, 0xD0];
+const RET_INSN: u8 = 0xC3;
+const ENDBR64_INSN: [u8; 4] = [0xF3, 0x0F, 0x1E, 0xFA];
+
+/// Compilers align the start of functions to 16 bytes on x86_64
+const FUNCTION_ALIGN: usize = 16;
+
+/// The size of the red zone below the stack pointer that code may use without adjusting the stack pointer
+const RED_ZONE_SIZE: usize = 128;
+
+/// `mov eax, imm32`
+const MOV_EAX_IMM32: [u8; 1] = [0xB8];
+/// `mov rax, imm32`, which sign-extends the immediate
+const MOV_RAX_IMM32: [u8; 3] = [0x48, 0xC7, 0xC0];
+
+static REWRITE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// A file descriptor for `/proc/self/mem`, used to write to code without changing its protection
+static PROC_SELF_MEM: AtomicI32 = AtomicI32::new(-1);
+
+/// The state components enabled in `XCR0`, which are saved by [`__wl_fast_syscall_entry`]
+static XSAVE_MASK: AtomicU64 = AtomicU64::new(0);
+
+/// The size of the `xsave` area for the components in [`XSAVE_MASK`], reported by `cpuid[eax=0x0D,ecx=0].ebx`
+static XSAVE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(target_arch = "x86_64")]
+#[unsafe(naked)]
+unsafe extern "C" fn __wl_fast_syscall_entry() {
+    unsafe {
+        core::arch::naked_asm! {
+            // The `call rax` already wrote the return address, but keep the rest of the red zone intact
+            "lea rsp, [rsp - {red_zone}]",
+            // The syscall instruction only clobbers rax, rcx, and r11, so save everything else that `__handle_syscall` may clobber.
+            "pushfq",
+            "push rdi",
+            "push rsi",
+            "push rdx",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push rbp",
+            "mov rbp, rsp",
+            "sub rsp, qword ptr [{xsave_size}+rip]",
+            "and rsp, -64",
+            "mov r11, rax", // `xsave` takes its mask in edx:eax
+            // `xrstor` requires the reserved part of the `xsave` header to be zero, and `xsave` doesn't write it
+            "xor ecx, ecx",
+            "mov qword ptr [rsp + 520], rcx",
+            "mov qword ptr [rsp + 528], rcx",
+            "mov qword ptr [rsp + 536], rcx",
+            "mov qword ptr [rsp + 544], rcx",
+            "mov qword ptr [rsp + 552], rcx",
+            "mov qword ptr [rsp + 560], rcx",
+            "mov qword ptr [rsp + 568], rcx",
+            "mov eax, dword ptr [{xsave_mask}+rip]",
+            "mov edx, dword ptr [{xsave_mask}+rip+4]",
+            "xsave64 [rsp]",
+            "mov rax, r11",
+            "mov rdx, qword ptr [rbp + 32]",
+            "mov rcx, r10", // The syscall interface uses `r10` to pass param 4, but sysv64 uses rcx
+            "call {handle_syscall}",
+            "mov r11, rax",
+            "mov eax, dword ptr [{xsave_mask}+rip]",
+            "mov edx, dword ptr [{xsave_mask}+rip+4]",
+            "xrstor64 [rsp]",
+            "mov rax, r11",
+            "mov rsp, rbp",
+            "pop rbp",
+            "pop r10",
+            "pop r9",
+            "pop r8",
+            "pop rdx",
+            "pop rsi",
+            "pop rdi",
+            "popfq",
+            "lea rsp, [rsp + {red_zone}]",
+            "ret",
+            handle_syscall = sym __handle_syscall,
+            xsave_size = sym XSAVE_SIZE,
+            xsave_mask = sym XSAVE_MASK,
+            red_zone = const RED_ZONE_SIZE,
+        }
+    }
+}
+
+#[unsafe(export_name = wl_enable_syscall_rewriting_name!())]
+unsafe extern "C" fn enable_syscall_rewriting() -> bool {
+    if REWRITE_ENABLED.load(Ordering::Relaxed) {
+        return true;
+    }
+
+    if !init_xsave() {
+        return false;
+    }
+
+    let Ok(mem) = (unsafe { openat(AT_FDCWD, c"/proc/self/mem".as_ptr(), O_RDWR | O_CLOEXEC, 0) })
+    else {
+        return false;
+    };
+
+    // Mapping address 0 requires `vm.mmap_min_addr` to be 0, so this fails on most systems with the default configuration
+    let Ok(trampoline) = (unsafe {
+        mmap(
+            core::ptr::null_mut(),
+            TRAMPOLINE_SIZE,
+            PROT_READ | PROT_EXEC,
+            MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED_NOREPLACE,
+            -1,
+            0,
+        )
+    }) else {
+        let _ = unsafe { close(mem) };
+        return false;
+    };
+
+    if trampoline.addr() != 0 {
+        let _ = unsafe { munmap(trampoline, TRAMPOLINE_SIZE) };
+        let _ = unsafe { close(mem) };
+        return false;
+    }
+
+    // The trampoline is at address 0, which Rust code can't access directly, so write it through `/proc/self/mem` instead
+    let sled = [0x90u8; 4096]; // nop
+    let mut stub = [0u8; 14];
+    stub[..6].copy_from_slice(&[0xFF, 0x25, 0x00, 0x00, 0x00, 0x00]); // jmp qword ptr [rip]
+    stub[6..].copy_from_slice(&(__wl_fast_syscall_entry as usize as u64).to_ne_bytes());
+
+    let written = (0..SLED_SIZE)
+        .step_by(sled.len())
+        .map(|off| (&sled[..], off))
+        .chain(core::iter::once((&stub[..], SLED_SIZE)))
+        .all(|(buf, off)| {
+            unsafe { pwrite64(mem, buf.as_ptr().cast(), buf.len(), off as _) }
+                .is_ok_and(|n| n == buf.len())
+        });
+
+    if !written {
+        let _ = unsafe { munmap(trampoline, TRAMPOLINE_SIZE) };
+        let _ = unsafe { close(mem) };
+        return false;
+    }
+
+    protect_trampoline(trampoline);
+
+    PROC_SELF_MEM.store(mem, Ordering::Relaxed);
+    REWRITE_ENABLED.store(true, Ordering::Release);
+    true
+}
+
+const _: EnableSyscallRewritingTy = enable_syscall_rewriting;
+
+/// Finds the mask and size that [`__wl_fast_syscall_entry`] uses to save the extended register state with `xsave`.
+///
+/// Returns `false` if the host doesn't support `xsave`.
+fn init_xsave() -> bool {
+    let eax1 = unsafe { core::arch::x86_64::__cpuid(1) };
+
+    // OSXSAVE, which implies XSAVE
+    if eax1.ecx & (1 << 27) == 0 {
+        return false;
+    }
+
+    let (lo, hi): (u32, u32);
+    unsafe {
+        core::arch::asm!(
+            "xgetbv",
+            in("ecx") 0,
+            out("eax") lo,
+            out("edx") hi,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+
+    let size = unsafe { core::arch::x86_64::__cpuid_count(0x0D, 0) }.ebx as usize;
+
+    XSAVE_MASK.store(((hi as u64) << 32) | (lo as u64), Ordering::Relaxed);
+    XSAVE_SIZE.store(size, Ordering::Relaxed);
+    true
+}
+
+/// Makes the trampoline execute-only using a protection key, if the host supports them.
+///
+/// Otherwise, the trampoline stays readable, so null pointer reads by the program no longer fault.
+fn protect_trampoline(trampoline: *mut c_void) {
+    let Ok(pkey) = (unsafe { pkey_alloc(0, PKEY_DISABLE_ACCESS) }) else {
+        return;
+    };
+
+    if unsafe { pkey_mprotect(trampoline, TRAMPOLINE_SIZE, PROT_EXEC, pkey) }.is_err() {
+        // The kernel still picks an execute-only key itself for `PROT_EXEC` mappings where it can
+        let _ = unsafe { mprotect(trampoline, TRAMPOLINE_SIZE, PROT_EXEC) };
+    }
+}
+
+/// Checks that the syscall at `site` is part of a syscall stub that always uses a syscall number inside of the `nop` sled.
+///
+/// The stub must start at a function boundary, and decode from there as an optional `endbr64`, a `mov` of a constant into `rax`, the `syscall`, and a `ret`.
+fn is_syscall_stub(site: *mut c_void) -> bool {
+    match unsafe { read_checked(site.wrapping_add(2).cast::<u8>()) } {
+        Ok(RET_INSN) => {}
+        _ => return false,
+    }
+
+    [MOV_EAX_IMM32.len(), MOV_RAX_IMM32.len()]
+        .into_iter()
+        .any(|op_len| {
+            let mov = site.wrapping_sub(op_len + 4);
+            let Ok(insn) = (unsafe { read_checked(mov.cast::<[u8; 7]>()) }) else {
+                return false;
+            };
+            let (op, imm) = insn.split_at(op_len);
+
+            let is_mov = op == MOV_EAX_IMM32 || op == MOV_RAX_IMM32;
+            let is_start = mov.addr() % FUNCTION_ALIGN == 0
+                || (mov.wrapping_sub(4).addr() % FUNCTION_ALIGN == 0
+                    && unsafe { read_checked(mov.wrapping_sub(4).cast::<[u8; 4]>()) }
+                        .is_ok_and(|insn| insn == ENDBR64_INSN));
+
+            is_mov
+                && is_start
+                && (u32::from_le_bytes(imm[..4].try_into().unwrap()) as usize) < SLED_SIZE
+        })
+}
+
+/// Rewrites the `syscall` instruction that ends at `next_ip` into a `call rax`, if syscall rewriting is enabled.
+///
+/// Called after a syscall has been handled on the signal path.
+pub(crate) fn try_rewrite_site(next_ip: *mut c_void) {
+    if !REWRITE_ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+
+    let site = next_ip.wrapping_sub(2);
+
+    // The instruction must be replaced by a single store, so that other threads never see a partially rewritten instruction.
+    if site.addr() & 7 == 7 {
+        return;
+    }
+
+    match unsafe { read_checked(site.cast::<[u8; 2]>()) } {
+        Ok(insn) if insn == SYSCALL_INSN => {}
+        _ => return,
+    }
+
+    if !is_syscall_stub(site) {
+        return;
+    }
+
+    let _ = unsafe {
+        pwrite64(
+            PROC_SELF_MEM.load(Ordering::Relaxed),
+            CALL_RAX_INSN.as_ptr().cast(),
+            CALL_RAX_INSN.len(),
+            site.addr() as _,
+        )
+    };
+}
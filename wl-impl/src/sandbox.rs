@@ -0,0 +1,174 @@
+//! Landlock sandboxing.
+//!
+//! The loader applies the sandbox profile (if any) before the program starts, and reports the restrictions it applied through [`SetSandboxRestrictionsTy`].
+//! Programs can restrict themselves further at runtime, by stacking additional Landlock rulesets that deny more accesses.
+//!
+//! Landlock restrictions are per-thread, so restrictions applied at runtime only affect the calling thread and threads and processes it creates afterwards,
+//! and are tracked for each thread.
+//!
+//! Restricting file system access at runtime would also stop winter-lily itself from reading the files it needs, such as lazily loaded subsystems.
+//! So every subsystem is loaded first, and subsystems can keep access to their own files with [`register_runtime_access`].
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+
+use alloc::{string::String, vec::Vec};
+use lilium_sys::result::{Error, Result};
+use rustix::fd::AsRawFd;
+use rustix::fs::{FileType, Mode, OFlags};
+use rustix::io::Errno;
+use wl_helpers::landlock::{Ruleset, abi_version, fs_access_for_abi, net_access_for_abi};
+use wl_interface_map::{SetSandboxRestrictionsTy, wl_set_sandbox_restrictions_name};
+
+use crate::helpers::{ErrorContext, linux_error_to_lilium_in, rustix_error_to_lilium};
+
+pub use wl_helpers::landlock::{
+    ACCESS_FS_READ, LANDLOCK_ACCESS_FS_MAKE_SOCK, LANDLOCK_ACCESS_FS_REMOVE_FILE,
+};
+pub use wl_interface_map::SANDBOX_RESTRICT_NETWORK;
+
+/// Every file system modification is denied
+pub const SANDBOX_RESTRICT_FS_WRITE: u32 = 1 << 1;
+/// Every file system access is denied
+pub const SANDBOX_RESTRICT_FS: u32 = 1 << 2;
+
+/// The restrictions applied by the loader, which apply to every thread
+static PROCESS_RESTRICTIONS: AtomicU32 = AtomicU32::new(0);
+
+/// The restrictions applied at runtime by the current thread, or by the thread that created it
+#[thread_local]
+static THREAD_RESTRICTIONS: Cell<u32> = Cell::new(0);
+
+#[unsafe(export_name = wl_set_sandbox_restrictions_name!())]
+unsafe extern "C" fn set_sandbox_restrictions(restrictions: u32) {
+    PROCESS_RESTRICTIONS.fetch_or(restrictions, Ordering::Relaxed);
+}
+
+const _: SetSandboxRestrictionsTy = set_sandbox_restrictions;
+
+/// The `SANDBOX_RESTRICT_*` restrictions that apply to the calling thread, either applied by the loader or by [`restrict`]
+pub fn restrictions() -> u32 {
+    PROCESS_RESTRICTIONS.load(Ordering::Relaxed) | THREAD_RESTRICTIONS.get()
+}
+
+/// Records that the calling thread was created by a thread with the restrictions `restrictions` (as returned by [`restrictions`]).
+///
+/// Landlock rulesets are inherited by new threads, so this must be called at the start of every thread that winter-lily creates.
+pub fn inherit_restrictions(restrictions: u32) {
+    THREAD_RESTRICTIONS.set(restrictions & !PROCESS_RESTRICTIONS.load(Ordering::Relaxed));
+}
+
+/// Files that a subsystem needs to keep accessing after the program restricts its file system access at runtime.
+pub struct RuntimeAccess {
+    /// Returns the paths to allow access beneath. Called each time the file system access is restricted, so the paths may depend on the environment.
+    pub paths: fn() -> Vec<String>,
+    /// The Landlock accesses to allow, such as [`ACCESS_FS_READ`]
+    pub access: u64,
+}
+
+static RUNTIME_ACCESS: [AtomicPtr<RuntimeAccess>; 16] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; 16];
+
+/// Keeps the access in `access` when file system access is restricted at runtime.
+///
+/// Subsystems register this from their init function, which always runs before any runtime restriction is applied.
+pub fn register_runtime_access(access: &'static RuntimeAccess) {
+    for slot in &RUNTIME_ACCESS {
+        if slot
+            .compare_exchange(
+                core::ptr::null_mut(),
+                core::ptr::from_ref(access).cast_mut(),
+                Ordering::Release,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+        {
+            return;
+        }
+    }
+    panic!(
+        "Cannot register more than {} runtime sandbox accesses",
+        RUNTIME_ACCESS.len()
+    )
+}
+
+/// Adds a rule for each registered [`RuntimeAccess`] to `ruleset`
+fn allow_runtime_access(ruleset: &mut Ruleset) -> Result<()> {
+    // SAFETY: we only store NULL or a reference from `register_runtime_access`, which is `'static`
+    let registered = RUNTIME_ACCESS
+        .iter()
+        .filter_map(|r| unsafe { r.load(Ordering::Acquire).as_ref() });
+
+    for access in registered {
+        for path in (access.paths)() {
+            let fd = match rustix::fs::open(&*path, OFlags::PATH | OFlags::CLOEXEC, Mode::empty()) {
+                Ok(fd) => fd,
+                Err(Errno::NOENT) => continue,
+                Err(e) => return Err(rustix_error_to_lilium(e, ErrorContext::File)),
+            };
+
+            let is_dir = rustix::fs::fstat(&fd)
+                .is_ok_and(|st| FileType::from_raw_mode(st.st_mode) == FileType::Directory);
+
+            ruleset
+                .allow_path(fd.as_raw_fd(), access.access, is_dir)
+                .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::General))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Permanently applies the `SANDBOX_RESTRICT_*` restrictions in `restrictions` to the calling thread.
+///
+/// Returns `UnsupportedOperation` if the kernel can't enforce the restrictions.
+pub fn restrict(restrictions: u32) -> Result<()> {
+    let restrictions = if (restrictions & SANDBOX_RESTRICT_FS) != 0 {
+        restrictions | SANDBOX_RESTRICT_FS_WRITE
+    } else {
+        restrictions
+    };
+
+    let abi = abi_version().ok_or(Error::UnsupportedOperation)?;
+
+    let mut handled_fs = 0;
+    let mut handled_net = 0;
+
+    if (restrictions & SANDBOX_RESTRICT_FS) != 0 {
+        handled_fs |= fs_access_for_abi(abi);
+    } else if (restrictions & SANDBOX_RESTRICT_FS_WRITE) != 0 {
+        handled_fs |= fs_access_for_abi(abi) & !ACCESS_FS_READ;
+    }
+
+    if (restrictions & SANDBOX_RESTRICT_NETWORK) != 0 {
+        handled_net = net_access_for_abi(abi);
+        if handled_net == 0 {
+            return Err(Error::UnsupportedOperation);
+        }
+    }
+
+    if handled_fs == 0 && handled_net == 0 {
+        return Ok(());
+    }
+
+    if handled_fs != 0 {
+        // Lazy subsystems couldn't be loaded afterwards, and loading them registers their runtime access
+        crate::syscall_handler::load_all_subsystems();
+    }
+
+    // A ruleset only allows the accesses it handles where a rule allows them
+    let mut ruleset = Ruleset::new(handled_fs, handled_net)
+        .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::General))?;
+
+    if handled_fs != 0 {
+        allow_runtime_access(&mut ruleset)?;
+    }
+
+    ruleset
+        .restrict_self()
+        .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::General))?;
+
+    THREAD_RESTRICTIONS.update(|v| v | restrictions);
+
+    Ok(())
+}
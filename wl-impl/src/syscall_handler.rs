@@ -1,16 +1,31 @@
+use core::cell::Cell;
 use core::sync::atomic::{AtomicUsize, Ordering};
 use core::{arch::global_asm, ffi::c_void, sync::atomic::AtomicPtr};
 
-use crate::syscall_helpers::SysCallTyErased;
+use crate::helpers::const_str_eq;
+use crate::syscall_helpers::{SysCallTyErased, SyscallDesc};
 use core::convert::Infallible;
+use lilium_sys::sys::info::SysInfoRequest;
 use lilium_sys::sys::result::SysResult;
 use lilium_sys::uuid::Uuid;
+use wl_interface_map::{RequestSubsysNumberTy, wl_request_subsys_number_name};
 
 pub struct SubsysInfo {
     pub name: &'static str,
     pub uuid: Uuid,
+    /// The version of the subsystem, computed by [`subsys_version`]
     pub subsys_version: u64,
-    pub max_sysno: u16,
+    /// The names of the syscalls implemented by the subsystem, indexed by syscall number within the subsystem
+    pub syscall_names: &'static [(usize, SyscallDesc)],
+}
+
+impl SubsysInfo {
+    pub fn syscall_desc(&self, sysno: usize) -> Option<&'static SyscallDesc> {
+        self.syscall_names
+            .iter()
+            .find(|(num, _)| *num == sysno)
+            .map(|(_, desc)| desc)
+    }
 }
 
 static SYSCALL_SUBSYS_ARRAY: [AtomicPtr<[Option<SysCallTyErased>; 4096]>; 64] =
@@ -18,6 +33,12 @@ static SYSCALL_SUBSYS_ARRAY: [AtomicPtr<[Option<SysCallTyErased>; 4096]>; 64] =
 
 static NEXT_DYN_SUBSYS: AtomicUsize = AtomicUsize::new(8);
 
+/// The subsystem number requested for the next subsystem initialized on this thread.
+///
+/// This is per-thread, since subsystems can be loaded lazily by several threads at once.
+#[thread_local]
+static REQUESTED_SUBSYS: Cell<usize> = Cell::new(!0);
+
 static SUBSYS_REGISTRY: [AtomicPtr<SubsysInfo>; 64] =
     [const { AtomicPtr::new(core::ptr::null_mut()) }; 64];
 
@@ -26,23 +47,104 @@ pub unsafe fn register_subsys(
     arr: &'static [Option<SysCallTyErased>; 4096],
     info: &'static SubsysInfo,
 ) {
-    let subsys = if subsys == !0 {
-        let val = NEXT_DYN_SUBSYS.fetch_add(1, Ordering::Relaxed);
-        if val > SYSCALL_SUBSYS_ARRAY.len() {
+    let requested = REQUESTED_SUBSYS.replace(!0);
+    let subsys = if subsys != !0 {
+        // The manifest (or lazy loading) expects the subsystem at `requested`, so a mismatch means its syscalls would never be found there
+        if requested != !0 && requested != subsys {
             panic!(
-                "Cannot register more than {} subsystems",
-                SYSCALL_SUBSYS_ARRAY.len()
+                "Subsystem {} has the fixed number {subsys}, but was loaded as subsystem {requested}",
+                info.name
             )
         }
-        val
-    } else {
         subsys
+    } else if requested != !0 {
+        requested
+    } else {
+        loop {
+            let val = NEXT_DYN_SUBSYS.fetch_add(1, Ordering::Relaxed);
+            if val >= SYSCALL_SUBSYS_ARRAY.len() {
+                panic!(
+                    "Cannot register more than {} subsystems",
+                    SYSCALL_SUBSYS_ARRAY.len()
+                )
+            }
+            if SUBSYS_REGISTRY[val].load(Ordering::Relaxed).is_null() {
+                break val;
+            }
+        }
     };
+    if subsys >= SYSCALL_SUBSYS_ARRAY.len() {
+        panic!("Subsystem number {subsys} is out of range")
+    }
+    if !SUBSYS_REGISTRY[subsys].load(Ordering::Relaxed).is_null() {
+        panic!("Subsystem number {subsys} is already in use")
+    }
     core::sync::atomic::fence(Ordering::Release);
     SYSCALL_SUBSYS_ARRAY[subsys].store(core::ptr::from_ref(arr).cast_mut(), Ordering::Relaxed);
     SUBSYS_REGISTRY[subsys].store(core::ptr::from_ref(info).cast_mut(), Ordering::Relaxed);
 }
 
+#[unsafe(export_name = wl_request_subsys_number_name!())]
+unsafe extern "C" fn request_subsys_number(subsys: usize) {
+    REQUESTED_SUBSYS.set(subsys);
+}
+
+const _: RequestSubsysNumberTy = request_subsys_number;
+
+pub fn subsys_info(subsys: usize) -> Option<&'static SubsysInfo> {
+    // SAFETY: we only store NULL or a reference from `register_subsys`, which is `'static`
+    SUBSYS_REGISTRY
+        .get(subsys)
+        .and_then(|r| unsafe { r.load(Ordering::Acquire).as_ref() })
+}
+
+/// Returns the syscall table registered for `subsys`, if any
+pub fn syscall_table(subsys: usize) -> Option<&'static [Option<SysCallTyErased>; 4096]> {
+    // SAFETY: we only store NULL or a reference from `register_subsys`, which is `'static`
+    SYSCALL_SUBSYS_ARRAY
+        .get(subsys)
+        .and_then(|r| unsafe { r.load(Ordering::Acquire).as_ref() })
+}
+
+/// Returns the highest syscall number implemented by `subsys`, or `0` if it doesn't implement any syscalls
+pub fn max_sysno(subsys: usize) -> u16 {
+    syscall_table(subsys)
+        .and_then(|arr| arr.iter().rposition(Option::is_some))
+        .unwrap_or(0) as u16
+}
+
+/// Fills `bitmap` such that bit `n % 64` of element `n / 64` is set if syscall `n` is implemented by `subsys`.
+///
+/// Elements of `bitmap` that cover syscall numbers above `max_sysno(subsys)` are set to 0.
+pub fn fill_syscall_bitmap(subsys: usize, bitmap: &mut [u64]) {
+    bitmap.fill(0);
+    let Some(arr) = syscall_table(subsys) else {
+        return;
+    };
+
+    for (n, _) in arr.iter().enumerate().filter(|(_, f)| f.is_some()) {
+        if let Some(word) = bitmap.get_mut(n / 64) {
+            *word |= 1 << (n % 64);
+        }
+    }
+}
+
+/// Computes a subsystem version from its components, in the same format as [`crate::consts::KAPI_VERSION`]
+pub const fn subsys_version(major: u32, minor: u32, patch: u32) -> u64 {
+    ((major as u64) << 40) | ((minor as u64) << 20) | (patch as u64)
+}
+
+/// Finds the implementation of the syscall `sysno`, loading the subsystem first if necessary
+pub(crate) fn find_syscall(sysno: usize) -> Option<SysCallTyErased> {
+    let subsys = SYSCALL_SUBSYS_ARRAY.get(sysno >> 12)?;
+    let mut arr = subsys.load(Ordering::Acquire);
+    if arr.is_null() && load_lazy_subsystem(sysno >> 12) {
+        arr = subsys.load(Ordering::Acquire);
+    }
+    // SAFETY: we only store NULL or a reference from `register_subsys`, which is `'static`
+    unsafe { arr.as_ref() }.and_then(|arr| arr[sysno & 0xFFF])
+}
+
 pub fn all_subsystems() -> impl Iterator<Item = (u16, &'static SubsysInfo)> {
     SUBSYS_REGISTRY
         .iter()
@@ -53,24 +155,92 @@ pub fn all_subsystems() -> impl Iterator<Item = (u16, &'static SubsysInfo)> {
         })
 }
 
+/// A handler for a [`SysInfoRequest`] type that isn't answered by the base subsystem itself.
+///
+/// This allows extension subsystems to define their own `SysInfoRequest` options.
+pub struct SysInfoHandler {
+    pub ty: Uuid,
+    pub process: fn(&mut SysInfoRequest) -> lilium_sys::result::Result<()>,
+}
+
+static SYSINFO_HANDLERS: [AtomicPtr<SysInfoHandler>; 16] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; 16];
+
+pub fn register_sysinfo_handler(handler: &'static SysInfoHandler) {
+    for slot in &SYSINFO_HANDLERS {
+        if slot
+            .compare_exchange(
+                core::ptr::null_mut(),
+                core::ptr::from_ref(handler).cast_mut(),
+                Ordering::Release,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+        {
+            return;
+        }
+    }
+    panic!(
+        "Cannot register more than {} SysInfoRequest handlers",
+        SYSINFO_HANDLERS.len()
+    )
+}
+
+pub fn find_sysinfo_handler(ty: Uuid) -> Option<&'static SysInfoHandler> {
+    SYSINFO_HANDLERS
+        .iter()
+        // SAFETY: we only store NULL or a reference from `register_sysinfo_handler`, which is `'static`
+        .filter_map(|r| unsafe { r.load(Ordering::Acquire).as_ref() })
+        .find(|handler| handler.ty == ty)
+}
+
 use core::arch::naked_asm;
 
 use lilium_sys::sys::result::errors::UNSUPPORTED_KERNEL_FUNCTION;
 
+/// Asks the loader to load the subsystem assigned to `subsys` on first use.
+///
+/// Returns `true` if the subsystem is now registered, and the syscall should be retried.
+extern "sysv64" fn load_lazy_subsystem(subsys: usize) -> bool {
+    let loaded = unsafe { crate::libc::__rtld_wl_load_subsystem_by_number(subsys) };
+
+    loaded
+        && !SYSCALL_SUBSYS_ARRAY[subsys]
+            .load(Ordering::Acquire)
+            .is_null()
+}
+
+/// Loads every subsystem in the manifest that isn't loaded yet
+pub(crate) fn load_all_subsystems() {
+    for (subsys, arr) in SYSCALL_SUBSYS_ARRAY.iter().enumerate() {
+        if arr.load(Ordering::Acquire).is_null() {
+            load_lazy_subsystem(subsys);
+        }
+    }
+}
+
 #[cfg(target_arch = "x86_64")]
 #[unsafe(naked)]
 pub(crate) unsafe extern "sysv64" fn __handle_syscall(_: Infallible) -> SysResult {
     unsafe {
         naked_asm! {
-            "lea r11, [{SUBSYS_ARR}+rip]",
+            "cmp dword ptr [{TRACE_FD}+rip], 0",
+            "jl 5f",
+            "push rax", // Stack is now 16-byte aligned, and the syscall number is the 7th parameter
+            "call {trace_syscall}",
+            "add rsp, 8",
+            "ret",
+            "5:",
             "mov r10, rax",
             "shr r10, 12",
             "and rax, 0xFFF",
             "cmp r10, 64",
             "jae 2f",
+            "4:",
+            "lea r11, [{SUBSYS_ARR}+rip]",
             "mov r11, qword ptr [8*r10+r11]",
             "test r11, r11",
-            "jz 2f",
+            "jz 3f",
             "mov r11, qword ptr [r11+8*rax]",
             "test r11, r11",
             "jz 2f",
@@ -78,8 +248,37 @@ pub(crate) unsafe extern "sysv64" fn __handle_syscall(_: Infallible) -> SysResul
             "2:",
             "mov rax, {UNSUPPORTED_KERNEL_FUNCTION}",
             "ret",
+            "3:",
+            // The subsystem isn't loaded yet, so load it if it's lazily loaded then try again
+            "push rdi",
+            "push rsi",
+            "push rdx",
+            "push rcx",
+            "push r8",
+            "push r9",
+            "push rax",
+            "push r10",
+            "sub rsp, 8", // Stack is now 16-byte aligned
+            "mov rdi, r10",
+            "call {load_lazy_subsystem}",
+            "add rsp, 8",
+            "pop r10",
+            "mov r11, rax",
+            "pop rax",
+            "pop r9",
+            "pop r8",
+            "pop rcx",
+            "pop rdx",
+            "pop rsi",
+            "pop rdi",
+            "test r11b, r11b",
+            "jnz 4b",
+            "jmp 2b",
             UNSUPPORTED_KERNEL_FUNCTION = const UNSUPPORTED_KERNEL_FUNCTION,
-            SUBSYS_ARR = sym SYSCALL_SUBSYS_ARRAY
+            SUBSYS_ARR = sym SYSCALL_SUBSYS_ARRAY,
+            load_lazy_subsystem = sym load_lazy_subsystem,
+            TRACE_FD = sym crate::trace::TRACE_FD,
+            trace_syscall = sym crate::trace::trace_syscall,
         }
     }
 }
@@ -101,15 +300,179 @@ macro_rules! erase {
     }};
 }
 
+#[doc(hidden)]
+pub use lilium_sys::uuid::parse_uuid as __parse_uuid;
+
+/// Returns the name of the syscall that a syscall number constant named `SYS_<Name>` refers to
+#[doc(hidden)]
+pub const fn __sysno_syscall_name(sysno_name: &'static str) -> &'static str {
+    match sysno_name.as_bytes() {
+        [b'S', b'Y', b'S', b'_', name @ ..] => match core::str::from_utf8(name) {
+            Ok(name) => name,
+            Err(_) => unreachable!(),
+        },
+        _ => panic!("Syscall number constants must be named `SYS_<Name>`"),
+    }
+}
+
+/// Checks the syscalls of a subsystem defined by [`def_subsystem!`].
+///
+/// Panics (and thus fails the build) if two syscalls share a number,
+/// or if a syscall in `declared` is neither implemented nor stubbed. In the latter case, the panic message is the name of the missing syscall.
+#[doc(hidden)]
+pub const fn __check_subsys_syscalls(sysnos: &[usize], names: &[&str], declared: &[&str]) {
+    let mut i = 0;
+    while i < sysnos.len() {
+        let mut j = i + 1;
+        while j < sysnos.len() {
+            if sysnos[i] == sysnos[j] {
+                panic!("Syscall number is used by more than one syscall")
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+
+    let mut i = 0;
+    'declared: while i < declared.len() {
+        let mut j = 0;
+        while j < names.len() {
+            if const_str_eq(declared[i], names[j]) {
+                i += 1;
+                continue 'declared;
+            }
+            j += 1;
+        }
+        panic!("{}", declared[i])
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __subsys_number {
+    (dynamic) => {
+        !0
+    };
+    ($num:expr) => {
+        $num
+    };
+}
+
+/// Defines the syscall table, [`SubsysInfo`], and init function of a subsystem.
+///
+/// ```rust,ignore
+/// def_subsystem! {
+///     name: "io",
+///     uuid: "144e7137-9e85-5b9e-8d4a-8c700fb9d6bd",
+///     number: 2, // or `dynamic`
+///     version: (1, 0, 0), // major, minor, patch
+///     declared: sysno::DECLARED_SYSCALLS, // Optional, see below
+///     syscalls: [
+///         SYS_IOWrite => basic::IOWrite,
+///     ],
+///     stubs: [SYS_IOSeek], // Optional
+///     init: init_extra, // Optional, called after the subsystem is registered
+/// }
+/// ```
+///
+/// The version is reported to programs through `GetSystemInfo`, and should be updated whenever the interface of the subsystem changes.
+///
+/// Each syscall is given by the constant for its syscall number, which must be named `SYS_<Name>`,
+///  and the path to its [`export_syscall!`] definition, which must be named `<Name>`. This is checked at compile time.
+///
+/// Stubs are syscalls that are declared by the subsystem but not implemented by winter-lily.
+/// They are not added to the syscall table (so they return `UNSUPPORTED_KERNEL_FUNCTION`, and are reported as unimplemented),
+///  but their names are available for diagnostics such as syscall tracing.
+///
+/// If `declared` is given, it is a list of syscall names (such as the `DECLARED_SYSCALLS` generated from the `.knum` files of the subsystem),
+///  and compilation fails if any of them is neither in `syscalls` nor in `stubs`.
 #[macro_export]
-macro_rules! def_subsystem{
-    ($syscalls:ident) => {
+macro_rules! def_subsystem {
+    (
+        name: $name:expr,
+        uuid: $uuid:expr,
+        number: $num:tt,
+        version: ($major:expr, $minor:expr, $patch:expr $(,)?),
+        $(declared: $declared:expr,)?
+        syscalls: [$($sysno:ident => $($fn:ident)::+),* $(,)?],
+        $(stubs: [$($stub:ident),* $(,)?],)?
+        $(init: $init:path,)?
+    ) => {
+        static SYSCALLS: [
+            $crate::syscall_helpers::_core::option::Option<$crate::syscall_helpers::SysCallTyErased>;
+            4096
+        ] = $crate::helpers::insert_elems(
+            [None; 4096],
+            [$((($sysno as usize) & 0xFFF, $crate::erase!($($fn)::+))),*],
+        );
+
+        static INFO: $crate::syscall_handler::SubsysInfo = $crate::syscall_handler::SubsysInfo {
+            name: $name,
+            uuid: $crate::syscall_handler::__parse_uuid($uuid),
+            subsys_version: $crate::syscall_handler::subsys_version($major, $minor, $patch),
+            syscall_names: &[
+                $((($sysno as usize) & 0xFFF, $($fn)::+::DESC),)*
+                $($((
+                    ($stub as usize) & 0xFFF,
+                    $crate::syscall_helpers::SyscallDesc {
+                        name: $crate::syscall_handler::__sysno_syscall_name(
+                            $crate::syscall_helpers::_core::stringify!($stub),
+                        ),
+                        params: &[],
+                    },
+                ),)*)?
+            ],
+        };
+
         const _: () = {
-            #[unsafe(export_name = $crate::wl_init_subsystem_name!())]
-            pub extern "C" fn __init_subsystem() {
-                $crate::syscall_handler::
-            }
+            $($crate::syscall_helpers::_core::assert!(
+                $crate::helpers::const_str_eq(
+                    $crate::syscall_handler::__sysno_syscall_name(
+                        $crate::syscall_helpers::_core::stringify!($sysno),
+                    ),
+                    $($fn)::+::DESC.name,
+                ),
+                $crate::syscall_helpers::_core::concat!(
+                    "`",
+                    $crate::syscall_helpers::_core::stringify!($sysno),
+                    "` is not the syscall number of `",
+                    $crate::syscall_helpers::_core::stringify!($($fn)::+),
+                    "`",
+                ),
+            );)*
+
+            // Fails with the name of the first declared syscall that isn't implemented or stubbed
+            $crate::syscall_handler::__check_subsys_syscalls(
+                &[
+                    $(($sysno as usize) & 0xFFF,)*
+                    $($(($stub as usize) & 0xFFF,)*)?
+                ],
+                &[
+                    $($($fn)::+::DESC.name,)*
+                    $($($crate::syscall_handler::__sysno_syscall_name(
+                        $crate::syscall_helpers::_core::stringify!($stub),
+                    ),)*)?
+                ],
+                {
+                    let __declared: &[&str] = &[];
+                    $(let __declared: &[&str] = $declared;)?
+                    __declared
+                },
+            );
         };
 
+        #[unsafe(export_name = $crate::wl_init_subsystem_name!())]
+        unsafe extern "C" fn __init_subsystem() {
+            unsafe {
+                $crate::syscall_handler::register_subsys(
+                    $crate::__subsys_number!($num),
+                    &SYSCALLS,
+                    &INFO,
+                );
+            }
+            $($init();)?
+        }
+
+        const _: $crate::InitSubsystemTy = __init_subsystem;
     };
 }
@@ -10,6 +10,8 @@ use lilium_sys::{
     uuid::Uuid,
 };
 
+use crate::user_ptr::{UserPtr, UserPtrMut, UserSlice, UserSliceMut, UserStr};
+
 #[cfg(target_arch = "x86_64")]
 pub type SysCallTyErased = unsafe extern "sysv64" fn(core::convert::Infallible);
 
@@ -25,7 +27,8 @@ macro_rules! export_syscall {
     //         $crate::syscall_helpers::SyscallRet::into_sys(ret_val)
     //     }
     // };
-    (unsafe extern fn $name:ident ($(|$ctx:pat_param|)? $($params:ident : $param_ty:ty),* $(,)?) -> $ret_ty:ty $body:block) => {
+    ($(#[$meta:meta])* unsafe extern fn $name:ident ($(|$ctx:pat_param|)? $($params:ident : $param_ty:ty),* $(,)?) -> $ret_ty:ty $body:block) => {
+        $(#[$meta])*
         #[unsafe(no_mangle)]
         #[allow(unreachable_code)]
         pub unsafe extern "sysv64" fn $name ($($params: $param_ty),*) -> <$ret_ty as $crate::syscall_helpers::SyscallRet>::Sys {
@@ -40,9 +43,64 @@ macro_rules! export_syscall {
                 $(__test_param::<$param_ty>();)*
             }
         };
+
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        pub mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            pub const DESC: $crate::syscall_helpers::SyscallDesc = $crate::syscall_helpers::SyscallDesc {
+                name: $crate::syscall_helpers::_core::stringify!($name),
+                params: &[$($crate::syscall_helpers::ParamDesc::of::<$param_ty>($crate::syscall_helpers::_core::stringify!($params))),*],
+            };
+        }
     };
 }
 
+/// Describes a syscall defined by [`export_syscall!`], for diagnostics such as syscall tracing.
+///
+/// The description of a syscall `Foo` is available as `Foo::DESC`.
+#[derive(Copy, Clone, Debug)]
+pub struct SyscallDesc {
+    pub name: &'static str,
+    pub params: &'static [ParamDesc],
+}
+
+/// How the value of a syscall parameter is displayed in diagnostics
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParamKind {
+    /// An integer or opaque value
+    Int,
+    /// A pointer into the program's memory
+    Pointer,
+    /// A handle
+    Handle,
+    /// A string, passed as a pointer and a length
+    Str,
+    /// A slice, passed as a pointer and a length
+    Slice,
+}
+
+/// Describes a parameter of a syscall defined by [`export_syscall!`]
+#[derive(Copy, Clone, Debug)]
+pub struct ParamDesc {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    /// The number of registers the parameter is passed in
+    pub words: usize,
+}
+
+impl ParamDesc {
+    pub const fn of<T: SyscallParam>(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: T::KIND,
+            words: size_of::<T>().div_ceil(size_of::<usize>()),
+        }
+    }
+}
+
 pub trait SyscallRet {
     type Sys: Copy + Clone + SysretTy;
 
@@ -68,6 +126,14 @@ impl SyscallRet for Result<()> {
     }
 }
 
+impl SyscallRet for SysResult {
+    type Sys = SysResult;
+
+    fn into_sys(self) -> Self::Sys {
+        self
+    }
+}
+
 impl SyscallRet for Result<usize> {
     type Sys = SysResult;
 
@@ -93,7 +159,10 @@ unsafe impl SysretTy for i64 {}
 unsafe impl SysretTy for () {}
 unsafe impl SysretTy for ! {}
 
-pub unsafe trait SyscallParam {}
+pub unsafe trait SyscallParam {
+    /// How the parameter is displayed by syscall tracing
+    const KIND: ParamKind = ParamKind::Int;
+}
 
 unsafe impl SyscallParam for usize {}
 unsafe impl SyscallParam for isize {}
@@ -104,23 +173,53 @@ unsafe impl SyscallParam for u64 {}
 #[cfg(not(target_pointer_width = "32"))]
 unsafe impl SyscallParam for i64 {}
 
-unsafe impl<H> SyscallParam for HandlePtr<H> {}
-unsafe impl<T> SyscallParam for *mut T {}
-unsafe impl<T> SyscallParam for *const T {}
+unsafe impl<H> SyscallParam for HandlePtr<H> {
+    const KIND: ParamKind = ParamKind::Handle;
+}
+unsafe impl<T> SyscallParam for *mut T {
+    const KIND: ParamKind = ParamKind::Pointer;
+}
+unsafe impl<T> SyscallParam for *const T {
+    const KIND: ParamKind = ParamKind::Pointer;
+}
 
 unsafe impl SyscallParam for Uuid {}
-unsafe impl<T> SyscallParam for KCSlice<T> {}
-unsafe impl<T> SyscallParam for KSlice<T> {}
-unsafe impl SyscallParam for KStrCPtr {}
+unsafe impl<T> SyscallParam for KCSlice<T> {
+    const KIND: ParamKind = ParamKind::Slice;
+}
+unsafe impl<T> SyscallParam for KSlice<T> {
+    const KIND: ParamKind = ParamKind::Slice;
+}
+unsafe impl SyscallParam for KStrCPtr {
+    const KIND: ParamKind = ParamKind::Str;
+}
+
+unsafe impl<T: SyscallParam> SyscallParam for MaybeValid<T> {
+    const KIND: ParamKind = T::KIND;
+}
 
-unsafe impl<T: SyscallParam> SyscallParam for MaybeValid<T> {}
+unsafe impl<T> SyscallParam for UserPtr<T> {
+    const KIND: ParamKind = ParamKind::Pointer;
+}
+unsafe impl<T> SyscallParam for UserPtrMut<T> {
+    const KIND: ParamKind = ParamKind::Pointer;
+}
+unsafe impl SyscallParam for UserStr {
+    const KIND: ParamKind = ParamKind::Str;
+}
+unsafe impl<T> SyscallParam for UserSlice<T> {
+    const KIND: ParamKind = ParamKind::Slice;
+}
+unsafe impl<T> SyscallParam for UserSliceMut<T> {
+    const KIND: ParamKind = ParamKind::Slice;
+}
 
 macro_rules! def_fn_tys {
     ($($ty:ident),*) => {
-        unsafe impl<__R: SysretTy, $($ty: SyscallParam),*> SyscallParam for extern "system" fn($($ty),*)->__R{}
-        unsafe impl<__R: SysretTy, $($ty: SyscallParam),*> SyscallParam for extern "system-unwind" fn($($ty),*)->__R{}
-        unsafe impl<__R: SysretTy, $($ty: SyscallParam),*> SyscallParam for Option<extern "system" fn($($ty),*)->__R>{}
-        unsafe impl<__R: SysretTy, $($ty: SyscallParam),*> SyscallParam for Option<extern "system-unwind" fn($($ty),*)->__R>{}
+        unsafe impl<__R: SysretTy, $($ty: SyscallParam),*> SyscallParam for extern "system" fn($($ty),*)->__R{ const KIND: ParamKind = ParamKind::Pointer; }
+        unsafe impl<__R: SysretTy, $($ty: SyscallParam),*> SyscallParam for extern "system-unwind" fn($($ty),*)->__R{ const KIND: ParamKind = ParamKind::Pointer; }
+        unsafe impl<__R: SysretTy, $($ty: SyscallParam),*> SyscallParam for Option<extern "system" fn($($ty),*)->__R>{ const KIND: ParamKind = ParamKind::Pointer; }
+        unsafe impl<__R: SysretTy, $($ty: SyscallParam),*> SyscallParam for Option<extern "system-unwind" fn($($ty),*)->__R>{ const KIND: ParamKind = ParamKind::Pointer; }
     };
 }
 
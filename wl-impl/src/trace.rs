@@ -0,0 +1,221 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use lilium_sys::result::Error;
+use lilium_sys::sys::handle::HandlePtr;
+use lilium_sys::sys::result::SysResult;
+use lilium_sys::sys::result::errors::UNSUPPORTED_KERNEL_FUNCTION;
+use wl_interface_map::{SetSyscallTraceFdTy, wl_set_syscall_trace_fd_name};
+
+use crate::handle_base::Handle;
+use crate::helpers::copy_nonoverlapping_checked;
+use crate::libc::{gettid, write};
+use crate::syscall_handler::{find_syscall, subsys_info};
+use crate::syscall_helpers::ParamKind;
+
+pub(crate) static TRACE_FD: AtomicI32 = AtomicI32::new(-1);
+
+#[unsafe(export_name = wl_set_syscall_trace_fd_name!())]
+unsafe extern "C" fn set_syscall_trace_fd(fd: i32) {
+    TRACE_FD.store(fd, Ordering::Relaxed);
+}
+
+const _: SetSyscallTraceFdTy = set_syscall_trace_fd;
+
+/// A line of trace output.
+///
+/// Each line is written with a single `write`, so that lines from different threads aren't interleaved.
+/// Lines that are too long are truncated.
+struct TraceLine {
+    buf: [u8; 1024],
+    len: usize,
+}
+
+impl TraceLine {
+    const fn new() -> Self {
+        Self {
+            buf: [0; 1024],
+            len: 0,
+        }
+    }
+
+    fn flush(&mut self, fd: i32) {
+        if self.len == self.buf.len() {
+            self.buf[self.len - 1] = b'\n';
+        }
+        let mut buf = &self.buf[..self.len];
+        while !buf.is_empty() {
+            match unsafe { write(fd, buf.as_ptr().cast(), buf.len()) } {
+                Ok(0) | Err(_) => break,
+                Ok(n) => buf = &buf[n..],
+            }
+        }
+        self.len = 0;
+    }
+}
+
+impl Write for TraceLine {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let rest = &mut self.buf[self.len..];
+        let n = s.len().min(rest.len());
+        rest[..n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// The maximum number of bytes of a string parameter that are logged
+const MAX_TRACE_STR: usize = 64;
+
+/// Writes the value of a parameter of kind `kind`, passed in the registers `vals`
+fn write_param(line: &mut TraceLine, kind: ParamKind, vals: &[usize]) {
+    match (kind, vals) {
+        (ParamKind::Pointer | ParamKind::Handle, [0]) => {
+            let _ = write!(line, "NULL");
+        }
+        (ParamKind::Pointer, [ptr]) => {
+            let _ = write!(line, "{ptr:#x}");
+        }
+        (ParamKind::Handle, &[hdl]) => {
+            let _ = write!(line, "{hdl:#x}");
+            // SAFETY: `try_deref` checks that `hdl` is a handle, and the handle is only read
+            match unsafe {
+                Handle::try_deref(core::mem::transmute::<usize, HandlePtr<Handle>>(hdl))
+            } {
+                Ok(hdl) => {
+                    let _ = write!(line, "<type {:#x}>", hdl.ident());
+                }
+                Err(_) => {
+                    let _ = write!(line, "<invalid>");
+                }
+            }
+        }
+        (ParamKind::Str, &[ptr, len]) => {
+            let mut buf = [0u8; MAX_TRACE_STR];
+            let n = len.min(MAX_TRACE_STR);
+            // SAFETY: the copy is checked, so an invalid string is logged as a pointer instead
+            let copied = unsafe {
+                copy_nonoverlapping_checked(
+                    core::ptr::with_exposed_provenance(ptr),
+                    buf.as_mut_ptr(),
+                    n,
+                )
+            };
+            match copied {
+                Ok(()) => {
+                    let _ = write!(line, "\"");
+                    for chunk in buf[..n].utf8_chunks() {
+                        let _ = write!(line, "{}", chunk.valid().escape_debug());
+                        for b in chunk.invalid() {
+                            let _ = write!(line, "\\x{b:02x}");
+                        }
+                    }
+                    let _ = write!(line, "\"");
+                    if len > n {
+                        let _ = write!(line, "...");
+                    }
+                }
+                Err(_) => {
+                    let _ = write!(line, "<invalid {ptr:#x}, {len}>");
+                }
+            }
+        }
+        (ParamKind::Slice, &[ptr, len]) => {
+            let _ = write!(line, "[{ptr:#x}; {len}]");
+        }
+        _ => {
+            for (i, val) in vals.iter().enumerate() {
+                let sep = if i == 0 { "" } else { ":" };
+                let _ = write!(line, "{sep}{val:#x}");
+            }
+        }
+    }
+}
+
+type SysCallTy6 = unsafe extern "sysv64" fn(usize, usize, usize, usize, usize, usize) -> SysResult;
+
+/// Invokes the syscall `sysno`, logging the call and its result to [`TRACE_FD`].
+///
+/// Called by `__handle_syscall` when tracing is enabled
+pub(crate) unsafe extern "sysv64" fn trace_syscall(
+    a: usize,
+    b: usize,
+    c: usize,
+    d: usize,
+    e: usize,
+    f: usize,
+    sysno: usize,
+) -> SysResult {
+    let fd = TRACE_FD.load(Ordering::Relaxed);
+    let args = [a, b, c, d, e, f];
+    let subsys = sysno >> 12;
+    let num = sysno & 0xFFF;
+    let tid = unsafe { gettid() }.unwrap_or(0);
+
+    let func = find_syscall(sysno);
+    let info = subsys_info(subsys);
+    let desc = info.and_then(|info| info.syscall_desc(num));
+
+    let mut line = TraceLine::new();
+
+    let _ = write!(line, "[{tid}] ");
+    match info {
+        Some(info) => {
+            let _ = write!(line, "{}.", info.name);
+        }
+        None => {
+            let _ = write!(line, "<subsys {subsys}>.");
+        }
+    }
+    match desc {
+        Some(desc) => {
+            let _ = write!(line, "{}(", desc.name);
+            let mut args = &args[..];
+            for (i, param) in desc.params.iter().enumerate() {
+                let Some((vals, rest)) = args.split_at_checked(param.words) else {
+                    break;
+                };
+                args = rest;
+                let sep = if i == 0 { "" } else { ", " };
+                let _ = write!(line, "{sep}{}=", param.name);
+                write_param(&mut line, param.kind, vals);
+            }
+        }
+        None => {
+            let _ = write!(line, "<{num:#x}>(");
+            for (i, val) in args.into_iter().enumerate() {
+                let sep = if i == 0 { "" } else { ", " };
+                let _ = write!(line, "{sep}{val:#x}");
+            }
+        }
+    }
+    let _ = writeln!(line, ")");
+    line.flush(fd);
+
+    let res = match func {
+        // SAFETY: the syscall is called with the registers it was invoked with
+        Some(func) => unsafe { core::mem::transmute::<_, SysCallTy6>(func)(a, b, c, d, e, f) },
+        None => UNSUPPORTED_KERNEL_FUNCTION,
+    };
+
+    let _ = write!(line, "[{tid}] ");
+    match (info, desc) {
+        (Some(info), Some(desc)) => {
+            let _ = write!(line, "{}.{}", info.name, desc.name);
+        }
+        _ => {
+            let _ = write!(line, "<{sysno:#x}>");
+        }
+    }
+    match Error::from_code(res) {
+        Ok(_) => {
+            let _ = writeln!(line, " = {res}");
+        }
+        Err(e) => {
+            let _ = writeln!(line, " = {res} ({e:?})");
+        }
+    }
+    line.flush(fd);
+
+    res
+}
@@ -0,0 +1,325 @@
+//! Typed wrappers for pointers passed to syscalls by the program.
+//!
+//! These can be used as [`export_syscall!`][crate::export_syscall] parameters in place of raw pointers, slices, and strings.
+//! Every access through them is checked, so that a bad pointer results in [`Error::InvalidMemory`] (or [`Error::InvalidString`]) rather than crashing winter-lily.
+//!
+//! The types read through these wrappers must be valid for any bit pattern, as the program can store arbitrary bytes in them.
+//! This is required by the [`UserValue`] bound on every accessor that reads memory.
+//!
+//! Addresses that winter-lily never dereferences itself (such as the base address of a mapping) remain raw pointers.
+
+use core::fmt;
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::{
+        except::ExceptionStatusInfo,
+        fs::FileOpenOptions,
+        handle::HandlePtr,
+        info::SysInfoRequest,
+        kstr::{KCSlice, KSlice, KStrCPtr, KStrPtr},
+        process::CreateProcessOption,
+    },
+    uuid::Uuid,
+};
+
+use crate::helpers::{
+    CheckUtfError, CheckedSliceIter, CheckedSliceIterMut, check_utf8, iter_checked,
+    iter_mut_checked, read_checked, write_checked,
+};
+
+/// A type that can be read from memory provided by the program.
+///
+/// # Safety
+/// Every bit pattern of `size_of::<Self>()` bytes must be a valid value of `Self`.
+/// In particular, `Self` must not contain `bool`s, `char`s, enums, or references.
+pub unsafe trait UserValue {}
+
+macro_rules! impl_user_value {
+    ($($ty:ty),* $(,)?) => {
+        $(unsafe impl UserValue for $ty {})*
+    };
+}
+
+impl_user_value!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+// Lilium types made up of integers, pointers, and unions of them
+impl_user_value!(
+    Uuid,
+    KStrCPtr,
+    KStrPtr,
+    SysInfoRequest,
+    CreateProcessOption,
+    FileOpenOptions,
+    ExceptionStatusInfo,
+);
+
+unsafe impl<T> UserValue for *const T {}
+unsafe impl<T> UserValue for *mut T {}
+unsafe impl<T> UserValue for HandlePtr<T> {}
+unsafe impl<T> UserValue for KCSlice<T> {}
+unsafe impl<T> UserValue for KSlice<T> {}
+unsafe impl<T: UserValue, const N: usize> UserValue for [T; N] {}
+
+/// A pointer to a value that is read by a syscall.
+#[repr(transparent)]
+pub struct UserPtr<T>(*const T);
+
+/// A pointer to a value that is written (and possibly read) by a syscall.
+#[repr(transparent)]
+pub struct UserPtrMut<T>(*mut T);
+
+/// A string passed to a syscall.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct UserStr(KStrCPtr);
+
+/// A slice that is read by a syscall.
+#[repr(transparent)]
+pub struct UserSlice<T>(KCSlice<T>);
+
+/// A slice that is written (and possibly read) by a syscall.
+#[repr(transparent)]
+pub struct UserSliceMut<T>(KSlice<T>);
+
+macro_rules! impl_copy {
+    ($($ty:ident),*) => {
+        $(
+            impl<T> Clone for $ty<T> {
+                fn clone(&self) -> Self {
+                    *self
+                }
+            }
+
+            impl<T> Copy for $ty<T> {}
+        )*
+    };
+}
+
+impl_copy!(UserPtr, UserPtrMut);
+
+impl<T> fmt::Debug for UserPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.0, f)
+    }
+}
+
+impl<T> fmt::Debug for UserPtrMut<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.0, f)
+    }
+}
+
+impl<T> UserPtr<T> {
+    /// Wraps `ptr`.
+    ///
+    /// # Safety
+    /// `ptr` must be provided by the program (for example, read from a structure it passed to a syscall).
+    /// It must not point to memory owned by winter-lily.
+    pub const unsafe fn from_raw(ptr: *const T) -> Self {
+        Self(ptr)
+    }
+
+    pub const fn as_raw(self) -> *const T {
+        self.0
+    }
+
+    pub fn is_null(self) -> bool {
+        self.0.is_null()
+    }
+}
+
+impl<T: UserValue> UserPtr<T> {
+    /// Reads the value pointed to.
+    ///
+    /// Returns [`Error::InvalidMemory`] if the pointer is null or the value cannot be read
+    pub fn read(self) -> Result<T> {
+        if self.is_null() {
+            return Err(Error::InvalidMemory);
+        }
+        Ok(unsafe { read_checked(self.0) }?)
+    }
+
+    /// Reads the value pointed to, or returns `None` if the pointer is null
+    pub fn read_opt(self) -> Result<Option<T>> {
+        if self.is_null() {
+            Ok(None)
+        } else {
+            self.read().map(Some)
+        }
+    }
+}
+
+impl<T> UserPtrMut<T> {
+    /// Wraps `ptr`.
+    ///
+    /// # Safety
+    /// `ptr` must be provided by the program (for example, read from a structure it passed to a syscall).
+    /// It must not point to memory owned by winter-lily.
+    pub const unsafe fn from_raw(ptr: *mut T) -> Self {
+        Self(ptr)
+    }
+
+    pub const fn as_raw(self) -> *mut T {
+        self.0
+    }
+
+    pub fn is_null(self) -> bool {
+        self.0.is_null()
+    }
+
+    /// Writes `val` to the value pointed to.
+    ///
+    /// Returns [`Error::InvalidMemory`] if the pointer is null or the value cannot be written
+    pub fn write(self, val: T) -> Result<()> {
+        if self.is_null() {
+            return Err(Error::InvalidMemory);
+        }
+        Ok(unsafe { write_checked(self.0, val) }?)
+    }
+
+    /// Writes `val` to the value pointed to, unless the pointer is null
+    pub fn write_opt(self, val: T) -> Result<()> {
+        if self.is_null() {
+            Ok(())
+        } else {
+            self.write(val)
+        }
+    }
+}
+
+impl<T: UserValue> UserPtrMut<T> {
+    /// Reads the value pointed to.
+    ///
+    /// Returns [`Error::InvalidMemory`] if the pointer is null or the value cannot be read
+    pub fn read(self) -> Result<T> {
+        if self.is_null() {
+            return Err(Error::InvalidMemory);
+        }
+        Ok(unsafe { read_checked(self.0) }?)
+    }
+}
+
+impl UserStr {
+    /// Wraps `kstr`.
+    ///
+    /// # Safety
+    /// `kstr` must be provided by the program (for example, read from a structure it passed to a syscall).
+    /// It must not point to memory owned by winter-lily.
+    pub const unsafe fn from_raw(kstr: KStrCPtr) -> Self {
+        Self(kstr)
+    }
+
+    pub const fn as_raw(self) -> KStrCPtr {
+        self.0
+    }
+
+    pub const fn len(&self) -> usize {
+        self.0.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    /// Validates the string and returns it.
+    ///
+    /// Returns [`Error::InvalidMemory`] if the string cannot be read, and [`Error::InvalidString`] if it is not valid UTF-8
+    pub fn as_str(&self) -> Result<&str> {
+        if self.is_empty() {
+            return Ok("");
+        }
+        unsafe { check_utf8(self.0) }.map_err(|e| match e {
+            CheckUtfError::Access(_) => Error::InvalidMemory,
+            CheckUtfError::InvalidUtf8 => Error::InvalidString,
+        })
+    }
+}
+
+impl<T> UserSlice<T> {
+    /// An empty slice
+    pub const fn empty() -> Self {
+        Self(KCSlice {
+            arr_ptr: core::ptr::null(),
+            len: 0,
+        })
+    }
+
+    /// Wraps `slice`.
+    ///
+    /// # Safety
+    /// `slice` must be provided by the program (for example, read from a structure it passed to a syscall).
+    /// It must not point to memory owned by winter-lily.
+    pub const unsafe fn from_raw(slice: KCSlice<T>) -> Self {
+        Self(slice)
+    }
+
+    pub const fn len(&self) -> usize {
+        self.0.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+}
+
+impl<T: UserValue> UserSlice<T> {
+    /// Iterates over the elements of the slice, checking each element as it is reached.
+    ///
+    /// Returns [`Error::InvalidMemory`] if the slice is null but not empty.
+    /// Each element that cannot be read yields a [`CheckedAccessError`][crate::helpers::CheckedAccessError], which converts to [`Error::InvalidMemory`].
+    pub fn iter(&self) -> Result<CheckedSliceIter<'_, T>> {
+        let arr_ptr = match (self.0.arr_ptr.is_null(), self.0.len) {
+            (false, _) => self.0.arr_ptr,
+            (true, 0) => core::ptr::dangling(),
+            (true, _) => return Err(Error::InvalidMemory),
+        };
+        Ok(unsafe {
+            iter_checked(KCSlice {
+                arr_ptr,
+                len: self.0.len,
+            })
+        })
+    }
+}
+
+impl<T> UserSliceMut<T> {
+    /// Wraps `slice`.
+    ///
+    /// # Safety
+    /// `slice` must be provided by the program (for example, read from a structure it passed to a syscall).
+    /// It must not point to memory owned by winter-lily.
+    pub const unsafe fn from_raw(slice: KSlice<T>) -> Self {
+        Self(slice)
+    }
+
+    pub const fn len(&self) -> usize {
+        self.0.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+}
+
+impl<T: UserValue> UserSliceMut<T> {
+    /// Iterates over the elements of the slice, checking each element as it is reached.
+    ///
+    /// Returns [`Error::InvalidMemory`] if the slice is null but not empty.
+    /// Each element that cannot be accessed yields a [`CheckedAccessError`][crate::helpers::CheckedAccessError], which converts to [`Error::InvalidMemory`].
+    pub fn iter_mut(&mut self) -> Result<CheckedSliceIterMut<'_, T>> {
+        let arr_ptr = match (self.0.arr_ptr.is_null(), self.0.len) {
+            (false, _) => self.0.arr_ptr,
+            (true, 0) => core::ptr::dangling_mut(),
+            (true, _) => return Err(Error::InvalidMemory),
+        };
+        Ok(unsafe {
+            iter_mut_checked(KSlice {
+                arr_ptr,
+                len: self.0.len,
+            })
+        })
+    }
+}
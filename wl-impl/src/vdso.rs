@@ -0,0 +1,224 @@
+//! A vDSO-like data page and fast-path functions, which let Lilium code answer cheap informational queries without trapping into winter-lily.
+//!
+//! The functions are exported from winter-lily, so they can be resolved through the Lilium rtld.
+//! Each returns `UNSUPPORTED_KERNEL_FUNCTION` when it can't answer a request, in which case the caller should make the syscall instead.
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use lilium_sys::result::{Error, Result};
+use lilium_sys::sys::info::{self as sys, SysInfoRequest};
+use lilium_sys::sys::kstr::KSlice;
+use lilium_sys::sys::result::SysResult;
+use lilium_sys::sys::result::errors::UNSUPPORTED_KERNEL_FUNCTION;
+use lilium_sys::uuid::{Uuid, parse_uuid};
+use rustix::time::{ClockId, clock_gettime};
+
+use crate::consts;
+use crate::helpers::{fill_str, iter_mut_checked, validate_option_head, write_checked};
+use crate::libc::{
+    MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE, getpid, gettid, mmap, mprotect,
+};
+
+/// The layout version of [`VdsoData`]. Incremented whenever fields are added.
+pub const VDSO_DATA_VERSION: u32 = 1;
+
+/// The data page shared with Lilium code. Lilium code must treat it as read-only.
+#[repr(C, align(4096))]
+pub struct VdsoData {
+    pub version: u32,
+    pub os_major: u32,
+    pub os_minor: u32,
+    pub kernel_major: u32,
+    pub kernel_minor: u32,
+    pub arch_version: u32,
+    pub arch_type: Uuid,
+    pub kernel_build_id: Uuid,
+    pub pid: i32,
+}
+
+const NIL_UUID: Uuid = parse_uuid("00000000-0000-0000-0000-000000000000");
+
+/// The data page, which is mapped separately from winter-lily so that it can be made read-only once it is filled in
+static VDSO_DATA: AtomicPtr<VdsoData> = AtomicPtr::new(core::ptr::null_mut());
+
+pub fn vdso_data() -> &'static VdsoData {
+    unsafe { &*VDSO_DATA.load(Ordering::Relaxed) }
+}
+
+/// Maps the data page, and fills in the fields that are known to winter-lily itself.
+///
+/// # Safety
+/// Must only be called during process initialization
+pub(crate) unsafe fn init_vdso_data() {
+    let page = unsafe {
+        mmap(
+            core::ptr::null_mut(),
+            size_of::<VdsoData>(),
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    }
+    .expect("Failed to map the vDSO data page")
+    .cast::<VdsoData>();
+
+    unsafe {
+        page.write(VdsoData {
+            version: VDSO_DATA_VERSION,
+            os_major: consts::OS_VERSION_MAJOR,
+            os_minor: consts::OS_VERSION_MINOR,
+            kernel_major: consts::VERSION_MAJOR,
+            kernel_minor: consts::VERSION_MINOR,
+            arch_version: 0,
+            arch_type: NIL_UUID,
+            kernel_build_id: NIL_UUID,
+            pid: getpid().unwrap_or(0),
+        });
+    }
+
+    VDSO_DATA.store(page, Ordering::Relaxed);
+}
+
+/// Publishes the system information computed by the base subsystem to the data page, then makes the page read-only.
+///
+/// # Safety
+/// Must only be called once, while initializing the base subsystem
+pub unsafe fn publish_base_info(kernel_build_id: Uuid, arch_type: Uuid, arch_version: u32) {
+    let page = VDSO_DATA.load(Ordering::Relaxed);
+    let data = unsafe { &mut *page };
+    data.kernel_build_id = kernel_build_id;
+    data.arch_type = arch_type;
+    data.arch_version = arch_version;
+
+    let _ = unsafe { mprotect(page.cast(), size_of::<VdsoData>(), PROT_READ) };
+}
+
+#[unsafe(no_mangle)]
+pub extern "sysv64" fn __wl_vdso_get_data() -> *const VdsoData {
+    vdso_data()
+}
+
+fn can_process_fast(req: &SysInfoRequest) -> bool {
+    let head = unsafe { &req.head };
+    (head.flags & 0x00010000) == 0
+        && matches!(
+            head.ty,
+            sys::SYSINFO_REQUEST_OSVER
+                | sys::SYSINFO_REQUEST_KVENDOR
+                | sys::SYSINFO_REQUEST_ARCH_INFO
+        )
+}
+
+fn process_request_fast(data: &VdsoData, req: &mut SysInfoRequest) -> Result<()> {
+    validate_option_head(unsafe { &req.head }, 0x0001)?;
+
+    match unsafe { req.head.ty } {
+        sys::SYSINFO_REQUEST_OSVER => {
+            let req = unsafe { &mut req.os_version };
+            req.head.flags &= !0x0001;
+            unsafe { fill_str(&mut req.osvendor_name, consts::OS_ELAB_NAME)? };
+            req.os_major = data.os_major;
+            req.os_minor = data.os_minor;
+            Ok(())
+        }
+        sys::SYSINFO_REQUEST_KVENDOR => {
+            let req = unsafe { &mut req.kernel_vendor };
+            req.head.flags &= !0x0001;
+            unsafe { fill_str(&mut req.kvendor_name, consts::KVENDOR_NAME)? };
+            req.build_id = data.kernel_build_id;
+            req.kernel_major = data.kernel_major;
+            req.kernel_minor = data.kernel_minor;
+            Ok(())
+        }
+        sys::SYSINFO_REQUEST_ARCH_INFO => {
+            let req = unsafe { &mut req.arch_info };
+            req.head.flags &= !0x0001;
+            req.arch_type = data.arch_type;
+            req.arch_version = data.arch_version;
+            Ok(())
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Fast path for `GetSystemInfo`.
+///
+/// Only answers the OS version, kernel vendor, and architecture requests. If any other request is present, nothing is processed.
+#[unsafe(no_mangle)]
+pub unsafe extern "sysv64" fn __wl_vdso_GetSystemInfo(reqs: KSlice<SysInfoRequest>) -> SysResult {
+    let data = vdso_data();
+
+    if unsafe { iter_mut_checked(reqs) }.any(|req| !req.is_ok_and(|req| can_process_fast(req))) {
+        return UNSUPPORTED_KERNEL_FUNCTION;
+    }
+
+    let mut res = Ok(());
+    for req in unsafe { iter_mut_checked(reqs) } {
+        res = res.and(
+            req.map_err(Error::from)
+                .and_then(|req| process_request_fast(data, req)),
+        );
+    }
+
+    match res {
+        Ok(()) => 0,
+        Err(e) => e.into_code(),
+    }
+}
+
+/// Returns the native process id of the current process
+#[unsafe(no_mangle)]
+pub extern "sysv64" fn __wl_vdso_current_pid() -> i64 {
+    vdso_data().pid as i64
+}
+
+#[thread_local]
+static TID: Cell<i32> = Cell::new(0);
+
+/// Returns the native thread id of the current thread
+#[unsafe(no_mangle)]
+pub extern "sysv64" fn __wl_vdso_current_tid() -> i64 {
+    let mut tid = TID.get();
+    if tid == 0 {
+        tid = unsafe { gettid() }.unwrap_or(0);
+        TID.set(tid);
+    }
+    tid as i64
+}
+
+pub const VDSO_CLOCK_REALTIME: u32 = 0;
+pub const VDSO_CLOCK_MONOTONIC: u32 = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct VdsoTime {
+    pub secs: i64,
+    pub nanos: u32,
+}
+
+/// Reads the clock `clock` (one of the `VDSO_CLOCK_*` constants) into `out`.
+///
+/// The host clock is read through the Linux vDSO where possible.
+#[unsafe(no_mangle)]
+pub unsafe extern "sysv64" fn __wl_vdso_clock_gettime(clock: u32, out: *mut VdsoTime) -> SysResult {
+    let id = match clock {
+        VDSO_CLOCK_REALTIME => ClockId::Realtime,
+        VDSO_CLOCK_MONOTONIC => ClockId::Monotonic,
+        _ => return Error::InvalidOperation.into_code(),
+    };
+
+    // The host vDSO falls back to a real syscall if it can't read the clock itself, which mustn't be intercepted as a Lilium syscall
+    let ts = crate::with_native_syscalls(|| clock_gettime(id));
+
+    let time = VdsoTime {
+        secs: ts.tv_sec as i64,
+        nanos: ts.tv_nsec as u32,
+    };
+
+    match unsafe { write_checked(out, time) } {
+        Ok(()) => 0,
+        Err(_) => Error::InvalidMemory.into_code(),
+    }
+}
@@ -1,8 +1,10 @@
 #![no_std]
 
+use core::ffi::c_char;
+
 use lilium_sys::sys::{
     handle::{Handle, HandlePtr},
-    kstr::KSlice,
+    kstr::{KCSlice, KSlice},
 };
 
 #[non_exhaustive]
@@ -17,11 +19,27 @@ pub enum FilterMode {
     Seccomp,
 }
 
+/// Information about the program being run, collected by the loader and passed to [`SetupProcessTy`].
+#[repr(C)]
+pub struct ProcessInfo {
+    /// The number of arguments in `argv`
+    pub argc: usize,
+    /// The arguments passed to the program, after any `--argv0` override is applied
+    pub argv: *const *const c_char,
+    /// The path used to execute the program (not necessarily absolute)
+    pub exec_path: *const c_char,
+    /// A file descriptor open to the program binary, or -1 if none is available.
+    ///
+    /// This is only valid for the duration of the call to [`SetupProcessTy`].
+    pub execfd: i32,
+}
+
 pub type SetupProcessTy = unsafe extern "C" fn(
     wl_load_base: *mut u8,
     wl_load_size: usize,
     mode: FilterMode,
     rand_init: [u8; 16],
+    proc_info: &ProcessInfo,
 );
 
 /// # Safety
@@ -31,10 +49,10 @@ pub type InitSubsystemTy = unsafe extern "C" fn();
 #[macro_export]
 macro_rules! wl_setup_process_name {
     () => {
-        "__wl_init_setup_process_v0"
+        "__wl_init_setup_process_v1"
     };
     (C) => {
-        c"__wl_init_setup_process_v0"
+        c"__wl_init_setup_process_v1"
     };
 }
 
@@ -48,14 +66,82 @@ macro_rules! wl_init_subsystem_name {
     };
 }
 
-pub type GetInitHandlesTy = unsafe extern "C" fn(&mut KSlice<HandlePtr<Handle>>);
+/// Fills the init handle array with handles for fds 0-2 (skipping any that are closed), followed by `extra_fds`.
+///
+/// The length of the slice is the capacity of the array on input, and the number of handles on output.
+pub type GetInitHandlesTy =
+    unsafe extern "C" fn(handles: &mut KSlice<HandlePtr<Handle>>, extra_fds: KCSlice<i32>);
 
 #[macro_export]
 macro_rules! wl_get_init_handles_name {
     () => {
-        "__wl_get_init_handles_v0"
+        "__wl_get_init_handles_v1"
+    };
+    (C) => {
+        c"__wl_get_init_handles_v1"
+    };
+}
+
+/// Requests that the next subsystem registered with a dynamic subsystem number uses `subsys` instead.
+///
+/// Subsystems registered with a fixed subsystem number ignore (and discard) the request.
+/// Passing `!0` cancels any pending request.
+pub type RequestSubsysNumberTy = unsafe extern "C" fn(subsys: usize);
+
+#[macro_export]
+macro_rules! wl_request_subsys_number_name {
+    () => {
+        "__wl_request_subsys_number_v0"
+    };
+    (C) => {
+        c"__wl_request_subsys_number_v0"
+    };
+}
+
+/// Enables syscall tracing, writing a line for each Lilium syscall made by the process to `fd`.
+///
+/// Passing a negative `fd` disables tracing.
+pub type SetSyscallTraceFdTy = unsafe extern "C" fn(fd: i32);
+
+#[macro_export]
+macro_rules! wl_set_syscall_trace_fd_name {
+    () => {
+        "__wl_set_syscall_trace_fd_v0"
+    };
+    (C) => {
+        c"__wl_set_syscall_trace_fd_v0"
+    };
+}
+
+/// Enables rewriting Lilium `syscall` instructions into direct calls into winter-lily.
+///
+/// Returns `false` if rewriting isn't available, in which case every syscall continues to use the signal path.
+pub type EnableSyscallRewritingTy = unsafe extern "C" fn() -> bool;
+
+#[macro_export]
+macro_rules! wl_enable_syscall_rewriting_name {
+    () => {
+        "__wl_enable_syscall_rewriting_v0"
+    };
+    (C) => {
+        c"__wl_enable_syscall_rewriting_v0"
+    };
+}
+
+/// The sandbox profile denies network access
+pub const SANDBOX_RESTRICT_NETWORK: u32 = 1 << 0;
+
+/// Informs winter-lily that the loader has applied a sandbox profile, with the `SANDBOX_RESTRICT_*` restrictions in `restrictions`.
+///
+/// The restrictions are reported by the security syscalls as kernel permissions that the program does not hold.
+pub type SetSandboxRestrictionsTy = unsafe extern "C" fn(restrictions: u32);
+
+#[macro_export]
+macro_rules! wl_set_sandbox_restrictions_name {
+    () => {
+        "__wl_set_sandbox_restrictions_v0"
     };
     (C) => {
-        c"__wl_get_init_handles_v0"
+        c"__wl_set_sandbox_restrictions_v0"
     };
 }
@@ -1,20 +1,27 @@
 use alloc::borrow::ToOwned;
-use ld_so_impl::elf::ElfHeader;
+use alloc::vec::Vec;
+use ld_so_impl::elf::consts::{PT_LOAD, PT_PHDR};
+use ld_so_impl::elf::{ElfHeader, ElfPhdr};
 use ld_so_impl::helpers::cstr_from_ptr;
 use ld_so_impl::loader::Error;
-use lilium_sys::sys::auxv::{AT_LILIUM_INIT_HANDLES, AT_LILIUM_INIT_HANDLES_LEN, AT_RANDOM};
+use lilium_sys::sys::auxv::{
+    AT_BASE, AT_ENTRY, AT_EXECFN, AT_HWCAP, AT_HWCAP2, AT_LILIUM_INIT_HANDLES,
+    AT_LILIUM_INIT_HANDLES_LEN, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_RANDOM, AT_SECURE,
+};
 use lilium_sys::sys::handle::{Handle, HandlePtr};
-use lilium_sys::sys::kstr::KSlice;
+use lilium_sys::sys::kstr::{KCSlice, KSlice};
 use linux_raw_sys::general::{
     MAP_ANONYMOUS, MAP_PRIVATE, O_RDONLY, PROT_NONE, PROT_READ, PROT_WRITE,
 };
 use linux_syscall::{
     Result as _, SYS_close, SYS_mmap, SYS_mprotect, SYS_open, SYS_openat, Syscall, syscall,
 };
-use rustix::fd::AsRawFd;
+use rustix::fd::{AsRawFd, IntoRawFd};
 use rustix::fs::{Mode, OFlags, open};
 use wl_interface_map::{
-    GetInitHandlesTy, wl_get_init_handles_name, wl_init_subsystem_name, wl_setup_process_name,
+    EnableSyscallRewritingTy, GetInitHandlesTy, SetSandboxRestrictionsTy, SetSyscallTraceFdTy,
+    wl_enable_syscall_rewriting_name, wl_get_init_handles_name, wl_init_subsystem_name,
+    wl_set_sandbox_restrictions_name, wl_set_syscall_trace_fd_name, wl_setup_process_name,
 };
 
 use core::ffi::{CStr, c_char, c_ulong, c_void};
@@ -27,7 +34,10 @@ use linux_syscall::{SYS_exit, SYS_prctl, SYS_write};
 use crate::auxv::AuxEnt;
 use crate::elf::{DynEntryType, ElfDyn};
 use crate::env;
-use crate::helpers::{FusedUnsafeCell, NullTerm, SyncPointer, open_sysroot_rdonly, rand::Gen};
+use crate::helpers::{
+    FusedUnsafeCell, MmapAllocator, NullTerm, SplitAscii, SyncPointer, has_prefix,
+    open_sysroot_rdonly, rand::Gen,
+};
 use crate::helpers::{pread_exact, udata};
 use crate::loader::{LOADER, TLS_MC, Tcb, set_tp, setup_tls_mc, update_tls};
 use crate::{env::__environ, resolver};
@@ -114,6 +124,18 @@ unsafe extern "C" fn __rust_entry(
     let mut execfn = core::ptr::null::<c_char>();
     let mut at_base = core::ptr::null();
 
+    let mut preloads = Vec::new_in(MmapAllocator::new_with_hint(
+        __MMAP_ADDR.0.wrapping_add(4096 * 16),
+    ));
+
+    let mut trace_dest = None::<&CStr>;
+    let mut sandbox_profile = None::<&CStr>;
+
+    let mut handle_fds = Vec::new_in(MmapAllocator::new_with_hint(
+        __MMAP_ADDR.0.wrapping_add(4096 * 20),
+    ));
+    let mut rewrite_syscalls = false;
+
     for auxent in auxv {
         match auxent.at_tag as u32 {
             linux_raw_sys::general::AT_BASE => {
@@ -184,6 +206,9 @@ unsafe extern "C" fn __rust_entry(
         }
     }
 
+    // The path of the program being run. When wl-ld-lilium is used as an interpreter, this is `AT_EXECFN`.
+    let mut exec_path = execfn;
+
     'a: {
         if execfd == !0 {
             if !execfn.is_null() {
@@ -245,15 +270,39 @@ unsafe extern "C" fn __rust_entry(
                             "\t\t<module> is either a library name (looked up in the lilium search path) or a path to a library."
                         );
                         println!(
-                            "\t--preload-native <module>, --preload-subsys <module>: Causes <module> to be loaded as a native library before any library other than ld.so."
+                            "\t--preload-native <module>, --preload-subsystem <module>: Causes <module> to be loaded as a native library before any library other than ld.so."
                         );
                         println!(
                             "\t\t<module> is either a library name (looked up in the native search path) or a path to a library."
                         );
                         println!(
-                            "\t\t--preload-subsys differs from --preload-native in that, after the library is loaded, a symbol named {} is found and executed.",
+                            "\t\t--preload-subsystem differs from --preload-native in that, after the library is loaded, a symbol named {} is found and executed.",
                             wl_init_subsystem_name!()
                         );
+                        println!(
+                            "\t\tEach preload option may be specified multiple times. Modules are loaded in the order given."
+                        );
+                        println!(
+                            "\t--rewrite-syscalls: Rewrite syscall instructions in Lilium code into direct calls into winter-lily the first time they are executed, which makes repeated syscalls much faster."
+                        );
+                        println!(
+                            "\t\tThis requires mapping address 0 (and thus vm.mmap_min_addr=0). If that isn't possible, syscalls are handled as normal."
+                        );
+                        println!(
+                            "\t--handle <fd>: Pass the inherited file descriptor <fd> to the program as an init handle, after the standard streams. May be specified multiple times."
+                        );
+                        println!(
+                            "\t--trace-syscalls <dest>: Log each Lilium syscall made by the program, with its arguments and result, to <dest>."
+                        );
+                        println!(
+                            "\t\t<dest> is either a file descriptor number or a path to a file, which is appended to."
+                        );
+                        println!(
+                            "\t--sandbox <profile>: Restrict the program with Landlock according to <profile> before it starts. The program is not started if the profile can't be fully applied."
+                        );
+                        println!(
+                            "\t\t<profile> is either `default` (read-only sysroot, writable /tmp, and no network access) or a path to a profile file."
+                        );
                         println!();
                         println!("Environment Variables:");
                         println!(
@@ -271,9 +320,33 @@ unsafe extern "C" fn __rust_entry(
                         println!(
                             "\tWL_LILIUM_LD_SO_CONF: Look in this file, instead of /etc/ld-lilium.so.conf, for paths to search for lilium libraries"
                         );
+                        println!(
+                            "\tWL_SUBSYS_CONF: Read the list of subsystems to load from this file, instead of /etc/winter-lily/subsystems.conf"
+                        );
                         println!(
                             "\tWL_SUBSYS_<name>: Specifies an **absolute path** to use when loading the subsystem with name <name>."
                         );
+                        println!(
+                            "\tWL_REWRITE_SYSCALLS: If set to a non-empty value other than 0, rewrite syscall instructions as if by --rewrite-syscalls"
+                        );
+                        println!(
+                            "\tWL_TRACE_SYSCALLS: Log Lilium syscalls as if by --trace-syscalls. Unlike --trace-syscalls, this also applies to child processes."
+                        );
+                        println!(
+                            "\tWL_SANDBOX: Apply the sandbox profile given by this variable, as if by --sandbox. This also applies to child processes, so the profile must be readable inside the sandbox."
+                        );
+                        println!(
+                            "\tWL_INIT_HANDLES: A list of inherited file descriptors (separated by ':') that are passed to the program as init handles, before any given by --handle. A file descriptor that is not open becomes a null init handle, so later handles keep their position."
+                        );
+                        println!(
+                            "\tWL_INIT_HANDLE_RIGHTS: A list of rights masks (in hex, separated by ':') that limit the rights of each init handle, starting with the standard streams. Set by CreateProcess."
+                        );
+                        println!(
+                            "\tWL_PRELOAD_NATIVE, WL_PRELOAD_SUBSYSTEM, WL_PRELOAD_LILIUM: A list of modules (separated by ':') that are preloaded as if by the corresponding --preload option."
+                        );
+                        println!(
+                            "\t\tModules in these variables are loaded before modules given on the command line. Preload options are added to these variables, so they apply to child processes as well."
+                        );
                         println!();
                         println!("Secure Mode:");
                         println!(
@@ -302,15 +375,79 @@ unsafe extern "C" fn __rust_entry(
                         return 0;
                     }
                     Ok("--argv0") => {
-                        let ptr = unsafe { argv.add(1).read() };
+                        let Some(name) = args.next() else {
+                            eprintln!("Option --argv0 requires an argument");
+                            return 1;
+                        };
 
-                        argv0_override = ptr;
+                        argv0_override = name.as_ptr().cast_mut();
+
+                        argv = unsafe { argv.add(2) };
+                    }
+                    Ok("--handle") => {
+                        let Some(fd) = args.next() else {
+                            eprintln!("Option --handle requires an argument");
+                            return 1;
+                        };
+
+                        let Some(fd) = core::str::from_utf8(fd.to_bytes())
+                            .ok()
+                            .and_then(|fd| fd.parse::<i32>().ok())
+                            .filter(|&fd| fd >= 0)
+                        else {
+                            eprintln!("Option --handle requires a file descriptor number");
+                            return 1;
+                        };
+
+                        handle_fds.push(fd);
+
+                        argv = unsafe { argv.add(2) };
+                    }
+                    Ok("--rewrite-syscalls") => {
+                        rewrite_syscalls = true;
+
+                        argv = unsafe { argv.add(1) };
+                    }
+                    Ok("--trace-syscalls") => {
+                        let Some(dest) = args.next() else {
+                            eprintln!("Option --trace-syscalls requires an argument");
+                            return 1;
+                        };
+
+                        trace_dest = Some(dest);
+
+                        argv = unsafe { argv.add(2) };
+                    }
+                    Ok("--sandbox") => {
+                        let Some(profile) = args.next() else {
+                            eprintln!("Option --sandbox requires an argument");
+                            return 1;
+                        };
+
+                        sandbox_profile = Some(profile);
+
+                        argv = unsafe { argv.add(2) };
+                    }
+                    Ok(
+                        opt @ ("--preload-subsystem"
+                        | "--preload-subsys"
+                        | "--preload-native"
+                        | "--preload-lilium"),
+                    ) => {
+                        let kind = match opt {
+                            "--preload-native" => PreloadKind::Native,
+                            "--preload-lilium" => PreloadKind::Lilium,
+                            _ => PreloadKind::Subsystem,
+                        };
+                        let Some(module) = args.next() else {
+                            eprintln!("Option {opt} requires an argument");
+                            return 1;
+                        };
+
+                        preloads.push((kind, module));
 
                         argv = unsafe { argv.add(2) };
                     }
-                    Ok("--preload-subsystem") => todo!("--preload-subsystem"),
-                    Ok("--preload-native") => todo!("--preload-native"),
-                    Ok("--preload-lilium") => todo!("--preload-lilium"),
                     Ok(x) if x.starts_with("--") => {
                         eprintln!(
                             "Unknown Option {x}. Note that if this is a relative program name, use `./{x}` instead"
@@ -326,6 +463,7 @@ unsafe extern "C" fn __rust_entry(
             }
 
             if let Some(exec_name) = exec_name {
+                exec_path = exec_name.as_ptr();
                 let fd = unsafe {
                     syscall!(
                         SYS_openat,
@@ -358,6 +496,15 @@ unsafe extern "C" fn __rust_entry(
         }
     }
 
+    let (envp, envpc) = export_preloads(envp, envpc, &preloads);
+
+    unsafe {
+        safe_addr_of!(__environ)
+            .cast::<*mut *mut c_char>()
+            .cast_mut()
+            .write(envp)
+    }
+
     let tls_block_ptr = unsafe {
         native_region_base
             .cast_mut()
@@ -444,6 +591,8 @@ unsafe extern "C" fn __rust_entry(
         (&mut *WL_RESOLVER.as_ptr()).delegate(&RESOLVER);
     }
 
+    load_preloads(PreloadKind::Native);
+
     let base = ldso::load_subsystem("base");
 
     let sym = RESOLVER.find_sym(wl_setup_process_name!(C), false);
@@ -454,12 +603,20 @@ unsafe extern "C" fn __rust_entry(
 
     let base_init_subsystem = RESOLVER.find_sym_in(wl_init_subsystem_name!(C), base, false);
 
+    let proc_info = wl_interface_map::ProcessInfo {
+        argc,
+        argv: argv.cast_const().cast(),
+        exec_path,
+        execfd,
+    };
+
     unsafe {
         setup_process(
             native_region_base.cast_mut().cast(),
             NATIVE_REGION_SIZE,
             wl_interface_map::FilterMode::Prctl,
             rand_bytes,
+            &proc_info,
         )
     }
 
@@ -469,11 +626,44 @@ unsafe extern "C" fn __rust_entry(
     unsafe {
         base_init_subsystem();
     }
-    ldso::load_and_init_subsystem("thread");
-    ldso::load_and_init_subsystem("io");
-    ldso::load_and_init_subsystem("process");
-    ldso::load_and_init_subsystem("debug");
-    ldso::load_and_init_subsystem("kmgmt");
+    subsys::mark_loaded(0);
+
+    if rewrite_syscalls
+        || env::get_env("WL_REWRITE_SYSCALLS").is_some_and(|v| !v.is_empty() && v != "0")
+    {
+        let sym = RESOLVER.find_sym(wl_enable_syscall_rewriting_name!(C), false);
+
+        let enable_syscall_rewriting: EnableSyscallRewritingTy =
+            unsafe { core::mem::transmute(sym) };
+
+        if !unsafe { enable_syscall_rewriting() } {
+            eprintln!("Syscall rewriting is not available, falling back to trapping syscalls");
+        }
+    }
+
+    if let Some(fd) = open_trace_fd(trace_dest) {
+        let sym = RESOLVER.find_sym(wl_set_syscall_trace_fd_name!(C), false);
+
+        let set_syscall_trace_fd: SetSyscallTraceFdTy = unsafe { core::mem::transmute(sym) };
+
+        unsafe {
+            set_syscall_trace_fd(fd);
+        }
+    }
+
+    for (num, ent) in subsys::manifest().iter() {
+        // base is already loaded, since it's needed to load anything else
+        // lazy subsystems are loaded by the syscall handler when they are first used
+        if ent.name() == "base" || ent.mode == LoadMode::Lazy {
+            continue;
+        }
+        // The init function of a subsystem earlier in the manifest may have loaded this one already
+        subsys::ensure_loaded(num);
+    }
+    ldso::load_and_init_native_subsystem();
+
+    load_preloads(PreloadKind::Subsystem);
+    load_preloads(PreloadKind::Lilium);
 
     let mut header: ElfHeader = bytemuck::zeroed();
 
@@ -487,25 +677,276 @@ unsafe extern "C" fn __rust_entry(
     };
 
     pread_exact(execfd, 0, bytemuck::bytes_of_mut(&mut header)).unwrap();
-    let _ = unsafe { syscall!(SYS_close, execfd) };
 
-    let entry = unsafe { binary.base.add(header.e_entry as usize) };
+    let program = LoadedProgram {
+        entry: unsafe { binary.base.add(header.e_entry as usize) },
+        phdr: find_phdrs(execfd, &header, binary.base),
+        phent: header.e_phentsize as usize,
+        phnum: header.e_phnum as usize,
+        exec_path,
+        loader_base: base_addr as *mut c_void,
+    };
+
+    let _ = unsafe { syscall!(SYS_close, execfd) };
 
     update_tls();
 
-    __setup_auxv(auxv, entry, argv, argc, envp, envpc, &mut rand)
+    let init_fds = init_fds(&handle_fds);
+
+    if let Some(profile) = sandbox_profile
+        .or_else(|| env::get_cenv("WL_SANDBOX"))
+        .filter(|profile| !profile.is_empty())
+    {
+        let restrictions = match sandbox::apply_sandbox(profile) {
+            Ok(restrictions) => restrictions,
+            Err(e) => {
+                eprintln!("Failed to apply sandbox profile {}: {e:?}", unsafe {
+                    core::str::from_utf8_unchecked(profile.to_bytes())
+                });
+                return 1;
+            }
+        };
+
+        let sym = RESOLVER.find_sym(wl_set_sandbox_restrictions_name!(C), false);
+
+        let set_sandbox_restrictions: SetSandboxRestrictionsTy =
+            unsafe { core::mem::transmute(sym) };
+
+        unsafe {
+            set_sandbox_restrictions(restrictions);
+        }
+    }
+
+    __setup_auxv(
+        auxv, &program, argv, argc, envp, envpc, &init_fds, &mut rand,
+    )
+}
+
+/// The parts of the loaded program (and loader) that are reported in the Lilium auxv
+struct LoadedProgram {
+    entry: *mut c_void,
+    /// The address of the program headers in memory, or null if they aren't mapped
+    phdr: *const c_void,
+    phent: usize,
+    phnum: usize,
+    exec_path: *const c_char,
+    loader_base: *mut c_void,
+}
+
+/// Finds the address the program headers of the binary open on `execfd` are mapped at, if any.
+///
+/// This is the `PT_PHDR` segment if present, or otherwise the `PT_LOAD` segment that contains the program headers in the file.
+fn find_phdrs(execfd: i32, header: &ElfHeader, base: *mut c_void) -> *const c_void {
+    let phoff = header.e_phoff as u64;
+    let phsize = (header.e_phentsize as u64) * (header.e_phnum as u64);
+    let mut from_load = core::ptr::null();
+
+    for i in 0..(header.e_phnum as u64) {
+        let mut phdr: ElfPhdr = bytemuck::zeroed();
+        if pread_exact(
+            execfd,
+            phoff + i * (header.e_phentsize as u64),
+            bytemuck::bytes_of_mut(&mut phdr),
+        )
+        .is_err()
+        {
+            return core::ptr::null();
+        }
+
+        // Segments are mapped at `p_paddr` (see `FdLoader::map_phdrs`)
+        match phdr.p_type {
+            PT_PHDR => return base.wrapping_add(phdr.p_paddr as usize),
+            PT_LOAD
+                if from_load.is_null()
+                    && (phdr.p_offset as u64) <= phoff
+                    && phoff + phsize <= (phdr.p_offset as u64) + (phdr.p_filesz as u64) =>
+            {
+                from_load = base
+                    .wrapping_add(phdr.p_paddr as usize)
+                    .wrapping_add((phoff - phdr.p_offset as u64) as usize);
+            }
+            _ => {}
+        }
+    }
+
+    from_load
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PreloadKind {
+    Native,
+    Subsystem,
+    Lilium,
+}
+
+impl PreloadKind {
+    const ALL: [PreloadKind; 3] = [Self::Native, Self::Subsystem, Self::Lilium];
+
+    const fn env_name(self) -> &'static str {
+        match self {
+            Self::Native => "WL_PRELOAD_NATIVE",
+            Self::Subsystem => "WL_PRELOAD_SUBSYSTEM",
+            Self::Lilium => "WL_PRELOAD_LILIUM",
+        }
+    }
+}
+
+/// Merges preloads given on the command line into the `WL_PRELOAD_*` environment variables, so that they are inherited by child processes.
+///
+/// Returns the new (null terminated) environment array and its length (not including the terminator)
+fn export_preloads(
+    envp: *mut *mut c_char,
+    envpc: usize,
+    preloads: &[(PreloadKind, &CStr)],
+) -> (*mut *mut c_char, usize) {
+    if preloads.is_empty() {
+        return (envp, envpc);
+    }
+
+    let alloc = MmapAllocator::new_with_hint(__MMAP_ADDR.0.wrapping_add(4096 * 24));
+
+    let old_env = unsafe { core::slice::from_raw_parts(envp, envpc) };
+
+    let mut new_env = Vec::with_capacity_in(envpc + PreloadKind::ALL.len() + 1, alloc);
+
+    for &var in old_env {
+        let bytes = unsafe { CStr::from_ptr(var) }.to_bytes();
+
+        let overriden = PreloadKind::ALL.into_iter().any(|kind| {
+            preloads.iter().any(|(k, _)| *k == kind)
+                && has_prefix(bytes, kind.env_name().as_bytes())
+                && bytes.get(kind.env_name().len()) == Some(&b'=')
+        });
+
+        if !overriden {
+            new_env.push(var);
+        }
+    }
+
+    for kind in PreloadKind::ALL {
+        let mut modules = preloads
+            .iter()
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, module)| module.to_bytes())
+            .peekable();
+
+        if modules.peek().is_none() {
+            continue;
+        }
+
+        let mut var = Vec::new_in(alloc);
+        var.extend_from_slice(kind.env_name().as_bytes());
+        var.push(b'=');
+
+        if let Some(existing) = env::get_cenv(kind.env_name()) {
+            if !existing.is_empty() {
+                var.extend_from_slice(existing.to_bytes());
+                var.push(b':');
+            }
+        }
+
+        for (i, module) in modules.enumerate() {
+            if i != 0 {
+                var.push(b':');
+            }
+            var.extend_from_slice(module);
+        }
+        var.push(0);
+
+        new_env.push(var.leak().as_mut_ptr().cast::<c_char>());
+    }
+
+    let len = new_env.len();
+    new_env.push(core::ptr::null_mut());
+
+    (new_env.leak().as_mut_ptr(), len)
+}
+
+/// Opens the destination for syscall tracing, given by `--trace-syscalls` or `WL_TRACE_SYSCALLS`
+fn open_trace_fd(dest: Option<&CStr>) -> Option<i32> {
+    let dest = dest.or_else(|| env::get_cenv("WL_TRACE_SYSCALLS"))?;
+
+    if dest.is_empty() {
+        return None;
+    }
+
+    if let Some(fd) = core::str::from_utf8(dest.to_bytes())
+        .ok()
+        .and_then(|dest| dest.parse::<i32>().ok())
+    {
+        return Some(fd);
+    }
+
+    match open(
+        dest,
+        OFlags::WRONLY | OFlags::CREATE | OFlags::APPEND | OFlags::CLOEXEC,
+        Mode::from_raw_mode(0o644),
+    ) {
+        Ok(fd) => Some(fd.into_raw_fd()),
+        Err(e) => {
+            eprintln!("Failed to open syscall trace file: {e:?}");
+            None
+        }
+    }
+}
+
+/// Collects the extra fds to pass as init handles, from `WL_INIT_HANDLES` followed by the `--handle` options
+fn init_fds(handle_fds: &[i32]) -> Vec<i32, MmapAllocator> {
+    let mut fds = Vec::new_in(MmapAllocator::new_with_hint(
+        __MMAP_ADDR.0.wrapping_add(4096 * 22),
+    ));
+
+    if let Some(list) = env::get_env("WL_INIT_HANDLES") {
+        for fd in SplitAscii::new(list, b':').filter(|fd| !fd.is_empty()) {
+            match fd.parse::<i32>() {
+                Ok(fd) if fd >= 0 => fds.push(fd),
+                _ => {
+                    // Keeps the position of the later init handles, and becomes a null handle
+                    eprintln!("Ignoring invalid file descriptor {fd} in WL_INIT_HANDLES");
+                    fds.push(-1);
+                }
+            }
+        }
+    }
+
+    fds.extend_from_slice(handle_fds);
+
+    fds
+}
+
+fn load_preloads(kind: PreloadKind) {
+    let Some(modules) = env::get_env(kind.env_name()) else {
+        return;
+    };
+
+    for module in SplitAscii::new(modules, b':').filter(|module| !module.is_empty()) {
+        match kind {
+            PreloadKind::Native => {
+                ldso::load_module(SearchType::Host, module);
+            }
+            PreloadKind::Subsystem => {
+                let ent = ldso::load_module(SearchType::Host, module);
+                ldso::request_subsys_number(subsys::alloc_dyn_subsys());
+                ldso::init_subsystem(ent);
+            }
+            PreloadKind::Lilium => {
+                ldso::load_module(SearchType::Winter, module);
+            }
+        }
+    }
 }
 
 fn __setup_auxv(
     host_auxv: &[AuxEnt],
-    entry: *mut c_void,
+    program: &LoadedProgram,
     argv: *mut *mut c_char,
     argc: usize,
     envp: *mut *mut c_char,
     envpc: usize,
+    init_fds: &[i32],
     rand: &mut Gen,
 ) -> ! {
-    let mut lilium_aux = bytemuck::zeroed::<[AuxEnt; 16]>();
+    let mut lilium_aux = bytemuck::zeroed::<[AuxEnt; 32]>();
     let mut init_handles = bytemuck::zeroed::<[HandlePtr<Handle>; 64]>();
     let mut random_bytes = [rand.next(), rand.next()];
     let mut init_handles = KSlice::from_slice_mut(&mut init_handles);
@@ -514,23 +955,68 @@ fn __setup_auxv(
 
     let get_init_handles: GetInitHandlesTy = unsafe { core::mem::transmute(get_init_handles) };
     unsafe {
-        get_init_handles(&mut init_handles);
+        get_init_handles(
+            &mut init_handles,
+            KCSlice {
+                arr_ptr: init_fds.as_ptr(),
+                len: init_fds.len(),
+            },
+        );
     }
 
-    lilium_aux[0] = AuxEnt {
-        at_tag: AT_RANDOM as usize,
-        at_val: core::ptr::addr_of_mut!(random_bytes).cast(),
-    };
-    lilium_aux[1] = AuxEnt {
-        at_tag: AT_LILIUM_INIT_HANDLES as usize,
-        at_val: init_handles.arr_ptr.cast(),
+    let host_aux = |tag: u32| {
+        host_auxv
+            .iter()
+            .find(|ent| ent.at_tag == tag as usize)
+            .map(|ent| ent.at_val)
     };
-    lilium_aux[2] = AuxEnt {
-        at_tag: AT_LILIUM_INIT_HANDLES_LEN as usize,
-        at_val: udata(init_handles.len),
+
+    let page_size = host_aux(linux_raw_sys::general::AT_PAGESZ).unwrap_or(udata(4096));
+    let hwcap = host_aux(linux_raw_sys::general::AT_HWCAP).unwrap_or(udata(0));
+    let hwcap2 = host_aux(linux_raw_sys::general::AT_HWCAP2).unwrap_or(udata(0));
+
+    let mut naux = 0;
+    let mut push_aux = |tag: usize, val: *mut c_void| {
+        lilium_aux[naux] = AuxEnt {
+            at_tag: tag,
+            at_val: val,
+        };
+        naux += 1;
     };
 
-    unsafe { __call_entry_point(argc, argv, envp, envpc, lilium_aux.as_mut_ptr(), 3, entry) }
+    push_aux(
+        AT_RANDOM as usize,
+        core::ptr::addr_of_mut!(random_bytes).cast(),
+    );
+    push_aux(AT_LILIUM_INIT_HANDLES as usize, init_handles.arr_ptr.cast());
+    push_aux(AT_LILIUM_INIT_HANDLES_LEN as usize, udata(init_handles.len));
+    push_aux(AT_PAGESZ as usize, page_size);
+    if !program.phdr.is_null() {
+        push_aux(AT_PHDR as usize, program.phdr.cast_mut());
+        push_aux(AT_PHENT as usize, udata(program.phent));
+        push_aux(AT_PHNUM as usize, udata(program.phnum));
+    }
+    push_aux(AT_ENTRY as usize, program.entry);
+    if !program.exec_path.is_null() {
+        push_aux(AT_EXECFN as usize, program.exec_path.cast_mut().cast());
+    }
+    push_aux(AT_HWCAP as usize, hwcap);
+    push_aux(AT_HWCAP2 as usize, hwcap2);
+    push_aux(AT_BASE as usize, program.loader_base);
+    // Secure mode is rejected at startup
+    push_aux(AT_SECURE as usize, udata(0));
+
+    unsafe {
+        __call_entry_point(
+            argc,
+            argv,
+            envp,
+            envpc,
+            lilium_aux.as_mut_ptr(),
+            naux,
+            program.entry,
+        )
+    }
 }
 
 unsafe extern "C" {
@@ -570,6 +1056,8 @@ fn resolve_error(c: &core::ffi::CStr, e: Error) -> ! {
 }
 
 use crate::ldso::{self, __MMAP_ADDR, SearchType};
+use crate::sandbox;
+use crate::subsys::{self, LoadMode};
 
 #[cfg(target_arch = "x86_64")]
 use x86_64::__call_entry_point;
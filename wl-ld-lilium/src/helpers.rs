@@ -421,14 +421,10 @@ impl<'a> DoubleEndedIterator for SplitAscii<'a> {
 
 static SYSROOT_FD: OnceLock<i32> = OnceLock::new();
 
-pub fn open_sysroot_rdonly(mut at_fd: i32, st: &str) -> crate::io::Result<i32> {
-    let mut path = safe_zeroed::<[u8; 256]>();
-    copy_to_slice_head(&mut path, st.as_bytes())[0] = 0;
-
-    let mut path = &path[..];
-    if path[0] == b'/' {
-        path = &path[1..];
-        at_fd = *(SYSROOT_FD.get_or_try_init(|| {
+/// Returns a directory fd open to `WL_SYSROOT` (or `/` if it isn't set)
+pub fn sysroot_fd() -> crate::io::Result<i32> {
+    SYSROOT_FD
+        .get_or_try_init(|| {
             let sysroot = get_cenv("WL_SYSROOT").unwrap_or(c"/");
 
             let ptr = sysroot.as_ptr();
@@ -443,7 +439,21 @@ pub fn open_sysroot_rdonly(mut at_fd: i32, st: &str) -> crate::io::Result<i32> {
             fd.check()?;
 
             Ok(fd.as_usize_unchecked() as i32)
-        })?);
+        })
+        .copied()
+}
+
+pub fn open_sysroot_rdonly(mut at_fd: i32, st: &str) -> crate::io::Result<i32> {
+    let mut path = safe_zeroed::<[u8; 256]>();
+    if st.len() >= path.len() {
+        return Err(linux_errno::ENAMETOOLONG);
+    }
+    copy_to_slice_head(&mut path, st.as_bytes())[0] = 0;
+
+    let mut path = &path[..];
+    if path[0] == b'/' {
+        path = &path[1..];
+        at_fd = sysroot_fd()?;
     }
     let fd = unsafe {
         syscall!(
@@ -6,8 +6,8 @@ use lilium_sys::sys::kstr::KStrCPtr;
 use core::mem::offset_of;
 
 use crate::entry::WL_RESOLVER;
-use crate::ldso::load_and_init_subsystem;
 use crate::loader::{TLS_MC, Tcb, get_tp, update_tls};
+use crate::subsys;
 
 #[repr(C)]
 pub struct TlsDesc {
@@ -77,5 +77,10 @@ unsafe extern "C" fn __rtld_update_global_tcb() {
 
 #[unsafe(no_mangle)]
 unsafe extern "C" fn __rtld_wl_load_subsystem_by_name(p: KStrCPtr) {
-    load_and_init_subsystem(unsafe { p.as_str() });
+    subsys::load_by_name(unsafe { p.as_str() });
+}
+
+#[unsafe(no_mangle)]
+unsafe extern "C" fn __rtld_wl_load_subsystem_by_number(subsys: usize) -> bool {
+    u16::try_from(subsys).is_ok_and(subsys::ensure_loaded)
 }
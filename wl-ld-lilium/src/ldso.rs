@@ -8,15 +8,16 @@ use ld_so_impl::helpers::{cstr_from_ptr, strlen_impl};
 use ld_so_impl::hidden_syms;
 use ld_so_impl::loader::{Error, LoaderImpl};
 use ld_so_impl::resolver::DynEntry;
-use linux_errno::{EACCES, ENOENT};
+use linux_errno::{EACCES, ENAMETOOLONG, ENOENT};
 use linux_raw_sys::general::AT_FDCWD;
-use linux_syscall::{Result as _, SYS_close, SYS_open, SYS_write, syscall};
+use linux_syscall::{Result as _, SYS_close, SYS_exit, SYS_open, SYS_write, syscall};
 
 use bytemuck::Zeroable;
-use wl_interface_map::wl_init_subsystem_name;
+use wl_interface_map::{wl_init_subsystem_name, wl_request_subsys_number_name};
 
-use crate::entry::RESOLVER;
+use crate::entry::{RESOLVER, WL_RESOLVER};
 use crate::env::{self, get_env};
+use crate::subsys;
 
 use crate::helpers::{
     FusedUnsafeCell, MmapAllocator, OnceLock, SyncPointer, copy_to_slice_head, has_prefix,
@@ -184,7 +185,7 @@ pub fn open_module(search: SearchType, name: &CStr) -> crate::io::Result<i32> {
                 return Ok(fd);
             }
             Err(e) => match e {
-                ENOENT | EACCES => continue,
+                ENOENT | EACCES | ENAMETOOLONG => continue,
                 v => return Err(v),
             },
         }
@@ -196,6 +197,29 @@ pub fn open_module(search: SearchType, name: &CStr) -> crate::io::Result<i32> {
 pub type Result<T> = core::result::Result<T, ld_so_impl::loader::Error>;
 
 pub fn load_subsystem(name: &str) -> &'static DynEntry {
+    let mut lib_name = [0u8; 96];
+    let next = copy_to_slice_head(&mut lib_name, "libwl-usi-".as_bytes());
+    let next = copy_to_slice_head(next, name.as_bytes());
+    let len = 96 - copy_to_slice_head(next, ".so".as_bytes()).len();
+
+    load_subsystem_from(name, unsafe {
+        core::str::from_utf8_unchecked(&lib_name[..len])
+    })
+}
+
+/// Loads and initializes the `wl-native-subsys` extension subsystem.
+///
+/// It can be overriden by `WL_SUBSYS_native` like any other subsystem.
+pub fn load_and_init_native_subsystem() -> &'static DynEntry {
+    let ent = load_subsystem_from("native", "libwl-native-subsys.so");
+
+    request_subsys_number(subsys::alloc_dyn_subsys());
+    init_subsystem(ent);
+
+    ent
+}
+
+fn load_subsystem_from(name: &str, lib_name: &str) -> &'static DynEntry {
     let _guard = LOAD_LOCK.write();
     let udata = core::ptr::without_provenance_mut(SearchType::Host as usize);
     let mut soname = [0u8; 96];
@@ -225,9 +249,7 @@ pub fn load_subsystem(name: &str) -> &'static DynEntry {
             }
         }
     } else {
-        let next = copy_to_slice_head(&mut var_name, "libwl-usi-".as_bytes());
-        let next = copy_to_slice_head(next, name.as_bytes());
-        copy_to_slice_head(next, ".so".as_bytes());
+        copy_to_slice_head(&mut var_name, lib_name.as_bytes())[0] = 0;
 
         let soname = CStr::from_bytes_until_nul(&var_name).unwrap();
 
@@ -245,9 +267,20 @@ pub fn load_subsystem(name: &str) -> &'static DynEntry {
     ret
 }
 
-pub fn load_and_init_subsystem(name: &str) -> &'static DynEntry {
+/// Loads and initializes the subsystem `name`, registering it as subsystem number `subsys`.
+///
+/// Use [`subsys::ensure_loaded`] or [`subsys::load_by_name`] instead, which only load each subsystem once.
+pub fn load_and_init_subsystem(name: &str, subsys: u16) -> &'static DynEntry {
     let ent = load_subsystem(name);
 
+    request_subsys_number(subsys);
+    init_subsystem(ent);
+
+    ent
+}
+
+/// Finds and runs the subsystem initializer of an already loaded module
+pub fn init_subsystem(ent: &'static DynEntry) {
     let init_subsystem = RESOLVER.find_sym_in(wl_init_subsystem_name!(C), ent, false);
 
     let init_subsystem: wl_interface_map::InitSubsystemTy =
@@ -256,6 +289,61 @@ pub fn load_and_init_subsystem(name: &str) -> &'static DynEntry {
     unsafe {
         init_subsystem();
     }
+}
 
-    ent
+/// Sets the subsystem number used by the next subsystem to be initialized, if it uses a dynamic subsystem number.
+///
+/// Must be called after the base subsystem is loaded.
+pub fn request_subsys_number(subsys: u16) {
+    let request_subsys_number = RESOLVER.find_sym(wl_request_subsys_number_name!(C), false);
+
+    let request_subsys_number: wl_interface_map::RequestSubsysNumberTy =
+        unsafe { core::mem::transmute(request_subsys_number) };
+
+    unsafe {
+        request_subsys_number(subsys as usize);
+    }
+}
+
+/// Loads an arbitrary module, either by library name (looked up in the search path for `search`) or by path.
+///
+/// Native modules are loaded into the global namespace, and Lilium modules into the Lilium namespace.
+pub fn load_module(search: SearchType, name: &str) -> &'static DynEntry {
+    let _guard = LOAD_LOCK.write();
+    let udata = core::ptr::without_provenance_mut(search as usize);
+    let mut buf = [0u8; 256];
+    if name.len() >= buf.len() {
+        eprintln!(
+            "Could not load: {name} (the name is longer than {} bytes)",
+            buf.len() - 1
+        );
+        let _ = unsafe { syscall!(SYS_exit, 1) };
+        crash_unrecoverably()
+    }
+    copy_to_slice_head(&mut buf, name.as_bytes())[0] = 0;
+    let cname = unsafe { cstr_from_ptr(buf.as_ptr().cast()) };
+
+    let fhdl = if name.contains('/') {
+        let fd = open_sysroot_rdonly(AT_FDCWD, name)
+            .unwrap_or_else(|_| RESOLVER.resolve_error(cname, Error::ObjectNotFound));
+
+        core::ptr::without_provenance_mut(fd as usize)
+    } else {
+        unsafe {
+            LOADER
+                .find(cname, udata)
+                .unwrap_or_else(|_| RESOLVER.resolve_error(cname, Error::ObjectNotFound))
+        }
+    };
+
+    let resolver = match search {
+        SearchType::Host => &RESOLVER,
+        SearchType::Winter => &WL_RESOLVER,
+    };
+
+    let ret = unsafe { resolver.load_from_handle(None, udata, fhdl, false) };
+    let _ = unsafe { syscall!(SYS_close, fhdl.addr() as i32) };
+    drop(_guard);
+    update_tls();
+    ret
 }
@@ -53,6 +53,8 @@ mod io;
 mod ldso;
 mod loader;
 mod resolver;
+mod sandbox;
+mod subsys;
 
 mod detect;
 mod env;
@@ -24,6 +24,7 @@ use crate::{
     helpers::{FusedUnsafeCell, SyncPointer, is_x86_feature_detected},
     io::STDERR,
     ldso::{self, SearchType},
+    subsys,
 };
 
 pub struct FdLoader {
@@ -42,14 +43,11 @@ impl LoaderImpl for FdLoader {
         let search: SearchType = unsafe { core::mem::transmute(udata) };
 
         let (search, nname) = if search == SearchType::Winter {
-            match soname.to_bytes() {
-                b"libusi-base.so" | b"libusi-thread.so" | b"libusi-io.so"
-                | b"libusi-process.so" | b"libusi-debug.so" | b"libusi-kmgmt.so" => {
-                    // default subsystem, preloaded before any lilium code is loaded
-                    return Err(Error::AssumePresent);
-                }
-                _ => (search, soname),
+            if subsys::is_subsystem_soname(soname.to_bytes()) {
+                // subsystem from the manifest, preloaded before any lilium code is loaded
+                return Err(Error::AssumePresent);
             }
+            (search, soname)
         } else {
             (search, soname)
         };
@@ -0,0 +1,180 @@
+//! Sandbox profiles, which are applied with Landlock before the program starts.
+//!
+//! A profile is a list of directives, one per line, with `#` starting a comment:
+//! * `read <path>` allows reading and executing files, and listing directories, beneath `<path>`,
+//! * `write <path>` additionally allows creating, modifying, and removing files beneath `<path>`,
+//! * `network allow` or `network deny` allows or denies TCP network access (it is allowed by default).
+//!
+//! Paths are host paths, except that a path starting with `@sysroot` is relative to `WL_SYSROOT` (or `/` if it isn't set).
+//! Paths that don't exist are ignored. Any other file system access is denied.
+
+use core::ffi::CStr;
+
+use linux_errno::{EINVAL, ENAMETOOLONG, ENOENT, EOPNOTSUPP};
+use linux_raw_sys::general::{AT_FDCWD, O_CLOEXEC, O_PATH};
+use linux_syscall::{Result as _, SYS_close, SYS_openat, syscall};
+use rustix::fd::BorrowedFd;
+use rustix::fs::FileType;
+use wl_interface_map::SANDBOX_RESTRICT_NETWORK;
+
+use crate::helpers::landlock::{
+    self, ACCESS_FS_READ, Ruleset, fs_access_for_abi, net_access_for_abi,
+};
+use crate::helpers::{SplitAscii, copy_to_slice_head, safe_zeroed, sysroot_fd};
+use crate::io::{BufFdReader, Result};
+
+/// The profile used for `--sandbox default`: a read-only sysroot and host libraries, writable `/tmp`, and no network access
+///
+/// Of `/proc`, only the entries of the process itself that winter-lily uses are allowed.
+/// `/proc/self` is resolved when the sandbox is applied, so child processes can't open their own entries.
+const DEFAULT_PROFILE: &[&str] = &[
+    "read @sysroot",
+    "read /lib",
+    "read /lib64",
+    "read /usr/lib",
+    "read /usr/lib64",
+    "read /proc/self/status",
+    "read /proc/self/fdinfo",
+    "write /proc/self/mem",
+    "write /tmp",
+    "write /dev/null",
+    "network deny",
+];
+
+struct SandboxBuilder {
+    ruleset: Ruleset,
+    allow_network: bool,
+}
+
+impl SandboxBuilder {
+    fn allow_path(&mut self, path: &str, access: u64) -> Result<()> {
+        let (dirfd, path) = match path
+            .strip_prefix("@sysroot")
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        {
+            Some(rest) => (sysroot_fd()?, rest.trim_start_matches('/')),
+            None => (AT_FDCWD, path),
+        };
+        let path = if path.is_empty() { "." } else { path };
+
+        let mut buf = safe_zeroed::<[u8; 256]>();
+        if path.len() >= buf.len() {
+            return Err(ENAMETOOLONG);
+        }
+        copy_to_slice_head(&mut buf, path.as_bytes())[0] = 0;
+
+        let fd = unsafe { syscall!(SYS_openat, dirfd, buf.as_ptr(), O_PATH | O_CLOEXEC) };
+        match fd.check() {
+            Ok(()) => {}
+            Err(ENOENT) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        let fd = fd.as_usize_unchecked() as i32;
+
+        let is_dir = rustix::fs::fstat(unsafe { BorrowedFd::borrow_raw(fd) })
+            .is_ok_and(|st| FileType::from_raw_mode(st.st_mode) == FileType::Directory);
+
+        let res = self.ruleset.allow_path(fd, access, is_dir);
+
+        let _ = unsafe { syscall!(SYS_close, fd) };
+
+        res
+    }
+
+    fn parse_directive(&mut self, st: &str) -> Result<()> {
+        let st = SplitAscii::new(st, b'#').split_once().0.trim_ascii();
+
+        if st.is_empty() {
+            return Ok(());
+        }
+
+        let mut fields = st.split_ascii_whitespace();
+
+        let (Some(directive), Some(arg), None) = (fields.next(), fields.next(), fields.next())
+        else {
+            eprintln!("Invalid sandbox profile entry: {st}");
+            return Err(EINVAL);
+        };
+
+        match (directive, arg) {
+            ("read", path) => self.allow_path(path, ACCESS_FS_READ),
+            ("write", path) => self.allow_path(path, !0),
+            ("network", "allow") => {
+                self.allow_network = true;
+                Ok(())
+            }
+            ("network", "deny") => {
+                self.allow_network = false;
+                Ok(())
+            }
+            _ => {
+                eprintln!("Invalid sandbox profile entry: {st}");
+                Err(EINVAL)
+            }
+        }
+    }
+}
+
+fn read_profile(builder: &mut SandboxBuilder, path: &CStr) -> Result<()> {
+    let fd = unsafe { syscall!(SYS_openat, AT_FDCWD, path.as_ptr(), O_CLOEXEC) };
+    fd.check()?;
+    let fd = fd.as_usize_unchecked() as i32;
+
+    let mut v = safe_zeroed::<[u8; 256]>();
+    let mut file = BufFdReader::new(fd);
+
+    let res = loop {
+        match file.read_line_static(&mut v) {
+            Ok(Some(line)) => {
+                if let Err(e) = builder.parse_directive(line) {
+                    break Err(e);
+                }
+            }
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+
+    let _ = unsafe { syscall!(SYS_close, fd) };
+
+    res
+}
+
+/// Applies the sandbox profile `profile` (either `default`, or the path to a profile) to the process.
+///
+/// Returns the `SANDBOX_RESTRICT_*` restrictions that were applied.
+/// If the profile can't be applied in full, an error is returned, and the program must not be started.
+pub fn apply_sandbox(profile: &CStr) -> Result<u32> {
+    let abi = landlock::abi_version().ok_or(EOPNOTSUPP)?;
+
+    let mut builder = SandboxBuilder {
+        ruleset: Ruleset::new(fs_access_for_abi(abi), 0)?,
+        allow_network: true,
+    };
+
+    if profile == c"default" {
+        for st in DEFAULT_PROFILE {
+            builder.parse_directive(st)?;
+        }
+    } else {
+        read_profile(&mut builder, profile)?;
+    }
+
+    let mut restrictions = 0;
+
+    builder.ruleset.restrict_self()?;
+
+    // Network access is denied by a separate layer, since it isn't known until the whole profile has been read
+    if !builder.allow_network {
+        let net_access = net_access_for_abi(abi);
+        if net_access == 0 {
+            eprintln!("Network access cannot be restricted by this kernel (Landlock ABI {abi})");
+            return Err(EOPNOTSUPP);
+        }
+
+        Ruleset::new(0, net_access)?.restrict_self()?;
+        restrictions |= SANDBOX_RESTRICT_NETWORK;
+    }
+
+    Ok(restrictions)
+}
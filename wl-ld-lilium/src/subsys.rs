@@ -0,0 +1,356 @@
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use linux_raw_sys::general::AT_FDCWD;
+use linux_syscall::{SYS_close, syscall};
+
+use crate::env::get_env;
+use crate::helpers::{
+    OnceLock, SplitAscii, copy_to_slice_head, has_prefix, has_suffix, open_sysroot_rdonly,
+    safe_zeroed,
+};
+use crate::io::BufFdReader;
+use crate::ldso;
+
+use wl_helpers::sync::Mutex;
+
+/// The maximum number of subsystems that can be registered in a process
+pub const MAX_SUBSYS: usize = 64;
+
+/// The first subsystem number that is allocated dynamically.
+///
+/// Lower numbers are reserved for the standard subsystems.
+pub const FIRST_DYN_SUBSYS: u16 = 8;
+
+const MAX_NAME_LEN: usize = 48;
+
+const DEFAULT_MANIFEST: [(&str, u16); 6] = [
+    ("base", 0),
+    ("thread", 1),
+    ("io", 2),
+    ("process", 3),
+    ("debug", 4),
+    ("kmgmt", 5),
+];
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum LoadMode {
+    /// The subsystem is loaded before the program starts
+    Eager,
+    /// The subsystem is loaded when it is first used
+    Lazy,
+}
+
+#[derive(Copy, Clone)]
+pub struct ManifestEntry {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    pub mode: LoadMode,
+}
+
+impl ManifestEntry {
+    const EMPTY: Self = Self {
+        name: [0; MAX_NAME_LEN],
+        name_len: 0,
+        mode: LoadMode::Eager,
+    };
+
+    fn new(name: &str, mode: LoadMode) -> Option<Self> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return None;
+        }
+        let mut ent = Self::EMPTY;
+        copy_to_slice_head(&mut ent.name, name.as_bytes());
+        ent.name_len = name.len();
+        ent.mode = mode;
+        Some(ent)
+    }
+
+    pub fn name(&self) -> &str {
+        unsafe { core::str::from_utf8_unchecked(&self.name[..self.name_len]) }
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.name_len != 0
+    }
+}
+
+/// The list of subsystems to load into the process, indexed by subsystem number.
+pub struct Manifest {
+    entries: [ManifestEntry; MAX_SUBSYS],
+}
+
+impl Manifest {
+    pub fn get(&self, subsys: u16) -> Option<&ManifestEntry> {
+        self.entries
+            .get(subsys as usize)
+            .filter(|ent| ent.is_present())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &ManifestEntry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, ent)| ent.is_present())
+            .map(|(num, ent)| (num as u16, ent))
+    }
+
+    pub fn find(&self, name: &str) -> Option<(u16, &ManifestEntry)> {
+        self.iter().find(|(_, ent)| ent.name() == name)
+    }
+}
+
+static MANIFEST: OnceLock<Manifest> = OnceLock::new();
+
+static NEXT_DYN_SUBSYS: AtomicU16 = AtomicU16::new(FIRST_DYN_SUBSYS);
+
+/// Set once the subsystem with each number is loaded and initialized.
+///
+/// Each subsystem is initialized while holding only its own entry, so the init function of a subsystem can use (and so load) other subsystems.
+static LOADED: [OnceLock<()>; MAX_SUBSYS] = [const { OnceLock::new() }; MAX_SUBSYS];
+
+/// The names of subsystems loaded by [`load_by_name`] that aren't in the manifest, indexed by subsystem number
+static UNLISTED: Mutex<[ManifestEntry; MAX_SUBSYS]> =
+    Mutex::new([ManifestEntry::EMPTY; MAX_SUBSYS]);
+
+/// Returns the subsystem manifest, reading it on first use.
+pub fn manifest() -> &'static Manifest {
+    MANIFEST.get_or_init(read_manifest)
+}
+
+/// Allocates a subsystem number for a subsystem that isn't in the manifest (such as a preloaded subsystem).
+pub fn alloc_dyn_subsys() -> u16 {
+    let manifest = manifest();
+    loop {
+        let num = NEXT_DYN_SUBSYS.fetch_add(1, Ordering::Relaxed);
+        if num as usize >= MAX_SUBSYS {
+            panic!("Cannot register more than {MAX_SUBSYS} subsystems");
+        }
+        if manifest.get(num).is_none() {
+            break num;
+        }
+    }
+}
+
+/// Records that the subsystem with number `subsys` has been loaded and initialized without [`ensure_loaded`] or [`load_by_name`]
+pub fn mark_loaded(subsys: u16) {
+    let _ = LOADED[subsys as usize].set(());
+}
+
+/// Loads and initializes the subsystem `name` as subsystem number `subsys`, unless that is already done.
+fn load_once(name: &str, subsys: u16) {
+    LOADED[subsys as usize].get_or_init(|| {
+        ldso::load_and_init_subsystem(name, subsys);
+    });
+}
+
+/// Loads and initializes the subsystem assigned to `subsys` by the manifest, if it isn't loaded already.
+///
+/// Returns `false` if the manifest doesn't assign any subsystem to `subsys`.
+pub fn ensure_loaded(subsys: u16) -> bool {
+    let Some(ent) = manifest().get(subsys) else {
+        return false;
+    };
+
+    load_once(ent.name(), subsys);
+
+    true
+}
+
+/// Loads and initializes the subsystem `name`, using the subsystem number from the manifest if it is listed there.
+///
+/// Does nothing if the subsystem is already loaded.
+pub fn load_by_name(name: &str) {
+    if let Some((num, _)) = manifest().find(name) {
+        ensure_loaded(num);
+        return;
+    }
+
+    // The lock is only held to pick the number, so that loading the subsystem can load others by name
+    let num = {
+        let mut unlisted = UNLISTED.lock();
+
+        match unlisted.iter().position(|ent| ent.name() == name) {
+            Some(num) => num as u16,
+            None => {
+                let Some(ent) = ManifestEntry::new(name, LoadMode::Eager) else {
+                    panic!("Invalid subsystem name {name}");
+                };
+
+                let num = alloc_dyn_subsys();
+                unlisted[num as usize] = ent;
+                num
+            }
+        }
+    };
+
+    load_once(name, num);
+}
+
+/// Checks if `soname` names the Lilium side of a subsystem that is loaded by winter-lily itself
+pub fn is_subsystem_soname(soname: &[u8]) -> bool {
+    if !(has_prefix(soname, b"libusi-") && has_suffix(soname, b".so")) {
+        return false;
+    }
+
+    let name = &soname[7..(soname.len() - 3)];
+
+    name == b"wl-native"
+        || manifest()
+            .iter()
+            .any(|(_, ent)| ent.name().as_bytes() == name)
+}
+
+fn read_manifest() -> Manifest {
+    let mut manifest = Manifest {
+        entries: [ManifestEntry::EMPTY; MAX_SUBSYS],
+    };
+
+    let path = get_env("WL_SUBSYS_CONF").unwrap_or("/etc/winter-lily/subsystems.conf");
+
+    let Ok(fd) = open_sysroot_rdonly(AT_FDCWD, path) else {
+        for (name, num) in DEFAULT_MANIFEST {
+            manifest.entries[num as usize] = ManifestEntry::new(name, LoadMode::Eager).unwrap();
+        }
+        return manifest;
+    };
+
+    let mut dynamic = [ManifestEntry::EMPTY; MAX_SUBSYS];
+    let mut ndynamic = 0;
+
+    if let Err(e) = parse_manifest(fd, &mut manifest, &mut dynamic, &mut ndynamic) {
+        eprintln!("Failed to read subsystem manifest {path}: {e:?}");
+    }
+
+    let _ = unsafe { syscall!(SYS_close, fd) };
+
+    // Base is required to load anything else, so it is always present
+    if manifest.find("base").is_none() {
+        manifest.entries[0] = ManifestEntry::new("base", LoadMode::Eager).unwrap();
+    }
+
+    let mut next = FIRST_DYN_SUBSYS as usize;
+
+    for ent in &dynamic[..ndynamic] {
+        while next < MAX_SUBSYS && manifest.entries[next].is_present() {
+            next += 1;
+        }
+        if next == MAX_SUBSYS {
+            eprintln!(
+                "Too many subsystems in manifest, ignoring subsystem {}",
+                ent.name()
+            );
+            continue;
+        }
+        manifest.entries[next] = *ent;
+    }
+
+    NEXT_DYN_SUBSYS.store(next as u16, Ordering::Relaxed);
+
+    manifest
+}
+
+fn parse_manifest(
+    fd: i32,
+    manifest: &mut Manifest,
+    dynamic: &mut [ManifestEntry; MAX_SUBSYS],
+    ndynamic: &mut usize,
+) -> crate::io::Result<()> {
+    let mut v = safe_zeroed::<[u8; 256]>();
+    let mut file = BufFdReader::new(fd);
+
+    loop {
+        let str = match file.read_line_static(&mut v)? {
+            Some(val) => val,
+            None => break,
+        };
+
+        let st = SplitAscii::new(str, b'#').split_once().0.trim_ascii();
+
+        if st.is_empty() {
+            continue;
+        }
+
+        let mut fields = st.split_ascii_whitespace();
+
+        let (Some(name), Some(num), mode, None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            eprintln!("Invalid subsystem manifest entry: {st}");
+            continue;
+        };
+
+        let mode = match mode {
+            None | Some("eager") => LoadMode::Eager,
+            Some("lazy") => LoadMode::Lazy,
+            Some(mode) => {
+                eprintln!("Invalid load mode {mode} for subsystem {name}");
+                continue;
+            }
+        };
+
+        if name == "base" && mode == LoadMode::Lazy {
+            eprintln!("The base subsystem cannot be loaded lazily");
+            continue;
+        }
+
+        if manifest.find(name).is_some()
+            || dynamic[..*ndynamic].iter().any(|ent| ent.name() == name)
+        {
+            eprintln!("Duplicate subsystem manifest entry for {name}");
+            continue;
+        }
+
+        let Some(ent) = ManifestEntry::new(name, mode) else {
+            eprintln!("Invalid subsystem name {name}");
+            continue;
+        };
+
+        let num = match num {
+            "dynamic" => None,
+            num => match num.parse::<u16>() {
+                Ok(num) if (num as usize) < MAX_SUBSYS => Some(num),
+                _ => {
+                    eprintln!("Invalid subsystem number {num} for subsystem {name}");
+                    continue;
+                }
+            },
+        };
+
+        // The standard subsystems always register themselves with their standard number
+        let num = match DEFAULT_MANIFEST
+            .iter()
+            .find(|(std_name, _)| *std_name == name)
+        {
+            Some(&(_, std_num)) => {
+                if num.is_some_and(|num| num != std_num) {
+                    eprintln!("Subsystem {name} must use subsystem number {std_num}");
+                }
+                Some(std_num)
+            }
+            None => num,
+        };
+
+        match num {
+            Some(num) => {
+                if manifest.entries[num as usize].is_present() {
+                    eprintln!(
+                        "Subsystem number {num} is already used by {}, ignoring subsystem {name}",
+                        manifest.entries[num as usize].name()
+                    );
+                    continue;
+                }
+                manifest.entries[num as usize] = ent;
+            }
+            None => {
+                if *ndynamic == MAX_SUBSYS {
+                    eprintln!("Too many subsystems in manifest, ignoring subsystem {name}");
+                    continue;
+                }
+                dynamic[*ndynamic] = ent;
+                *ndynamic += 1;
+            }
+        }
+    }
+
+    Ok(())
+}
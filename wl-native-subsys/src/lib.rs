@@ -0,0 +1,32 @@
+#![no_std]
+#![feature(never_type)]
+use wl_impl::{def_subsystem, syscall_handler::register_sysinfo_handler};
+
+mod native;
+mod sysinfo;
+
+/// Syscall numbers generated from the `.knum` files of the subsystem
+#[allow(non_upper_case_globals)]
+mod sysno {
+    include!(concat!(env!("OUT_DIR"), "/sysno.rs"));
+}
+
+use sysno::{SYS_WlExecuteNative, SYS_WlGetLastNativeError};
+
+def_subsystem! {
+    name: "wl-native",
+    uuid: "a22304af-3619-59d8-9a95-1335d8e45441",
+    number: dynamic,
+    version: (0, 1, 0),
+    declared: sysno::DECLARED_SYSCALLS,
+    syscalls: [
+        SYS_WlExecuteNative => native::WlExecuteNative,
+        SYS_WlGetLastNativeError => native::WlGetLastNativeError,
+    ],
+    init: register_sysinfo_handlers,
+}
+
+fn register_sysinfo_handlers() {
+    register_sysinfo_handler(&sysinfo::NATIVE_PLATFORM);
+    register_sysinfo_handler(&sysinfo::SUBSYSTEM_SYSCALLS);
+}
@@ -0,0 +1,31 @@
+use core::ffi::c_void;
+
+use lilium_sys::{result::Error, sys::result::SysResult};
+use wl_impl::{
+    export_syscall,
+    helpers::{last_unmapped_errno, read_checked},
+    with_native_syscalls,
+};
+
+export_syscall! {
+    unsafe extern fn WlExecuteNative(exec_addr: *const c_void, udata: *mut c_void) -> SysResult {
+        if exec_addr.is_null() {
+            return Error::InvalidMemory.into_code();
+        }
+
+        // Make sure the code is at least mapped before we jump to it. Execute permissions are checked by the cpu.
+        if unsafe { read_checked(exec_addr.cast::<u8>()) }.is_err() {
+            return Error::InvalidMemory.into_code();
+        }
+
+        let native: unsafe extern "sysv64" fn(*mut c_void) -> SysResult = unsafe { core::mem::transmute(exec_addr) };
+
+        with_native_syscalls(|| unsafe { native(udata) })
+    }
+}
+
+export_syscall! {
+    unsafe extern fn WlGetLastNativeError() -> SysResult {
+        last_unmapped_errno() as SysResult
+    }
+}
@@ -0,0 +1,123 @@
+use core::ffi::c_char;
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::{
+        info::SysInfoRequest,
+        kstr::{KSlice, KStrCPtr},
+        option::ExtendedOptionHead,
+    },
+    uuid::{Uuid, parse_uuid},
+};
+use wl_helpers::LazyLock;
+use wl_impl::{
+    helpers::copy_nonoverlapping_checked,
+    libc::{new_utsname, uname},
+    syscall_handler::{SysInfoHandler, all_subsystems, fill_syscall_bitmap, syscall_table},
+};
+
+pub const SYSINFO_REQUEST_WL_NATIVE_PLATFORM: Uuid =
+    parse_uuid("1719e373-21ed-5423-b2d8-5eed28f27587");
+
+/// Provides information about the native platform (typically gathered from `uname(2)`)
+#[repr(C)]
+pub struct SysInfoOptionWlNativePlatform {
+    pub head: ExtendedOptionHead,
+    /// The name of the Native Platform (usually the kernel)
+    pub native_sys: KStrCPtr,
+    /// The release of the native platform
+    pub native_release: KStrCPtr,
+    /// The version of the native platform
+    pub native_version: KStrCPtr,
+}
+
+const _: () = assert!(
+    core::mem::size_of::<SysInfoOptionWlNativePlatform>() <= core::mem::size_of::<SysInfoRequest>()
+);
+
+pub const SYSINFO_REQUEST_WL_SUBSYSTEM_SYSCALLS: Uuid =
+    parse_uuid("c30ae25c-70ca-57ff-9906-02bb3601e413");
+
+/// Reports which syscalls are implemented by a subsystem
+#[repr(C)]
+pub struct SysInfoOptionWlSubsystemSyscalls {
+    pub head: ExtendedOptionHead,
+    /// The id of the subsystem to query
+    pub subsys_id: Uuid,
+    /// Filled with a bitmap of the implemented syscalls, where bit `n % 64` of element `n / 64` is set if syscall `n` is implemented.
+    /// `len` is set to the number of elements needed to cover every implemented syscall.
+    pub implemented: KSlice<u64>,
+}
+
+const _: () = assert!(
+    core::mem::size_of::<SysInfoOptionWlSubsystemSyscalls>()
+        <= core::mem::size_of::<SysInfoRequest>()
+);
+
+static UNAME: LazyLock<new_utsname> = LazyLock::new(|| {
+    let mut buf = unsafe { core::mem::zeroed() };
+    if unsafe { uname(&mut buf) }.is_err() {
+        buf = unsafe { core::mem::zeroed() };
+    }
+    buf
+});
+
+fn uname_field(field: &'static [c_char]) -> KStrCPtr {
+    let n = field.iter().take_while(|c| (**c) != 0).count();
+    let st = str::from_utf8(bytemuck::cast_slice(&field[..n])).unwrap_or("");
+
+    KStrCPtr {
+        str_ptr: st.as_ptr(),
+        len: st.len(),
+    }
+}
+
+fn process_native_platform(req: &mut SysInfoRequest) -> Result<()> {
+    let req = unsafe { &mut *(req as *mut SysInfoRequest as *mut SysInfoOptionWlNativePlatform) };
+    req.head.flags &= !0x0001;
+
+    let uts = &*UNAME;
+    req.native_sys = uname_field(&uts.sysname);
+    req.native_release = uname_field(&uts.release);
+    req.native_version = uname_field(&uts.version);
+    Ok(())
+}
+
+fn process_subsystem_syscalls(req: &mut SysInfoRequest) -> Result<()> {
+    let req =
+        unsafe { &mut *(req as *mut SysInfoRequest as *mut SysInfoOptionWlSubsystemSyscalls) };
+    req.head.flags &= !0x0001;
+
+    let Some((subsys, _)) = all_subsystems().find(|(_, info)| info.uuid == req.subsys_id) else {
+        return Err(Error::InvalidOption);
+    };
+
+    // A subsystem that doesn't implement any syscalls needs no elements at all
+    let needed = syscall_table(subsys as usize)
+        .and_then(|arr| arr.iter().rposition(Option::is_some))
+        .map_or(0, |max| (max / 64) + 1);
+    let mut bitmap = [0u64; 4096 / 64];
+    fill_syscall_bitmap(subsys as usize, &mut bitmap[..needed]);
+
+    let len = req.implemented.len.min(needed);
+
+    unsafe {
+        copy_nonoverlapping_checked(bitmap.as_ptr(), req.implemented.arr_ptr, len)?;
+    }
+
+    if core::mem::replace(&mut req.implemented.len, needed) < needed {
+        Err(Error::InsufficientLength)
+    } else {
+        Ok(())
+    }
+}
+
+pub static SUBSYSTEM_SYSCALLS: SysInfoHandler = SysInfoHandler {
+    ty: SYSINFO_REQUEST_WL_SUBSYSTEM_SYSCALLS,
+    process: process_subsystem_syscalls,
+};
+
+pub static NATIVE_PLATFORM: SysInfoHandler = SysInfoHandler {
+    ty: SYSINFO_REQUEST_WL_NATIVE_PLATFORM,
+    process: process_native_platform,
+};
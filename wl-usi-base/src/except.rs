@@ -2,17 +2,18 @@ use core::ffi::c_void;
 
 use lilium_sys::result::Result;
 use lilium_sys::sys::except::ExceptionStatusInfo;
-use wl_impl::{eprintln, export_syscall, helpers::exit_unrecoverably};
+use wl_impl::{eprintln, export_syscall, helpers::exit_unrecoverably, user_ptr::UserPtr};
 
 export_syscall! {
-    unsafe extern fn UnmanagedException(ptr: *const ExceptionStatusInfo) -> ! {
-        exit_unrecoverably(Some(unsafe{(*ptr).except_code}))
+    unsafe extern fn UnmanagedException(ptr: UserPtr<ExceptionStatusInfo>) -> ! {
+        exit_unrecoverably(ptr.read().ok().map(|info| info.except_code))
     }
 }
 
 export_syscall! {
-    unsafe extern fn ExceptHandleSynchronous(ptr: *const ExceptionStatusInfo, _data: *const c_void) -> Result<()> {
+    unsafe extern fn ExceptHandleSynchronous(ptr: UserPtr<ExceptionStatusInfo>, _data: UserPtr<c_void>) -> Result<()> {
+        let info = ptr.read()?;
         // TODO: There is more stuff to do, but for now, treat it as though exceptions are all unmanaged
-        exit_unrecoverably(Some(unsafe{(*ptr).except_code}))
+        exit_unrecoverably(Some(info.except_code))
     }
 }
@@ -0,0 +1,370 @@
+use core::mem::MaybeUninit;
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::handle::{HANDLE_TYPE_PROC, Handle as SysHandle, HandlePtr},
+};
+use rustix::{
+    fd::{AsFd, BorrowedFd, IntoRawFd, OwnedFd},
+    fs::{MemfdFlags, ftruncate, memfd_create},
+    io::{Errno, IoSlice, IoSliceMut, fcntl_dupfd_cloexec, pread, pwrite},
+    net::{
+        RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, ReturnFlags, SendAncillaryBuffer,
+        SendAncillaryMessage, SendFlags, recvmsg, sendmsg,
+    },
+    process::{PTracer, Pid, PidfdGetfdFlags, getpid, pidfd_getfd, set_ptracer},
+};
+use wl_impl::{
+    export_syscall,
+    handle_base::{
+        HANDLE_RIGHT_CLOSE, HANDLE_RIGHT_TRANSFER, Handle, handle_for_fd, insert_handle,
+    },
+    helpers::{ErrorContext, rustix_error_to_lilium},
+    libc::close,
+    ministd::{AsRawFd, Mutex},
+    user_ptr::UserPtrMut,
+};
+
+#[repr(C, align(16))]
+struct Align16<T>(T);
+
+/// The data sent along with the fds of a handle by [`SendHandle`].
+///
+/// The type of the handle isn't sent, since the receiver determines it from the fd itself.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
+struct HandleMessage {
+    rights: usize,
+}
+
+const SPACE_NEEDED: usize = rustix::cmsg_space!(ScmRights(2));
+
+/// The fd of the handoff mailbox of a process, where [`SendHandle`] leaves handles for processes that it has no channel to.
+///
+/// The number is fixed, so that the receiving process can open the mailbox of the sender with `pidfd_getfd`.
+const MAILBOX_FD: i32 = 1023;
+
+const MAILBOX_ENTRIES: usize = 64;
+
+/// A handle left in the handoff mailbox
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
+struct MailboxEntry {
+    /// The pid of the process the handle is for, or 0 once it has been taken
+    target: i32,
+    rights: u32,
+    /// The fds of the handle in the sending process, or -1
+    fds: [i32; 2],
+}
+
+/// The fds of each entry in the handoff mailbox, which are kept open until the entry is taken.
+///
+/// `None` until the mailbox is created.
+static MAILBOX_PENDING: Mutex<Option<[[i32; 2]; MAILBOX_ENTRIES]>> = Mutex::new(None);
+
+/// Inserts `hdl` and stores it in `hdl_out`, closing it if it can't be stored
+fn store_handle(hdl: Handle, hdl_out: UserPtrMut<HandlePtr<SysHandle>>) -> Result<()> {
+    let fds = [hdl.borrow_fd(), hdl.borrow_fd2()].map(|fd| fd.map(|fd| fd.as_raw_fd()));
+
+    let ptr = insert_handle(hdl).inspect_err(|_| {
+        for fd in fds.into_iter().flatten() {
+            let _ = unsafe { close(fd) };
+        }
+    })?;
+
+    hdl_out.write(ptr.cast()).inspect_err(|_| {
+        unsafe { Handle::deref_unchecked(ptr) }.release();
+    })
+}
+
+fn handle_error(e: Errno) -> Error {
+    rustix_error_to_lilium(e, ErrorContext::General)
+}
+
+/// Creates the handle for `fd` and `fd2` received from another process, with at most the rights in `rights`.
+///
+/// The sender is untrusted, so the type and rights come from the fd, and the sender can only remove rights.
+fn handle_from_fds(fd: OwnedFd, fd2: Option<OwnedFd>, rights: u32) -> Result<Handle> {
+    let mut hdl = handle_for_fd(fd.as_raw_fd()).ok_or(Error::InvalidState)?;
+    hdl.rights &= rights;
+
+    if let Some(fd2) = fd2 {
+        // `blob2` of a process handle is its pid, not an fd
+        if hdl.ty == HANDLE_TYPE_PROC as usize {
+            return Err(Error::InvalidState);
+        }
+        hdl.blob2 = core::ptr::without_provenance_mut(-(fd2.into_raw_fd() as isize) as usize);
+    }
+
+    let _ = fd.into_raw_fd();
+
+    Ok(hdl)
+}
+
+fn read_entry(mailbox: BorrowedFd, idx: usize) -> Result<MailboxEntry> {
+    let mut ent = MailboxEntry {
+        target: 0,
+        rights: 0,
+        fds: [-1; 2],
+    };
+    let off = (idx * size_of::<MailboxEntry>()) as u64;
+
+    match pread(mailbox, bytemuck::bytes_of_mut(&mut ent), off) {
+        Ok(n) if n == size_of::<MailboxEntry>() => Ok(ent),
+        Ok(_) => Err(Error::InvalidState),
+        Err(e) => Err(handle_error(e)),
+    }
+}
+
+fn write_entry(mailbox: BorrowedFd, idx: usize, ent: MailboxEntry) -> Result<()> {
+    let off = (idx * size_of::<MailboxEntry>()) as u64;
+
+    match pwrite(mailbox, bytemuck::bytes_of(&ent), off) {
+        Ok(n) if n == size_of::<MailboxEntry>() => Ok(()),
+        Ok(_) => Err(Error::InvalidState),
+        Err(e) => Err(handle_error(e)),
+    }
+}
+
+/// Creates the handoff mailbox of the current process at [`MAILBOX_FD`]
+fn create_mailbox() -> Result<()> {
+    let memfd = memfd_create(c"/winter-lily/handoff", MemfdFlags::CLOEXEC).map_err(handle_error)?;
+    ftruncate(&memfd, (MAILBOX_ENTRIES * size_of::<MailboxEntry>()) as u64)
+        .map_err(handle_error)?;
+
+    let fd = fcntl_dupfd_cloexec(&memfd, MAILBOX_FD).map_err(handle_error)?;
+
+    // `MAILBOX_FD` is already in use
+    if fd.as_raw_fd() != MAILBOX_FD {
+        return Err(Error::ResourceLimitExhausted);
+    }
+
+    let _ = fd.into_raw_fd();
+    Ok(())
+}
+
+/// Leaves a handle with the fds `fd` and `fd2` in the handoff mailbox of the current process, for the process `target`.
+///
+/// `target` takes it with `pidfd_getfd`, which requires it to be allowed to ptrace the current process.
+/// Where Yama restricts ptrace to descendants, `target` is made the ptracer of the current process, and since there is only one,
+///  only the most recent target can take its handle.
+fn send_to_process(
+    target: i32,
+    rights: u32,
+    fd: BorrowedFd,
+    fd2: Option<BorrowedFd>,
+) -> Result<()> {
+    // The process has already been reaped
+    let target_pid = Pid::from_raw(target).ok_or(Error::InvalidState)?;
+
+    let mut pending = MAILBOX_PENDING.lock();
+    if pending.is_none() {
+        create_mailbox()?;
+        *pending = Some([[-1; 2]; MAILBOX_ENTRIES]);
+    }
+    let pending = pending.as_mut().unwrap();
+    let mailbox = unsafe { BorrowedFd::borrow_raw(MAILBOX_FD) };
+
+    // Close the fds of the handles that were taken since the last send, and find a free entry
+    let mut free = None;
+    for (idx, fds) in pending.iter_mut().enumerate() {
+        if fds[0] >= 0 && read_entry(mailbox, idx)?.target == 0 {
+            for fd in fds.iter_mut().filter(|fd| **fd >= 0) {
+                let _ = unsafe { close(*fd) };
+                *fd = -1;
+            }
+        }
+        if fds[0] < 0 && free.is_none() {
+            free = Some(idx);
+        }
+    }
+    let idx = free.ok_or(Error::ResourceLimitExhausted)?;
+
+    // The sender keeps its own handle, so the mailbox gets its own fds
+    let dup = |fd: BorrowedFd| {
+        fcntl_dupfd_cloexec(fd, 0)
+            .map(|fd| fd.into_raw_fd())
+            .map_err(handle_error)
+    };
+    let fds = [dup(fd)?, -1];
+    let fds = match fd2.map(dup).transpose() {
+        Ok(fd2) => [fds[0], fd2.unwrap_or(-1)],
+        Err(e) => {
+            let _ = unsafe { close(fds[0]) };
+            return Err(e);
+        }
+    };
+
+    if let Err(e) = write_entry(
+        mailbox,
+        idx,
+        MailboxEntry {
+            target,
+            rights,
+            fds,
+        },
+    ) {
+        for fd in fds.into_iter().filter(|fd| *fd >= 0) {
+            let _ = unsafe { close(fd) };
+        }
+        return Err(e);
+    }
+    pending[idx] = fds;
+
+    // Fails with `EINVAL` if Yama isn't enabled, in which case it isn't needed
+    let _ = set_ptracer(PTracer::ProcessID(target_pid));
+
+    Ok(())
+}
+
+/// Takes the first handle left for the current process in the handoff mailbox of the process `pidfd`
+fn receive_from_process(pidfd: BorrowedFd) -> Result<Handle> {
+    let getfd = |fd: i32| pidfd_getfd(pidfd, fd, PidfdGetfdFlags::empty()).map_err(handle_error);
+
+    let mailbox = match pidfd_getfd(pidfd, MAILBOX_FD, PidfdGetfdFlags::empty()) {
+        Ok(fd) => fd,
+        // The process has never sent a handle to another process
+        Err(Errno::BADF) => return Err(Error::InvalidState),
+        Err(e) => return Err(handle_error(e)),
+    };
+    let me = getpid().as_raw_nonzero().get();
+
+    for idx in 0..MAILBOX_ENTRIES {
+        let mut ent = read_entry(mailbox.as_fd(), idx)?;
+        if ent.target != me {
+            continue;
+        }
+
+        let fd = getfd(ent.fds[0])?;
+        let fd2 = if ent.fds[1] >= 0 {
+            Some(getfd(ent.fds[1])?)
+        } else {
+            None
+        };
+
+        ent.target = 0;
+        write_entry(mailbox.as_fd(), idx, ent)?;
+
+        return handle_from_fds(fd, fd2, ent.rights);
+    }
+
+    Err(Error::InvalidState)
+}
+
+export_syscall! {
+    unsafe extern fn CloseHandle(hdl: HandlePtr<SysHandle>) -> Result<()> {
+        let hdl = unsafe { Handle::try_deref(hdl.cast())? };
+        hdl.check_rights(HANDLE_RIGHT_CLOSE)?;
+
+        hdl.release();
+
+        Ok(())
+    }
+}
+
+export_syscall! {
+    /// Stores a new handle to the same object as `hdl` in `hdl_out`.
+    ///
+    /// The new handle has only the rights of `hdl` that are also in `rights`. Pass `!0` to keep all of them.
+    unsafe extern fn DuplicateHandle(hdl_out: UserPtrMut<HandlePtr<SysHandle>>, hdl: HandlePtr<SysHandle>, rights: u32) -> Result<()> {
+        // Check that the handle can be stored before duplicating it
+        hdl_out.write(HandlePtr::null())?;
+
+        let hdl = unsafe { Handle::try_deref(hdl.cast())? };
+
+        let mut dup = hdl.try_clone()?;
+        dup.rights &= rights;
+
+        store_handle(dup, hdl_out)
+    }
+}
+
+export_syscall! {
+    /// Returns the full type of `hdl`, including the subtype in the upper bits
+    unsafe extern fn GetHandleType(hdl: HandlePtr<SysHandle>) -> Result<usize> {
+        let hdl = unsafe { Handle::try_deref(hdl.cast())? };
+
+        Ok(hdl.ident())
+    }
+}
+
+export_syscall! {
+    /// Sends `hdl` to another process. The sender keeps its own handle.
+    ///
+    /// If `chan` is a unix domain socket (such as an IPC channel), the handle is received by `ReceiveHandle` on the other end.
+    /// If `chan` is a process handle, the handle is left for that process, which takes it by calling `ReceiveHandle` with a handle to the current process.
+    /// This works without a channel (for example, for a child process), but needs the receiver to be allowed to use `pidfd_getfd` on the sender (see [`send_to_process`]).
+    unsafe extern fn SendHandle(chan: HandlePtr<SysHandle>, hdl: HandlePtr<SysHandle>) -> Result<()> {
+        let chan = unsafe { Handle::try_deref(chan.cast())? };
+
+        let hdl = unsafe { Handle::try_deref(hdl.cast())? };
+        hdl.check_rights(HANDLE_RIGHT_TRANSFER)?;
+        let fd = hdl.borrow_fd().ok_or(Error::UnsupportedOperation)?;
+        let fd2 = hdl.borrow_fd2();
+
+        if chan.ty == HANDLE_TYPE_PROC as usize {
+            return send_to_process(chan.blob2.addr() as i32, hdl.rights, fd, fd2);
+        }
+
+        let chan = chan.borrow_fd().ok_or(Error::UnsupportedOperation)?;
+
+        let msg = HandleMessage {
+            rights: hdl.rights as usize,
+        };
+
+        let fds = [fd, fd2.unwrap_or(fd)];
+        let fds = &fds[..if fd2.is_some() { 2 } else { 1 }];
+
+        let mut buf = Align16([const { MaybeUninit::uninit() }; SPACE_NEEDED]);
+        let mut cmsg = SendAncillaryBuffer::new(&mut buf.0);
+        if !cmsg.push(SendAncillaryMessage::ScmRights(fds)) {
+            return Err(Error::InsufficientMemory);
+        }
+
+        sendmsg(chan, &[IoSlice::new(bytemuck::bytes_of(&msg))], &mut cmsg, SendFlags::NOSIGNAL)
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::Net))?;
+
+        Ok(())
+    }
+}
+
+export_syscall! {
+    /// Receives a handle sent by `SendHandle`, and stores it in `hdl_out`.
+    ///
+    /// If `chan` is a unix domain socket, this blocks until a handle is received on it.
+    /// If `chan` is a process handle, this takes a handle that process left for the current process, and fails with `InvalidState` if there is none.
+    unsafe extern fn ReceiveHandle(hdl_out: UserPtrMut<HandlePtr<SysHandle>>, chan: HandlePtr<SysHandle>) -> Result<()> {
+        hdl_out.write(HandlePtr::null())?;
+
+        let chan = unsafe { Handle::try_deref(chan.cast())? };
+
+        if chan.ty == HANDLE_TYPE_PROC as usize {
+            let pidfd = chan.borrow_fd().ok_or(Error::InvalidHandle)?;
+            return store_handle(receive_from_process(pidfd)?, hdl_out);
+        }
+
+        let chan = chan.borrow_fd().ok_or(Error::UnsupportedOperation)?;
+
+        let mut msg = HandleMessage { rights: 0 };
+
+        let mut buf = Align16([const { MaybeUninit::uninit() }; SPACE_NEEDED]);
+        let mut cmsg = RecvAncillaryBuffer::new(&mut buf.0);
+
+        let res = recvmsg(chan, &mut [IoSliceMut::new(bytemuck::bytes_of_mut(&mut msg))], &mut cmsg, RecvFlags::CMSG_CLOEXEC)
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::Net))?;
+
+        let mut fds = cmsg.drain().find_map(|msg| match msg {
+            RecvAncillaryMessage::ScmRights(fds) => Some(fds),
+            _ => None,
+        }).ok_or(Error::InvalidState)?;
+
+        let fd = fds.next().ok_or(Error::InvalidState)?;
+        let fd2 = fds.next();
+
+        if res.bytes != size_of::<HandleMessage>() || res.flags.contains(ReturnFlags::CTRUNC) {
+            return Err(Error::InvalidState);
+        }
+
+        store_handle(handle_from_fds(fd, fd2, msg.rights as u32)?, hdl_out)
+    }
+}
@@ -2,17 +2,16 @@ use core::cell::SyncUnsafeCell;
 
 use lilium_sys::{
     result::Error,
-    sys::{
-        info::{self as sys, SysInfoRequest, SysInfoRequestSupportedSubsystem},
-        kstr::KSlice,
-    },
+    sys::info::{self as sys, SysInfoRequest, SysInfoRequestSupportedSubsystem},
     uuid::{Uuid, parse_uuid},
 };
 use wl_impl::{
     consts, export_syscall,
     helpers::*,
     libc::{new_utsname, uname},
-    syscall_handler::all_subsystems,
+    syscall_handler::{all_subsystems, find_sysinfo_handler, max_sysno},
+    user_ptr::UserSliceMut,
+    vdso,
 };
 
 use lilium_sys::result::Result;
@@ -68,6 +67,14 @@ static ARCH_TYPE_VERSION: LazyLock<(Uuid, u32)> = LazyLock::new(|| {
     }
 });
 
+/// Publishes the information used by the fast path for `GetSystemInfo`
+pub fn publish_vdso_info() {
+    let (arch_type, arch_version) = *ARCH_TYPE_VERSION;
+    unsafe {
+        vdso::publish_base_info(*VERSION, arch_type, arch_version);
+    }
+}
+
 fn process_request(req: &mut sys::SysInfoRequest) -> Result<()> {
     let head = unsafe { &req.head };
     // Yes, I intend to do `and` here. Not `and_then`.
@@ -118,11 +125,15 @@ fn process_request(req: &mut sys::SysInfoRequest) -> Result<()> {
             ret
         }
         id => {
-            if let Some((subsys, info)) = all_subsystems().find(|(_, subsys)| subsys.uuid == id) {
+            if let Some(handler) = find_sysinfo_handler(id) {
+                (handler.process)(req)
+            } else if let Some((subsys, info)) =
+                all_subsystems().find(|(_, subsys)| subsys.uuid == id)
+            {
                 let req = unsafe {
                     &mut *(req as *mut SysInfoRequest as *mut SysInfoRequestSupportedSubsystem)
                 };
-                req.max_sysno = info.max_sysno;
+                req.max_sysno = max_sysno(subsys as usize);
                 req.subsys_version = info.subsys_version;
                 req.subsystem_no = subsys;
                 Ok(())
@@ -138,9 +149,10 @@ fn process_request(req: &mut sys::SysInfoRequest) -> Result<()> {
 }
 
 export_syscall! {
-    unsafe extern fn GetSystemInfo(reqs: KSlice<sys::SysInfoRequest>) -> Result<()> {
+    unsafe extern fn GetSystemInfo(reqs: UserSliceMut<sys::SysInfoRequest>) -> Result<()> {
+        let mut reqs = reqs;
         let mut res = Ok(());
-        for req in unsafe { iter_mut_checked(reqs) } {
+        for req in reqs.iter_mut()? {
             // This is intentional. Each request is processed independently
             res = res.and(process_request(req?));
         }
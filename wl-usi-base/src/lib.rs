@@ -1,38 +1,48 @@
 #![feature(never_type, sync_unsafe_cell)]
 #![no_std]
-use lilium_sys::{
-    sys::sysno::base::{SYS_GetSystemInfo, SYS_UnmanagedException},
-    uuid::parse_uuid,
-};
-use wl_impl::{
-    erase,
-    helpers::insert_elems,
-    syscall_handler::{SubsysInfo, register_subsys},
-    syscall_helpers::SysCallTyErased,
-    wl_init_subsystem_name,
+use wl_impl::def_subsystem;
+
+/// Syscall numbers generated from the `.knum` files of the subsystem
+#[allow(non_upper_case_globals)]
+mod sysno {
+    include!(concat!(env!("OUT_DIR"), "/sysno.rs"));
+}
+
+use sysno::{
+    SYS_CloseHandle, SYS_DropElevatedPrincipal, SYS_DropKernelPermission, SYS_DuplicateHandle,
+    SYS_ExceptHandleSynchronous, SYS_GetHandleType, SYS_GetSecurityGroups,
+    SYS_GetSecurityPrincipal, SYS_GetSystemInfo, SYS_ReceiveHandle, SYS_SendHandle,
+    SYS_TestFileAccess, SYS_TestKernelPermission, SYS_UnmanagedException,
 };
 
 pub mod except;
+pub mod handle;
 pub mod info;
+pub mod security;
 
-static SYSCALLS: [Option<SysCallTyErased>; 4096] = insert_elems(
-    [None; 4096],
-    [
-        (SYS_UnmanagedException, erase!(except::UnmanagedException)),
-        (SYS_GetSystemInfo, erase!(info::GetSystemInfo)),
-    ],
-);
+extern crate alloc;
 
-static INFO: SubsysInfo = SubsysInfo {
+def_subsystem! {
     name: "base",
-    uuid: parse_uuid("9e780f9e-f35f-580a-ada0-444d0c24e3ea"),
-    subsys_version: 0,
-    max_sysno: 128,
-};
-
-#[unsafe(export_name = wl_init_subsystem_name!())]
-unsafe extern "C" fn init_subsystem() {
-    unsafe {
-        register_subsys(0, &SYSCALLS, &INFO);
-    }
+    uuid: "9e780f9e-f35f-580a-ada0-444d0c24e3ea",
+    number: 0,
+    version: (1, 0, 0),
+    declared: sysno::DECLARED_SYSCALLS,
+    syscalls: [
+        SYS_UnmanagedException => except::UnmanagedException,
+        SYS_ExceptHandleSynchronous => except::ExceptHandleSynchronous,
+        SYS_GetSystemInfo => info::GetSystemInfo,
+        SYS_CloseHandle => handle::CloseHandle,
+        SYS_DuplicateHandle => handle::DuplicateHandle,
+        SYS_GetHandleType => handle::GetHandleType,
+        SYS_SendHandle => handle::SendHandle,
+        SYS_ReceiveHandle => handle::ReceiveHandle,
+        SYS_GetSecurityPrincipal => security::GetSecurityPrincipal,
+        SYS_GetSecurityGroups => security::GetSecurityGroups,
+        SYS_TestFileAccess => security::TestFileAccess,
+        SYS_TestKernelPermission => security::TestKernelPermission,
+        SYS_DropKernelPermission => security::DropKernelPermission,
+        SYS_DropElevatedPrincipal => security::DropElevatedPrincipal,
+    ],
+    init: info::publish_vdso_info,
 }
@@ -0,0 +1,278 @@
+//! Security contexts, backed by Linux credentials.
+//!
+//! The principal of the current thread is derived from its effective uid, and its groups are the effective gid and the supplementary groups.
+//! Kernel permissions are mapped to Linux capabilities, and file permissions are tested with `faccessat(2)`.
+//! The file system and network permissions are instead backed by the Landlock sandbox (see [`wl_impl::sandbox`]), and dropping them stacks a new Landlock ruleset.
+//!
+//! Linux capabilities and Landlock rulesets are per-thread, so dropping a kernel permission only affects the calling thread and threads it creates afterwards.
+//! Linux uids and gids are also per-thread, but Lilium principals are per-process, so the principal can only be changed while the process has a single thread.
+
+use alloc::vec::Vec;
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::{
+        fs::FileHandle,
+        handle::{self, HandlePtr},
+    },
+    uuid::Uuid,
+};
+use rustix::{
+    fs::{Access, AtFlags, Mode, OFlags},
+    process::{getegid, geteuid, getgid, getuid},
+    thread::{CapabilitySet, CapabilitySets, capabilities, set_capabilities},
+};
+use wl_impl::{
+    export_syscall,
+    handle_base::Handle,
+    helpers::{ErrorContext, linux_error_to_lilium_in, rustix_error_to_lilium},
+    libc::{
+        __kernel_gid_t, PR_CAPBSET_DROP, PR_CAPBSET_READ, getgroups, prctl, setresgid, setresuid,
+    },
+    sandbox::{self, SANDBOX_RESTRICT_FS, SANDBOX_RESTRICT_FS_WRITE, SANDBOX_RESTRICT_NETWORK},
+    user_ptr::{UserPtrMut, UserSliceMut, UserStr},
+};
+
+const PRINCIPAL_NAMESPACE: uuid::Uuid = uuid::uuid!("427a152a-3fc4-47cd-935a-aa32a47ff029");
+
+/// Passed to [`TestFileAccess`] to test for read access
+pub const FILE_ACCESS_READ: u32 = 1;
+/// Passed to [`TestFileAccess`] to test for write access
+pub const FILE_ACCESS_WRITE: u32 = 2;
+/// Passed to [`TestFileAccess`] to test for execute (or directory search) access
+pub const FILE_ACCESS_EXECUTE: u32 = 4;
+
+/// The maximum number of supplementary groups on Linux (`NGROUPS_MAX`)
+const NGROUPS_MAX: usize = 65536;
+
+/// Kernel permissions, and the capabilities that grant them
+const KERNEL_PERMISSIONS: &[(&str, CapabilitySet)] = &[
+    ("ChangeFileOwner", CapabilitySet::CHOWN),
+    ("OverrideFileAccess", CapabilitySet::DAC_OVERRIDE),
+    ("SignalAnyProcess", CapabilitySet::KILL),
+    (
+        "ChangePrincipal",
+        CapabilitySet::SETUID.union(CapabilitySet::SETGID),
+    ),
+    ("BindReservedPort", CapabilitySet::NET_BIND_SERVICE),
+    ("RawNetwork", CapabilitySet::NET_RAW),
+    ("ManageNetwork", CapabilitySet::NET_ADMIN),
+    ("LoadKernelModule", CapabilitySet::SYS_MODULE),
+    ("DebugAnyProcess", CapabilitySet::SYS_PTRACE),
+    ("SetSystemTime", CapabilitySet::SYS_TIME),
+    ("Reboot", CapabilitySet::SYS_BOOT),
+    ("RaisePriority", CapabilitySet::SYS_NICE),
+    ("ManageSystem", CapabilitySet::SYS_ADMIN),
+];
+
+/// Kernel permissions that are enforced by the sandbox, and the `SANDBOX_RESTRICT_*` restriction that removes them
+const SANDBOX_PERMISSIONS: &[(&str, u32)] = &[
+    ("AccessNetwork", SANDBOX_RESTRICT_NETWORK),
+    ("WriteFiles", SANDBOX_RESTRICT_FS_WRITE),
+    ("AccessFiles", SANDBOX_RESTRICT_FS),
+];
+
+enum KernelPermission {
+    Capabilities(CapabilitySet),
+    Sandbox(u32),
+}
+
+fn kernel_permission(name: &str) -> Result<KernelPermission> {
+    KERNEL_PERMISSIONS
+        .iter()
+        .find(|(perm, _)| *perm == name)
+        .map(|(_, caps)| KernelPermission::Capabilities(*caps))
+        .or_else(|| {
+            SANDBOX_PERMISSIONS
+                .iter()
+                .find(|(perm, _)| *perm == name)
+                .map(|(_, restriction)| KernelPermission::Sandbox(*restriction))
+        })
+        .ok_or(Error::DoesNotExist)
+}
+
+/// Returns the principal for the linux user or group `id`.
+///
+/// `kind` distinguishes users (`b'u'`) from groups (`b'g'`), which have separate id spaces.
+pub fn principal_for(kind: u8, id: u32) -> Uuid {
+    let mut name = [kind, 0, 0, 0, 0];
+    name[1..].copy_from_slice(&id.to_be_bytes());
+
+    uuid::Uuid::new_v5(&PRINCIPAL_NAMESPACE, &name).into()
+}
+
+export_syscall! {
+    unsafe extern fn GetSecurityPrincipal(principal_out: UserPtrMut<Uuid>) -> Result<()> {
+        principal_out.write(principal_for(b'u', geteuid().as_raw()))
+    }
+}
+
+export_syscall! {
+    unsafe extern fn GetSecurityGroups(groups_out: UserSliceMut<Uuid>) -> Result<usize> {
+        // Returns the total number of groups, and writes up to `groups_out.len` of them. The primary group is always first.
+        let mut groups = [0 as __kernel_gid_t; 64];
+        let mut large_groups = Vec::new();
+
+        let groups = match unsafe { getgroups(groups.len() as i32, groups.as_mut_ptr()) } {
+            Ok(n) => &groups[..n as usize],
+            // More than 64 groups
+            Err(_) => {
+                let n = unsafe { getgroups(0, core::ptr::null_mut()) }
+                    .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::General))?;
+                large_groups.resize((n as usize).min(NGROUPS_MAX), 0);
+                let n = unsafe { getgroups(large_groups.len() as i32, large_groups.as_mut_ptr()) }
+                    .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::General))?;
+                &large_groups[..n as usize]
+            }
+        };
+
+        let egid = getegid().as_raw();
+        let all_groups = core::iter::once(egid)
+            .chain(groups.iter().copied().filter(|&gid| gid != egid));
+
+        let mut groups_out = groups_out;
+        let mut count = 0;
+        let mut outs = groups_out.iter_mut()?;
+
+        for gid in all_groups {
+            if let Some(out) = outs.next() {
+                *out? = principal_for(b'g', gid);
+            }
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn TestFileAccess(resolution_base: HandlePtr<FileHandle>, path: UserStr, access: u32) -> Result<()> {
+        // Returns `Permission` if the current principal does not have every `FILE_ACCESS_*` access in `access` to the file
+        let dirfd = if resolution_base == HandlePtr::null() {
+            rustix::fs::CWD
+        } else {
+            let res_base = unsafe { Handle::try_deref(resolution_base.cast())? };
+            res_base.check_type(handle::HANDLE_SUBTYPE_IO_FILE as usize, 0)?;
+            res_base.borrow_fd().ok_or(Error::UnsupportedOperation)?
+        };
+
+        let mut mode = Access::EXISTS;
+
+        if (access & FILE_ACCESS_READ) != 0 {
+            mode |= Access::READ_OK;
+        }
+        if (access & FILE_ACCESS_WRITE) != 0 {
+            mode |= Access::WRITE_OK;
+        }
+        if (access & FILE_ACCESS_EXECUTE) != 0 {
+            mode |= Access::EXEC_OK;
+        }
+
+        rustix::fs::accessat(dirfd, path.as_str()?, mode, AtFlags::EACCESS)
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::File))
+    }
+}
+
+export_syscall! {
+    unsafe extern fn TestKernelPermission(name: UserStr) -> Result<()> {
+        // Returns `Permission` if the current thread does not hold the kernel permission `name`, and `DoesNotExist` if the permission is unknown
+        let caps = match kernel_permission(name.as_str()?)? {
+            KernelPermission::Capabilities(caps) => caps,
+            KernelPermission::Sandbox(restriction) => {
+                return if (sandbox::restrictions() & restriction) == 0 {
+                    Ok(())
+                } else {
+                    Err(Error::Permission)
+                };
+            }
+        };
+
+        let sets = capabilities(None)
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::General))?;
+
+        if sets.effective.contains(caps) {
+            Ok(())
+        } else {
+            Err(Error::Permission)
+        }
+    }
+}
+
+export_syscall! {
+    unsafe extern fn DropKernelPermission(name: UserStr) -> Result<()> {
+        // Removes the kernel permission `name` from the current thread.
+        // The permission is only removed permanently if the thread holds `CAP_SETPCAP`. Otherwise, it can be regained by executing a privileged program.
+        let caps = match kernel_permission(name.as_str()?)? {
+            KernelPermission::Capabilities(caps) => caps,
+            KernelPermission::Sandbox(restriction) => return sandbox::restrict(restriction),
+        };
+
+        let sets = capabilities(None)
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::General))?;
+
+        // Removing the capabilities from the bounding set stops them from being regained by executing a set-user-id or file-capability program.
+        // This needs `CAP_SETPCAP`, so it must happen before the capabilities are dropped (which may include `CAP_SETPCAP` itself).
+        if sets.effective.contains(CapabilitySet::SETPCAP) {
+            for cap in (0..64).filter(|cap| (caps.bits() & (1 << cap)) != 0) {
+                if matches!(unsafe { prctl(PR_CAPBSET_READ as i32, cap, 0, 0, 0) }, Ok(1)) {
+                    unsafe { prctl(PR_CAPBSET_DROP as i32, cap, 0, 0, 0) }
+                        .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::General))?;
+                }
+            }
+        }
+
+        let sets = CapabilitySets {
+            effective: sets.effective - caps,
+            permitted: sets.permitted - caps,
+            inheritable: sets.inheritable - caps,
+        };
+
+        set_capabilities(None, sets)
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::General))
+    }
+}
+
+export_syscall! {
+    unsafe extern fn DropElevatedPrincipal() -> Result<()> {
+        // Permanently resets the effective and saved principal and primary group to the real ones, discarding any elevation from a set-user-id or set-group-id program.
+        // Fails with `InvalidState` if the process has more than one thread, since other threads would keep the elevated principal.
+        if thread_count()? != 1 {
+            return Err(Error::InvalidState);
+        }
+
+        let uid = getuid().as_raw();
+        let gid = getgid().as_raw();
+
+        // The group must be changed first, as changing the user may remove the permission to do so
+        unsafe { setresgid(gid, gid, gid) }
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::General))?;
+        unsafe { setresuid(uid, uid, uid) }
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::General))
+    }
+}
+
+/// Returns the number of threads in the current process, from `/proc/self/status`
+fn thread_count() -> Result<usize> {
+    let status = rustix::fs::open(
+        "/proc/self/status",
+        OFlags::RDONLY | OFlags::CLOEXEC,
+        Mode::empty(),
+    )
+    .map_err(|e| rustix_error_to_lilium(e, ErrorContext::File))?;
+
+    let mut buf = [0u8; 4096];
+    let mut len = 0;
+    while len < buf.len() {
+        match rustix::io::read(&status, &mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) => return Err(rustix_error_to_lilium(e, ErrorContext::File)),
+        }
+    }
+
+    buf[..len]
+        .split(|&b| b == b'\n')
+        .find_map(|line| line.strip_prefix(b"Threads:"))
+        .and_then(|count| core::str::from_utf8(count).ok()?.trim().parse().ok())
+        .ok_or(Error::InvalidState)
+}
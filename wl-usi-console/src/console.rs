@@ -0,0 +1,227 @@
+//! Console handles, mapped onto host terminals.
+//!
+//! Console modes are mapped onto termios flags, and sizes onto `TIOCGWINSZ` and `TIOCSWINSZ`.
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::{
+        handle::{self, HandlePtr},
+        io::IOHandle,
+    },
+};
+use rustix::{
+    fd::BorrowedFd,
+    termios::{
+        ControlModes, InputModes, LocalModes, OptionalActions, OutputModes, SpecialCodeIndex,
+        Termios, Winsize, tcgetattr, tcgetwinsize, tcsetattr, tcsetwinsize,
+    },
+};
+use wl_impl::{
+    env::environment,
+    export_syscall,
+    handle_base::{HANDLE_RIGHT_READ, HANDLE_RIGHT_WRITE, Handle},
+    helpers::{ErrorContext, rustix_error_to_lilium},
+    user_ptr::{UserPtr, UserPtrMut, UserValue},
+};
+
+// The console modes and `ConsoleSize` are generated from `console.knum`
+pub use crate::knum::console::{
+    CONSOLE_MODE_DEFAULT, CONSOLE_MODE_ECHO, CONSOLE_MODE_FLOW_CONTROL, CONSOLE_MODE_LINE_INPUT,
+    CONSOLE_MODE_PROCESS_OUTPUT, CONSOLE_MODE_RAW, CONSOLE_MODE_SIGNALS,
+    CONSOLE_MODE_TRANSLATE_INPUT, ConsoleSize,
+};
+
+// SAFETY: `ConsoleSize` is `Pod`
+unsafe impl UserValue for ConsoleSize {}
+
+impl ConsoleSize {
+    pub(crate) fn from_winsize(ws: Winsize) -> Self {
+        Self {
+            rows: ws.ws_row,
+            columns: ws.ws_col,
+            width: ws.ws_xpixel,
+            height: ws.ws_ypixel,
+        }
+    }
+
+    pub(crate) fn to_winsize(self) -> Winsize {
+        Winsize {
+            ws_row: self.rows,
+            ws_col: self.columns,
+            ws_xpixel: self.width,
+            ws_ypixel: self.height,
+        }
+    }
+}
+
+fn console_error(e: rustix::io::Errno) -> Error {
+    rustix_error_to_lilium(e, ErrorContext::General)
+}
+
+/// Returns the fd of the console `hdl`.
+///
+/// Changing the console needs the handle to be readable or writable, since standard input and output handles only have one of the rights.
+fn console_fd<'a>(hdl: HandlePtr<IOHandle>, modify: bool) -> Result<BorrowedFd<'a>> {
+    let hdl = unsafe { Handle::try_deref(hdl.cast())? };
+    hdl.check_type(handle::HANDLE_SUBTYPE_IO_TERMINAL as usize, 0)?;
+
+    if modify && (hdl.rights & (HANDLE_RIGHT_READ | HANDLE_RIGHT_WRITE)) == 0 {
+        return Err(Error::Permission);
+    }
+
+    hdl.borrow_fd().ok_or(Error::InvalidHandle)
+}
+
+/// Where each console mode is stored in the termios flags
+enum ModeFlag {
+    Local(LocalModes),
+    Output(OutputModes),
+    Input(InputModes),
+}
+
+const MODE_FLAGS: [(u32, ModeFlag); 6] = [
+    (CONSOLE_MODE_ECHO, ModeFlag::Local(LocalModes::ECHO)),
+    (CONSOLE_MODE_LINE_INPUT, ModeFlag::Local(LocalModes::ICANON)),
+    (CONSOLE_MODE_SIGNALS, ModeFlag::Local(LocalModes::ISIG)),
+    (
+        CONSOLE_MODE_PROCESS_OUTPUT,
+        ModeFlag::Output(OutputModes::OPOST),
+    ),
+    (
+        CONSOLE_MODE_TRANSLATE_INPUT,
+        ModeFlag::Input(InputModes::ICRNL),
+    ),
+    (CONSOLE_MODE_FLOW_CONTROL, ModeFlag::Input(InputModes::IXON)),
+];
+
+/// Flags that follow a console mode, but aren't used to report it, since they don't change how the console behaves in most cases
+const EXTRA_MODE_FLAGS: [(u32, ModeFlag); 2] = [
+    // Extended line editing, such as Ctrl+V and Ctrl+W
+    (CONSOLE_MODE_LINE_INPUT, ModeFlag::Local(LocalModes::IEXTEN)),
+    // A break condition interrupts the foreground process
+    (CONSOLE_MODE_SIGNALS, ModeFlag::Input(InputModes::BRKINT)),
+];
+
+fn termios_mode(termios: &Termios) -> u32 {
+    let mut mode = 0;
+
+    for (flag, tflag) in MODE_FLAGS {
+        let set = match tflag {
+            ModeFlag::Local(f) => termios.local_modes.contains(f),
+            ModeFlag::Output(f) => termios.output_modes.contains(f),
+            ModeFlag::Input(f) => termios.input_modes.contains(f),
+        };
+        if set {
+            mode |= flag;
+        }
+    }
+
+    mode
+}
+
+/// Sets the flags of `termios` for `mode`.
+///
+/// The result only depends on `mode`, and not on the previous mode, so switching to `CONSOLE_MODE_RAW` and back restores the console.
+/// `CONSOLE_MODE_RAW` is the same as `cfmakeraw(3)`.
+fn set_termios_mode(termios: &mut Termios, mode: u32) {
+    // Flags that are cleared by `cfmakeraw`, but not covered by a console mode. These are also clear on a terminal in its usual state.
+    termios.input_modes.remove(
+        InputModes::IGNBRK
+            | InputModes::PARMRK
+            | InputModes::ISTRIP
+            | InputModes::INLCR
+            | InputModes::IGNCR,
+    );
+    termios.local_modes.remove(LocalModes::ECHONL);
+    // 8-bit characters without parity, so every byte is passed through unchanged
+    termios
+        .control_modes
+        .remove(ControlModes::CSIZE | ControlModes::PARENB);
+    termios.control_modes.insert(ControlModes::CS8);
+
+    for (flag, tflag) in MODE_FLAGS.into_iter().chain(EXTRA_MODE_FLAGS) {
+        let set = (mode & flag) != 0;
+        match tflag {
+            ModeFlag::Local(f) => termios.local_modes.set(f, set),
+            ModeFlag::Output(f) => termios.output_modes.set(f, set),
+            ModeFlag::Input(f) => termios.input_modes.set(f, set),
+        }
+    }
+
+    if (mode & CONSOLE_MODE_LINE_INPUT) == 0 {
+        // Reads return as soon as a byte is available
+        termios.special_codes[SpecialCodeIndex::VMIN] = 1;
+        termios.special_codes[SpecialCodeIndex::VTIME] = 0;
+    }
+}
+
+/// Determines the number of colours the console supports from the environment of the program, since the host terminal can't be queried without writing to it
+fn console_colors() -> usize {
+    let env = environment().read();
+
+    if env.var("NO_COLOR").is_some_and(|val| !val.is_empty()) {
+        return 0;
+    }
+
+    if matches!(env.var("COLORTERM"), Some("truecolor" | "24bit")) {
+        return 0x1000000;
+    }
+
+    match env.var("TERM") {
+        None | Some("" | "dumb") => 0,
+        Some(term) if term.ends_with("-256color") => 256,
+        Some(term) if term.ends_with("-16color") => 16,
+        Some(_) => 8,
+    }
+}
+
+export_syscall! {
+    unsafe extern fn GetConsoleMode(hdl: HandlePtr<IOHandle>, mode_out: UserPtrMut<u32>) -> Result<()> {
+        let fd = console_fd(hdl, false)?;
+        let termios = tcgetattr(fd).map_err(console_error)?;
+
+        mode_out.write(termios_mode(&termios))
+    }
+}
+
+export_syscall! {
+    unsafe extern fn SetConsoleMode(hdl: HandlePtr<IOHandle>, mode: u32) -> Result<()> {
+        if (mode & !CONSOLE_MODE_DEFAULT) != 0 {
+            return Err(Error::InvalidOption);
+        }
+
+        let fd = console_fd(hdl, true)?;
+        let mut termios = tcgetattr(fd).map_err(console_error)?;
+
+        set_termios_mode(&mut termios, mode);
+
+        tcsetattr(fd, OptionalActions::Drain, &termios).map_err(console_error)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn GetConsoleSize(hdl: HandlePtr<IOHandle>, size_out: UserPtrMut<ConsoleSize>) -> Result<()> {
+        let fd = console_fd(hdl, false)?;
+        let ws = tcgetwinsize(fd).map_err(console_error)?;
+
+        size_out.write(ConsoleSize::from_winsize(ws))
+    }
+}
+
+export_syscall! {
+    unsafe extern fn SetConsoleSize(hdl: HandlePtr<IOHandle>, size: UserPtr<ConsoleSize>) -> Result<()> {
+        // The foreground process of the console is notified with `SIGWINCH` by the host
+        let size = size.read()?;
+        let fd = console_fd(hdl, true)?;
+
+        tcsetwinsize(fd, size.to_winsize()).map_err(console_error)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn GetConsoleColors(hdl: HandlePtr<IOHandle>) -> Result<usize> {
+        console_fd(hdl, false)?;
+
+        Ok(console_colors())
+    }
+}
@@ -0,0 +1,39 @@
+#![no_std]
+#![feature(never_type)]
+use wl_impl::def_subsystem;
+
+/// Syscall numbers generated from the `.knum` files of the subsystem
+#[allow(non_upper_case_globals)]
+mod sysno {
+    include!(concat!(env!("OUT_DIR"), "/sysno.rs"));
+}
+
+/// Constants and structs generated from the `.knum` files of the subsystem, with a module for each file
+#[allow(dead_code)]
+mod knum {
+    include!(concat!(env!("OUT_DIR"), "/knum.rs"));
+}
+
+use sysno::{
+    SYS_CreatePseudoConsole, SYS_GetConsoleColors, SYS_GetConsoleMode, SYS_GetConsoleSize,
+    SYS_SetConsoleMode, SYS_SetConsoleSize,
+};
+
+pub mod console;
+pub mod pty;
+
+def_subsystem! {
+    name: "console",
+    uuid: "18ec03be-0d3d-52d9-ab1a-11f9dbed7374",
+    number: dynamic,
+    version: (0, 1, 0),
+    declared: sysno::DECLARED_SYSCALLS,
+    syscalls: [
+        SYS_GetConsoleMode => console::GetConsoleMode,
+        SYS_SetConsoleMode => console::SetConsoleMode,
+        SYS_GetConsoleSize => console::GetConsoleSize,
+        SYS_SetConsoleSize => console::SetConsoleSize,
+        SYS_GetConsoleColors => console::GetConsoleColors,
+        SYS_CreatePseudoConsole => pty::CreatePseudoConsole,
+    ],
+}
@@ -0,0 +1,85 @@
+use lilium_sys::{
+    result::{Error, Result},
+    sys::{
+        handle::{self, HandlePtr},
+        io::IOHandle,
+    },
+};
+use rustix::{
+    fd::{IntoRawFd, OwnedFd},
+    pty::{OpenptFlags, grantpt, ioctl_tiocgptpeer, openpt, unlockpt},
+    termios::tcsetwinsize,
+};
+use wl_impl::{
+    export_syscall,
+    handle_base::{
+        HANDLE_RIGHT_CLOSE, HANDLE_RIGHT_READ, HANDLE_RIGHT_TRANSFER, HANDLE_RIGHT_WRITE, Handle,
+        insert_handle,
+    },
+    helpers::{ErrorContext, rustix_error_to_lilium},
+    ministd::AsRawFd,
+    user_ptr::{UserPtr, UserPtrMut},
+};
+
+use crate::console::ConsoleSize;
+
+/// The rights of both ends of a pseudo-console
+const PTY_RIGHTS: u32 =
+    HANDLE_RIGHT_READ | HANDLE_RIGHT_WRITE | HANDLE_RIGHT_TRANSFER | HANDLE_RIGHT_CLOSE;
+
+fn pty_error(e: rustix::io::Errno) -> Error {
+    rustix_error_to_lilium(e, ErrorContext::General)
+}
+
+/// Inserts a console handle for `fd`, which takes ownership of the fd.
+///
+/// The controller is also a console handle, since the host applies termios and size changes made through it to the console.
+fn new_console_handle(fd: OwnedFd) -> Result<HandlePtr<Handle>> {
+    let hdl = Handle {
+        ty: handle::HANDLE_SUBTYPE_IO_TERMINAL as usize,
+        blob1: core::ptr::null_mut(),
+        blob2: core::ptr::null_mut(),
+        fd: fd.as_raw_fd() as i64,
+        rights: PTY_RIGHTS,
+    };
+
+    let ptr = insert_handle(hdl)?;
+    let _ = fd.into_raw_fd();
+    Ok(ptr)
+}
+
+export_syscall! {
+    unsafe extern fn CreatePseudoConsole(controller_out: UserPtrMut<HandlePtr<IOHandle>>, console_out: UserPtrMut<HandlePtr<IOHandle>>, size: UserPtr<ConsoleSize>, flags: u32) -> Result<()> {
+        if flags != 0 {
+            return Err(Error::InvalidOption);
+        }
+
+        let size = size.read_opt()?;
+
+        let controller = openpt(OpenptFlags::RDWR | OpenptFlags::NOCTTY | OpenptFlags::CLOEXEC)
+            .map_err(pty_error)?;
+        grantpt(&controller).map_err(pty_error)?;
+        unlockpt(&controller).map_err(pty_error)?;
+
+        // Opens the console through the controller, rather than by the path from `ptsname`, so it can't be raced by another pseudo-console reusing the path
+        let console = ioctl_tiocgptpeer(&controller, OpenptFlags::RDWR | OpenptFlags::NOCTTY | OpenptFlags::CLOEXEC)
+            .map_err(pty_error)?;
+
+        if let Some(size) = size {
+            tcsetwinsize(&controller, size.to_winsize()).map_err(pty_error)?;
+        }
+
+        let controller = new_console_handle(controller)?;
+        let console = new_console_handle(console).inspect_err(|_| {
+            unsafe { Handle::deref_unchecked(controller) }.close(false);
+        })?;
+
+        controller_out
+            .write(controller.cast())
+            .and_then(|()| console_out.write(console.cast()))
+            .inspect_err(|_| {
+                unsafe { Handle::deref_unchecked(controller) }.close(false);
+                unsafe { Handle::deref_unchecked(console) }.close(false);
+            })
+    }
+}
@@ -9,19 +9,21 @@ use lilium_sys::{
 };
 use wl_impl::{
     eprintln, export_syscall,
-    handle_base::Handle,
+    handle_base::{HANDLE_RIGHT_READ, HANDLE_RIGHT_WRITE, Handle},
     helpers::linux_error_to_lilium,
     libc::{read, write},
     ministd::AsRawFd as _,
+    user_ptr::{UserPtr, UserPtrMut},
 };
 
 export_syscall! {
-    unsafe extern fn IOWrite(hdl: HandlePtr<sys::IOHandle>, base: *const c_void, len: usize) -> Result<usize> {
+    unsafe extern fn IOWrite(hdl: HandlePtr<sys::IOHandle>, base: UserPtr<c_void>, len: usize) -> Result<usize> {
         let hdl = unsafe { Handle::try_deref(hdl.cast())? };
         hdl.check_type(HANDLE_TYPE_IO as usize, 0xF0000000)?;
-        // TODO: check capabilities
+        hdl.check_rights(HANDLE_RIGHT_WRITE)?;
         let fd = hdl.borrow_fd().expect("Expected an IOHandle to have an attached handle");
-        let v = unsafe { write(fd.as_raw_fd(), base, len) }
+        // The buffer is checked by the host, which fails with `EFAULT` (`InvalidMemory`)
+        let v = unsafe { write(fd.as_raw_fd(), base.as_raw(), len) }
             .map_err(linux_error_to_lilium)?;
 
         Ok(v)
@@ -29,12 +31,13 @@ export_syscall! {
 }
 
 export_syscall! {
-    unsafe extern fn IORead(hdl: HandlePtr<sys::IOHandle>, base: *mut c_void, len: usize) -> Result<usize> {
+    unsafe extern fn IORead(hdl: HandlePtr<sys::IOHandle>, base: UserPtrMut<c_void>, len: usize) -> Result<usize> {
         let hdl = unsafe { Handle::try_deref(hdl.cast())? };
         hdl.check_type(HANDLE_TYPE_IO as usize, 0xF0000000)?;
-        // TODO: check capabilities
+        hdl.check_rights(HANDLE_RIGHT_READ)?;
         let fd = hdl.borrow_fd().expect("Expected an IOHandle to have an attached handle");
-        let v = unsafe { read(fd.as_raw_fd(), base, len) }
+        // The buffer is checked by the host, which fails with `EFAULT` (`InvalidMemory`)
+        let v = unsafe { read(fd.as_raw_fd(), base.as_raw(), len) }
             .map_err(linux_error_to_lilium)?;
 
         Ok(v)
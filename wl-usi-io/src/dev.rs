@@ -1,6 +1,6 @@
 use wl_impl::{
     export_syscall, handle_base::Handle, helpers::linux_error_to_lilium, libc::write,
-    ministd::AsRawFd as _,
+    ministd::AsRawFd as _, user_ptr::UserPtrMut,
 };
 
 use lilium_sys::{
@@ -13,7 +13,7 @@ use lilium_sys::{
 };
 
 export_syscall! {
-    unsafe extern fn OpenDevice(hdl: *mut HandlePtr<DeviceHandle>, id: Uuid) -> Result<()> {
+    unsafe extern fn OpenDevice(hdl: UserPtrMut<HandlePtr<DeviceHandle>>, id: Uuid) -> Result<()> {
 
         Err(Error::UnknownDevice)
     }
@@ -1,16 +1,22 @@
 use alloc::ffi::CString;
 use lilium_sys::result::{Error, Result};
 use lilium_sys::sys::handle::{self, HandlePtr};
-use lilium_sys::sys::kstr::KStrCPtr;
 use lilium_sys::sys::{fs as sys, io};
 use rustix::fd::{BorrowedFd, IntoRawFd};
 use rustix::fs::{Mode, OFlags};
-use wl_impl::handle_base::{self, Handle};
-use wl_impl::helpers::linux_error_to_lilium;
+use wl_impl::handle_base::{
+    self, HANDLE_RIGHT_CLOSE, HANDLE_RIGHT_EXECUTE, HANDLE_RIGHT_MAP, HANDLE_RIGHT_READ,
+    HANDLE_RIGHT_SEEK, HANDLE_RIGHT_TRANSFER, HANDLE_RIGHT_WRITE, Handle,
+};
+use wl_impl::helpers::{ErrorContext, rustix_error_to_lilium};
+use wl_impl::user_ptr::{UserPtr, UserPtrMut, UserStr};
 use wl_impl::{eprintln, export_syscall, libc};
 
 export_syscall! {
-    unsafe extern fn OpenFile(ohdl: *mut HandlePtr<sys::FileHandle>, resolution_base: HandlePtr<sys::FileHandle>, path: KStrCPtr, opts: *const sys::FileOpenOptions) -> Result<()> {
+    unsafe extern fn OpenFile(ohdl: UserPtrMut<HandlePtr<sys::FileHandle>>, resolution_base: HandlePtr<sys::FileHandle>, path: UserStr, opts: UserPtr<sys::FileOpenOptions>) -> Result<()> {
+        // Check that the handle can be stored before opening the file
+        ohdl.write(HandlePtr::null())?;
+
         let dirfd = if resolution_base == HandlePtr::null() {
             rustix::fs::CWD
         } else {
@@ -19,10 +25,12 @@ export_syscall! {
             res_base.borrow_fd().ok_or(Error::UnsupportedOperation)?
         };
 
-        let opts = unsafe{&*opts};
+        let opts = opts.read()?;
 
         let mut oflags = OFlags::CLOEXEC;
 
+        let mut rights = HANDLE_RIGHT_TRANSFER | HANDLE_RIGHT_CLOSE;
+
         if opts.op_mode == sys::OP_NO_ACCESS || opts.op_mode == sys::OP_ACL_ACCESS {
             oflags |= OFlags::PATH;
         }
@@ -34,6 +42,16 @@ export_syscall! {
             oflags |= OFlags::WRONLY
         }
 
+        if !oflags.contains(OFlags::PATH) {
+            // Linux requires read access to map or execute a file through an fd
+            if (opts.access_mode & sys::ACCESS_READ) != 0 {
+                rights |= HANDLE_RIGHT_READ | HANDLE_RIGHT_MAP | HANDLE_RIGHT_EXECUTE | HANDLE_RIGHT_SEEK;
+            }
+            if (opts.access_mode & sys::ACCESS_WRITE) != 0 {
+                rights |= HANDLE_RIGHT_WRITE | HANDLE_RIGHT_SEEK;
+            }
+        }
+
         if ( opts.access_mode & sys::ACCESS_CREATE) != 0 {
             oflags |= OFlags::CREATE
         }
@@ -58,7 +76,7 @@ export_syscall! {
             oflags |= OFlags::DIRECTORY;
         }
 
-        let path = CString::new(unsafe { path.as_str() })
+        let path = CString::new(path.as_str()?)
             .or_else(|_| Error::from_code(-0x801).map(|_| unreachable!()))?;
 
         eprintln!("openat({dirfd:?}, {path:?}, {oflags:?})");
@@ -66,7 +84,7 @@ export_syscall! {
         let mode = Mode::from_raw_mode(0o666);
 
         let fd = rustix::fs::openat(dirfd, &*path, oflags, mode)
-            .map_err(|v| linux_error_to_lilium(unsafe { linux_errno::Error::new_unchecked(v.raw_os_error() as u16)}))?;
+            .map_err(|v| rustix_error_to_lilium(v, ErrorContext::File))?;
 
         let fd = fd.into_raw_fd() as i64;
 
@@ -75,12 +93,13 @@ export_syscall! {
             ty: handle::HANDLE_SUBTYPE_IO_FILE as usize,
             blob1: core::ptr::null_mut(),
             blob2: core::ptr::null_mut(),
-            fd
+            fd,
+            rights,
         };
 
         let ptr = handle_base::insert_handle(hdl)?;
 
-        unsafe { ohdl.write(ptr.cast()); }
+        ohdl.write(ptr.cast())?;
 
         Ok(())
     }
@@ -1,33 +1,31 @@
 #![no_std]
 #![feature(box_vec_non_null)]
-use lilium_sys::{sys::io::IOWrite, uuid::parse_uuid};
-use wl_impl::{
-    erase,
-    helpers::insert_elems,
-    syscall_handler::{SubsysInfo, register_subsys},
-    syscall_helpers::SysCallTyErased,
-    wl_init_subsystem_name,
-};
+use wl_impl::def_subsystem;
+
+/// Syscall numbers generated from the `.knum` files of the subsystem
+#[allow(non_upper_case_globals)]
+mod sysno {
+    include!(concat!(env!("OUT_DIR"), "/sysno.rs"));
+}
+
+use sysno::{SYS_IORead, SYS_IOWrite, SYS_OpenDevice, SYS_OpenFile};
 
 extern crate alloc;
 
 mod hdl_impl;
 
-static SYSCALLS: [Option<SysCallTyErased>; 4096] =
-    insert_elems([None; 4096], [(1, erase!(IOWrite))]);
-
-static INFO: SubsysInfo = SubsysInfo {
+def_subsystem! {
     name: "io",
-    uuid: parse_uuid("144e7137-9e85-5b9e-8d4a-8c700fb9d6bd"),
-    subsys_version: 0,
-    max_sysno: 128,
-};
-
-#[unsafe(export_name = wl_init_subsystem_name!())]
-unsafe extern "C" fn init_subsystem() {
-    unsafe {
-        register_subsys(2, &SYSCALLS, &INFO);
-    }
+    uuid: "144e7137-9e85-5b9e-8d4a-8c700fb9d6bd",
+    number: 2,
+    version: (1, 0, 0),
+    declared: sysno::DECLARED_SYSCALLS,
+    syscalls: [
+        SYS_IOWrite => basic::IOWrite,
+        SYS_IORead => basic::IORead,
+        SYS_OpenFile => fs::OpenFile,
+        SYS_OpenDevice => dev::OpenDevice,
+    ],
 }
 
 mod basic;
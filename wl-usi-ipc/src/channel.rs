@@ -0,0 +1,297 @@
+//! IPC channels, and named channels that unrelated processes can connect to.
+//!
+//! Channels are connected Unix domain `SOCK_SEQPACKET` sockets, so they preserve message boundaries, and can carry handles (with `SendHandle`).
+//! Named channels are listening sockets bound in a directory private to the user, which is (in order of preference):
+//! * `WL_CHANNEL_DIR`
+//! * `$XDG_RUNTIME_DIR/winter-lily/channels`
+//! * `/tmp/winter-lily-<euid>/channels`
+
+use alloc::{format, string::String, vec::Vec};
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::{
+        handle::{self, HandlePtr},
+        io::IOHandle,
+    },
+};
+use rustix::{
+    fd::{BorrowedFd, OwnedFd},
+    fs::{CWD, FileType, Mode, RenameFlags, lstat, renameat_with},
+    io::Errno,
+    net::{
+        AddressFamily, SocketAddrUnix, SocketFlags, SocketType, accept_with, bind, connect, listen,
+        socket_with, socketpair,
+    },
+    process::geteuid,
+};
+use wl_impl::{
+    env::host_var,
+    export_syscall,
+    handle_base::{
+        HANDLE_RIGHT_CLOSE, HANDLE_RIGHT_READ, HANDLE_RIGHT_TRANSFER, HANDLE_RIGHT_WRITE, Handle,
+    },
+    helpers::{ErrorContext, rustix_error_to_lilium},
+    rand::fast_rand,
+    sandbox::{LANDLOCK_ACCESS_FS_MAKE_SOCK, LANDLOCK_ACCESS_FS_REMOVE_FILE, RuntimeAccess},
+    user_ptr::{UserPtrMut, UserStr},
+};
+
+use crate::{nonblocking, store_handle, store_pair};
+
+/// The rights of a connected channel
+const CHANNEL_RIGHTS: u32 =
+    HANDLE_RIGHT_READ | HANDLE_RIGHT_WRITE | HANDLE_RIGHT_TRANSFER | HANDLE_RIGHT_CLOSE;
+/// The rights of a named channel that accepts connections
+const LISTENER_RIGHTS: u32 = HANDLE_RIGHT_READ | HANDLE_RIGHT_TRANSFER | HANDLE_RIGHT_CLOSE;
+
+/// The maximum number of pending connections to a named channel
+const LISTEN_BACKLOG: i32 = 128;
+
+/// The length of `sun_path`, including the nul terminator
+const MAX_SOCKET_PATH: usize = 108;
+
+fn ipc_error(e: Errno) -> Error {
+    rustix_error_to_lilium(e, ErrorContext::Net)
+}
+
+fn file_error(e: Errno) -> Error {
+    rustix_error_to_lilium(e, ErrorContext::File)
+}
+
+fn socket_flags(flags: u32) -> Result<SocketFlags> {
+    if nonblocking(flags)? {
+        Ok(SocketFlags::CLOEXEC | SocketFlags::NONBLOCK)
+    } else {
+        Ok(SocketFlags::CLOEXEC)
+    }
+}
+
+fn channel_socket(flags: SocketFlags) -> Result<OwnedFd> {
+    socket_with(AddressFamily::UNIX, SocketType::SEQPACKET, flags, None).map_err(ipc_error)
+}
+
+/// Creates `path` if it doesn't exist, and checks that it is a directory (not a symlink to one) that only the current user can modify
+fn ensure_private_dir(path: &str) -> Result<()> {
+    match rustix::fs::mkdir(path, Mode::RWXU) {
+        Ok(()) | Err(Errno::EXIST) => {}
+        Err(e) => return Err(file_error(e)),
+    }
+
+    // In a shared directory like `/tmp`, the directory might have been created by another user, or be a symlink to somewhere else
+    let stat = lstat(path).map_err(file_error)?;
+
+    if FileType::from_raw_mode(stat.st_mode) != FileType::Directory
+        || stat.st_uid != geteuid().as_raw()
+        || (stat.st_mode & 0o022) != 0
+    {
+        return Err(Error::Permission);
+    }
+
+    Ok(())
+}
+
+/// Returns the directory named channels are created in, creating it if necessary
+fn channel_dir() -> Result<String> {
+    let dir = if let Some(dir) = host_var("WL_CHANNEL_DIR") {
+        String::from(dir.trim_end_matches('/'))
+    } else {
+        let base = match host_var("XDG_RUNTIME_DIR") {
+            Some(dir) => format!("{}/winter-lily", dir.trim_end_matches('/')),
+            None => format!("/tmp/winter-lily-{}", geteuid().as_raw()),
+        };
+        ensure_private_dir(&base)?;
+        base + "/channels"
+    };
+
+    ensure_private_dir(&dir)?;
+
+    Ok(dir)
+}
+
+/// Keeps named channels working after the program drops `WriteFiles` or `AccessFiles`
+pub(crate) static CHANNEL_DIR_ACCESS: RuntimeAccess = RuntimeAccess {
+    paths: || channel_dir().into_iter().collect::<Vec<_>>(),
+    access: LANDLOCK_ACCESS_FS_MAKE_SOCK | LANDLOCK_ACCESS_FS_REMOVE_FILE,
+};
+
+/// Returns the path of the socket of the named channel `name`
+fn channel_path(name: UserStr) -> Result<String> {
+    let name = name.as_str()?;
+
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+        return Err(Error::InvalidString);
+    }
+
+    let path = format!("{}/{name}", channel_dir()?);
+
+    if path.len() >= MAX_SOCKET_PATH {
+        return Err(Error::InvalidString);
+    }
+
+    Ok(path)
+}
+
+fn channel_addr(path: &str) -> Result<SocketAddrUnix> {
+    SocketAddrUnix::new(path).map_err(ipc_error)
+}
+
+/// Binds `fd` to a new temporary name in the same directory as `path`, and returns that name
+fn bind_temporary(fd: &OwnedFd, path: &str) -> Result<String> {
+    let dir = path.rsplit_once('/').map_or(".", |(dir, _)| dir);
+
+    loop {
+        let tmp = format!("{dir}/.{:08x}", fast_rand() as u32);
+        match bind(fd, &channel_addr(&tmp)?) {
+            Ok(()) => break Ok(tmp),
+            Err(Errno::ADDRINUSE) => continue,
+            Err(e) => break Err(ipc_error(e)),
+        }
+    }
+}
+
+/// Moves the listening socket bound to `tmp` to `path`.
+///
+/// A socket at `path` that was left behind by a channel that was closed without being removed is replaced,
+/// but only if nothing accepts connections on it anymore. `path` names a bound socket the whole time.
+fn publish_channel(tmp: &str, path: &str) -> Result<()> {
+    let res = try_publish_channel(tmp, path);
+
+    // After a successful rename, `tmp` may already be used by another process
+    if !matches!(res, Ok(false)) {
+        let _ = rustix::fs::unlink(tmp);
+    }
+
+    res.map(|_| ())
+}
+
+/// Returns whether the socket at `tmp` afterwards is a stale socket (or still the socket being published), which needs to be removed
+fn try_publish_channel(tmp: &str, path: &str) -> Result<bool> {
+    loop {
+        match renameat_with(CWD, tmp, CWD, path, RenameFlags::NOREPLACE) {
+            Ok(()) => return Ok(false),
+            Err(Errno::EXIST) => {}
+            Err(e) => return Err(file_error(e)),
+        }
+
+        let probed = match lstat(path) {
+            Ok(stat) => stat,
+            Err(Errno::NOENT) => continue,
+            Err(e) => return Err(file_error(e)),
+        };
+
+        match is_stale_channel(path) {
+            Ok(true) => {}
+            Ok(false) => return Err(Error::AlreadyExists),
+            Err(Errno::NOENT) => continue,
+            Err(e) => return Err(ipc_error(e)),
+        }
+
+        match renameat_with(CWD, tmp, CWD, path, RenameFlags::EXCHANGE) {
+            Ok(()) => {}
+            Err(Errno::NOENT) => continue,
+            Err(e) => return Err(file_error(e)),
+        }
+
+        // Another process may have replaced the same stale socket between the probe and the swap.
+        // Its channel is listening, so it is swapped back, without connecting to it.
+        let displaced = lstat(tmp).map_err(file_error)?;
+        if (displaced.st_dev, displaced.st_ino) != (probed.st_dev, probed.st_ino) {
+            let _ = renameat_with(CWD, tmp, CWD, path, RenameFlags::EXCHANGE);
+            return Err(Error::AlreadyExists);
+        }
+
+        return Ok(true);
+    }
+}
+
+/// Checks if nothing accepts connections on the socket at `path` anymore.
+///
+/// The connection is nonblocking, so a full backlog of a channel that is still listening doesn't block the probe.
+fn is_stale_channel(path: &str) -> rustix::io::Result<bool> {
+    let probe = socket_with(
+        AddressFamily::UNIX,
+        SocketType::SEQPACKET,
+        SocketFlags::CLOEXEC | SocketFlags::NONBLOCK,
+        None,
+    )?;
+
+    match connect(&probe, &SocketAddrUnix::new(path)?) {
+        Err(Errno::CONNREFUSED) => Ok(true),
+        Ok(()) | Err(Errno::AGAIN | Errno::INPROGRESS) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the fd of the named channel `listener`, after checking that it accepts connections
+fn listener_fd<'a>(listener: HandlePtr<IOHandle>) -> Result<BorrowedFd<'a>> {
+    let hdl = unsafe { Handle::try_deref(listener.cast())? };
+    hdl.check_type(handle::HANDLE_SUBTYPE_IO_SOCKET as usize, 0)?;
+    hdl.check_rights(HANDLE_RIGHT_READ)?;
+    hdl.borrow_fd().ok_or(Error::InvalidHandle)
+}
+
+export_syscall! {
+    unsafe extern fn CreateChannel(first_out: UserPtrMut<HandlePtr<IOHandle>>, second_out: UserPtrMut<HandlePtr<IOHandle>>, flags: u32) -> Result<()> {
+        let ends = socketpair(AddressFamily::UNIX, SocketType::SEQPACKET, socket_flags(flags)?, None)
+            .map_err(ipc_error)?;
+
+        store_pair(ends, handle::HANDLE_SUBTYPE_IO_SOCKET as usize, (CHANNEL_RIGHTS, CHANNEL_RIGHTS), first_out, second_out)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn CreateNamedChannel(hdl_out: UserPtrMut<HandlePtr<IOHandle>>, name: UserStr, flags: u32) -> Result<()> {
+        let sflags = socket_flags(flags)?;
+        let path = channel_path(name)?;
+
+        let fd = channel_socket(sflags)?;
+
+        // The socket is bound under a temporary name, and only renamed to `path` once it's listening.
+        // So another process never sees it as a stale socket, and a stale socket is never unlinked while another process is replacing it.
+        let tmp = bind_temporary(&fd, &path)?;
+        if let Err(e) = listen(&fd, LISTEN_BACKLOG) {
+            let _ = rustix::fs::unlink(&*tmp);
+            return Err(ipc_error(e));
+        }
+        publish_channel(&tmp, &path)?;
+
+        store_handle(fd, handle::HANDLE_SUBTYPE_IO_SOCKET as usize, LISTENER_RIGHTS, hdl_out)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn AcceptChannel(hdl_out: UserPtrMut<HandlePtr<IOHandle>>, listener: HandlePtr<IOHandle>, flags: u32) -> Result<()> {
+        let sflags = socket_flags(flags)?;
+        let listener = listener_fd(listener)?;
+
+        let fd = accept_with(listener, sflags).map_err(ipc_error)?;
+
+        store_handle(fd, handle::HANDLE_SUBTYPE_IO_SOCKET as usize, CHANNEL_RIGHTS, hdl_out)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn ConnectNamedChannel(hdl_out: UserPtrMut<HandlePtr<IOHandle>>, name: UserStr, flags: u32) -> Result<()> {
+        let sflags = socket_flags(flags)?;
+        let addr = channel_addr(&channel_path(name)?)?;
+
+        // Connect while blocking, so that a nonblocking channel is fully connected when it's returned
+        let fd = channel_socket(SocketFlags::CLOEXEC)?;
+        connect(&fd, &addr).map_err(ipc_error)?;
+
+        if sflags.contains(SocketFlags::NONBLOCK) {
+            rustix::io::ioctl_fionbio(&fd, true).map_err(ipc_error)?;
+        }
+
+        store_handle(fd, handle::HANDLE_SUBTYPE_IO_SOCKET as usize, CHANNEL_RIGHTS, hdl_out)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn RemoveNamedChannel(name: UserStr) -> Result<()> {
+        let path = channel_path(name)?;
+
+        rustix::fs::unlink(&*path).map_err(file_error)
+    }
+}
@@ -0,0 +1,114 @@
+#![no_std]
+#![feature(never_type)]
+
+extern crate alloc;
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::{handle::HandlePtr, io::IOHandle},
+};
+use rustix::fd::{IntoRawFd, OwnedFd};
+use wl_impl::{
+    def_subsystem,
+    handle_base::{Handle, insert_handle},
+    ministd::AsRawFd,
+    user_ptr::UserPtrMut,
+};
+
+/// Syscall numbers generated from the `.knum` files of the subsystem
+#[allow(non_upper_case_globals)]
+mod sysno {
+    include!(concat!(env!("OUT_DIR"), "/sysno.rs"));
+}
+
+use sysno::{
+    SYS_AcceptChannel, SYS_ConnectNamedChannel, SYS_CreateChannel, SYS_CreateNamedChannel,
+    SYS_CreatePipe, SYS_RemoveNamedChannel,
+};
+
+pub mod channel;
+pub mod pipe;
+
+/// Operations on the handle fail with `WouldBlock` instead of blocking
+pub const IPC_FLAG_NONBLOCK: u32 = 1;
+
+/// Checks that `flags` only contains `IPC_FLAG_*` flags, and returns whether the handle is nonblocking
+fn nonblocking(flags: u32) -> Result<bool> {
+    if (flags & !IPC_FLAG_NONBLOCK) != 0 {
+        return Err(Error::InvalidOption);
+    }
+
+    Ok((flags & IPC_FLAG_NONBLOCK) != 0)
+}
+
+/// Inserts a handle for `fd`, which takes ownership of the fd
+fn new_handle(fd: OwnedFd, ty: usize, rights: u32) -> Result<HandlePtr<Handle>> {
+    let hdl = Handle {
+        ty,
+        blob1: core::ptr::null_mut(),
+        blob2: core::ptr::null_mut(),
+        fd: fd.as_raw_fd() as i64,
+        rights,
+    };
+
+    let ptr = insert_handle(hdl)?;
+    let _ = fd.into_raw_fd();
+    Ok(ptr)
+}
+
+/// Inserts a handle for `fd` and stores it in `hdl_out`, closing the fd if it can't be stored
+fn store_handle(
+    fd: OwnedFd,
+    ty: usize,
+    rights: u32,
+    hdl_out: UserPtrMut<HandlePtr<IOHandle>>,
+) -> Result<()> {
+    let ptr = new_handle(fd, ty, rights)?;
+
+    hdl_out.write(ptr.cast()).inspect_err(|_| {
+        unsafe { Handle::deref_unchecked(ptr) }.close(false);
+    })
+}
+
+/// Inserts handles for both ends of a pipe or channel and stores them, closing both if either can't be stored
+fn store_pair(
+    (first, second): (OwnedFd, OwnedFd),
+    ty: usize,
+    rights: (u32, u32),
+    first_out: UserPtrMut<HandlePtr<IOHandle>>,
+    second_out: UserPtrMut<HandlePtr<IOHandle>>,
+) -> Result<()> {
+    let first = new_handle(first, ty, rights.0)?;
+    let second = new_handle(second, ty, rights.1).inspect_err(|_| {
+        unsafe { Handle::deref_unchecked(first) }.close(false);
+    })?;
+
+    first_out
+        .write(first.cast())
+        .and_then(|()| second_out.write(second.cast()))
+        .inspect_err(|_| {
+            unsafe { Handle::deref_unchecked(first) }.close(false);
+            unsafe { Handle::deref_unchecked(second) }.close(false);
+        })
+}
+
+def_subsystem! {
+    name: "ipc",
+    uuid: "444ad439-e30f-5da8-8a97-675a0e7cc80f",
+    number: dynamic,
+    version: (0, 1, 0),
+    declared: sysno::DECLARED_SYSCALLS,
+    syscalls: [
+        SYS_CreatePipe => pipe::CreatePipe,
+        SYS_CreateChannel => channel::CreateChannel,
+        SYS_CreateNamedChannel => channel::CreateNamedChannel,
+        SYS_AcceptChannel => channel::AcceptChannel,
+        SYS_ConnectNamedChannel => channel::ConnectNamedChannel,
+        SYS_RemoveNamedChannel => channel::RemoveNamedChannel,
+    ],
+    init: register_sandbox_access,
+}
+
+fn register_sandbox_access() {
+    wl_impl::sandbox::register_runtime_access(&channel::CHANNEL_DIR_ACCESS);
+}
@@ -0,0 +1,36 @@
+use lilium_sys::{
+    result::Result,
+    sys::{
+        handle::{self, HandlePtr},
+        io::IOHandle,
+    },
+};
+use rustix::pipe::{PipeFlags, pipe_with};
+use wl_impl::{
+    export_syscall,
+    handle_base::{
+        HANDLE_RIGHT_CLOSE, HANDLE_RIGHT_READ, HANDLE_RIGHT_TRANSFER, HANDLE_RIGHT_WRITE,
+    },
+    helpers::{ErrorContext, rustix_error_to_lilium},
+    user_ptr::UserPtrMut,
+};
+
+use crate::{nonblocking, store_pair};
+
+export_syscall! {
+    unsafe extern fn CreatePipe(read_out: UserPtrMut<HandlePtr<IOHandle>>, write_out: UserPtrMut<HandlePtr<IOHandle>>, flags: u32) -> Result<()> {
+        let mut pflags = PipeFlags::CLOEXEC;
+        if nonblocking(flags)? {
+            pflags |= PipeFlags::NONBLOCK;
+        }
+
+        let ends = pipe_with(pflags).map_err(|e| rustix_error_to_lilium(e, ErrorContext::File))?;
+
+        let rights = (
+            HANDLE_RIGHT_READ | HANDLE_RIGHT_TRANSFER | HANDLE_RIGHT_CLOSE,
+            HANDLE_RIGHT_WRITE | HANDLE_RIGHT_TRANSFER | HANDLE_RIGHT_CLOSE,
+        );
+
+        store_pair(ends, handle::HANDLE_SUBTYPE_IO_PIPE as usize, rights, read_out, write_out)
+    }
+}
@@ -0,0 +1,171 @@
+use lilium_sys::result::{Error, Result};
+use rustix::net::AddressFamily;
+use wl_impl::user_ptr::UserValue;
+
+// `SocketAddress` and the address families are generated from `socket.knum`
+pub use crate::knum::socket::{
+    ADDRESS_FAMILY_INET4, ADDRESS_FAMILY_INET6, ADDRESS_FAMILY_UNIX, ADDRESS_FAMILY_UNSPEC,
+    SocketAddress,
+};
+
+// SAFETY: `SocketAddress` is `Pod`
+unsafe impl UserValue for SocketAddress {}
+
+impl SocketAddress {
+    /// An IPv4 address and port
+    pub fn inet4(addr: [u8; 4], port: u16) -> Self {
+        let mut sa: SocketAddress = bytemuck::zeroed();
+        sa.family = ADDRESS_FAMILY_INET4;
+        sa.port = port;
+        sa.addr[..4].copy_from_slice(&addr);
+        sa
+    }
+
+    /// An IPv6 address and port
+    pub fn inet6(addr: [u8; 16], port: u16, scope_id: u32) -> Self {
+        let mut sa: SocketAddress = bytemuck::zeroed();
+        sa.family = ADDRESS_FAMILY_INET6;
+        sa.port = port;
+        sa.scope_id = scope_id;
+        sa.addr = addr;
+        sa
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
+struct SockaddrIn {
+    family: u16,
+    port: [u8; 2],
+    addr: [u8; 4],
+    zero: [u8; 8],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
+struct SockaddrIn6 {
+    family: u16,
+    port: [u8; 2],
+    flow_info: [u8; 4],
+    addr: [u8; 16],
+    scope_id: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
+struct SockaddrUn {
+    family: u16,
+    path: [u8; 108],
+}
+
+/// A host socket address (`struct sockaddr_storage`), and the length of the address stored in it
+#[repr(C, align(8))]
+#[derive(Copy, Clone, bytemuck::Zeroable)]
+pub(crate) struct SockaddrStorage {
+    bytes: [u8; 128],
+    pub len: u32,
+}
+
+impl SockaddrStorage {
+    /// An empty address that can be filled by the host with up to the full size of the storage
+    pub fn empty() -> Self {
+        Self {
+            bytes: [0; 128],
+            len: 128,
+        }
+    }
+
+    fn store<T: bytemuck::Pod>(val: &T) -> Self {
+        let mut storage = Self::empty();
+        let bytes = bytemuck::bytes_of(val);
+        storage.bytes[..bytes.len()].copy_from_slice(bytes);
+        storage.len = bytes.len() as u32;
+        storage
+    }
+
+    fn load<T: bytemuck::Pod>(&self) -> T {
+        let mut val: T = bytemuck::zeroed();
+        let bytes = bytemuck::bytes_of_mut(&mut val);
+        let len = (self.len as usize).min(bytes.len());
+        bytes[..len].copy_from_slice(&self.bytes[..len]);
+        val
+    }
+
+    pub fn as_ptr(&self) -> *const core::ffi::c_void {
+        self.bytes.as_ptr().cast()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut core::ffi::c_void {
+        self.bytes.as_mut_ptr().cast()
+    }
+
+    fn family(&self) -> u16 {
+        u16::from_ne_bytes([self.bytes[0], self.bytes[1]])
+    }
+
+    /// Converts a Lilium socket address to the host representation
+    pub fn from_lilium(addr: &SocketAddress) -> Result<Self> {
+        match addr.family {
+            ADDRESS_FAMILY_INET4 => Ok(Self::store(&SockaddrIn {
+                family: AddressFamily::INET.as_raw(),
+                port: addr.port.to_be_bytes(),
+                addr: addr.addr[..4].try_into().unwrap(),
+                zero: [0; 8],
+            })),
+            ADDRESS_FAMILY_INET6 => Ok(Self::store(&SockaddrIn6 {
+                family: AddressFamily::INET6.as_raw(),
+                port: addr.port.to_be_bytes(),
+                flow_info: addr.flow_info.to_be_bytes(),
+                addr: addr.addr,
+                scope_id: addr.scope_id,
+            })),
+            ADDRESS_FAMILY_UNIX => {
+                let len = addr.path_len as usize;
+                if len > addr.path.len() {
+                    return Err(Error::InvalidString);
+                }
+                let mut storage = Self::store(&SockaddrUn {
+                    family: AddressFamily::UNIX.as_raw(),
+                    path: addr.path,
+                });
+                storage.len = (size_of::<u16>() + len) as u32;
+                Ok(storage)
+            }
+            _ => Err(Error::InvalidOption),
+        }
+    }
+
+    /// Converts a host socket address filled in by the host to the Lilium representation
+    pub fn to_lilium(&self) -> Result<SocketAddress> {
+        let mut sa: SocketAddress = bytemuck::zeroed();
+
+        match self.family() {
+            0 => {}
+            f if f == AddressFamily::INET.as_raw() => {
+                let raw: SockaddrIn = self.load();
+                sa = SocketAddress::inet4(raw.addr, u16::from_be_bytes(raw.port));
+            }
+            f if f == AddressFamily::INET6.as_raw() => {
+                let raw: SockaddrIn6 = self.load();
+                sa = SocketAddress::inet6(raw.addr, u16::from_be_bytes(raw.port), raw.scope_id);
+                sa.flow_info = u32::from_be_bytes(raw.flow_info);
+            }
+            f if f == AddressFamily::UNIX.as_raw() => {
+                let raw: SockaddrUn = self.load();
+                sa.family = ADDRESS_FAMILY_UNIX;
+                // Unnamed sockets have no path, and pathname sockets may include the nul terminator
+                let mut len = (self.len as usize).saturating_sub(size_of::<u16>());
+                len = len.min(raw.path.len());
+                if len > 0 && raw.path[0] != 0 {
+                    len = raw.path[..len].iter().position(|&b| b == 0).unwrap_or(len);
+                }
+                sa.path = raw.path;
+                sa.path[len..].fill(0);
+                sa.path_len = len as u32;
+            }
+            _ => return Err(Error::UnsupportedOperation),
+        }
+
+        Ok(sa)
+    }
+}
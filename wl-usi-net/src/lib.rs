@@ -0,0 +1,58 @@
+#![cfg_attr(not(test), no_std)]
+#![feature(never_type)]
+
+extern crate alloc;
+
+use wl_impl::def_subsystem;
+
+/// Syscall numbers generated from the `.knum` files of the subsystem
+#[allow(non_upper_case_globals)]
+mod sysno {
+    include!(concat!(env!("OUT_DIR"), "/sysno.rs"));
+}
+
+/// Constants and structs generated from the `.knum` files of the subsystem, with a module for each file
+#[allow(dead_code)]
+mod knum {
+    include!(concat!(env!("OUT_DIR"), "/knum.rs"));
+}
+
+use sysno::{
+    SYS_AcceptSocket, SYS_BindSocket, SYS_ConnectSocket, SYS_CreateSocket, SYS_GetSocketAddress,
+    SYS_GetSocketOption, SYS_GetSocketPeerAddress, SYS_ListenSocket, SYS_ReceiveSocket,
+    SYS_ResolveAddress, SYS_ResolveHostName, SYS_SendSocket, SYS_SetSocketOption,
+    SYS_ShutdownSocket,
+};
+
+pub mod addr;
+pub mod resolve;
+pub mod socket;
+
+def_subsystem! {
+    name: "net",
+    uuid: "11b7dc26-a007-5708-ba47-282aeeff5329",
+    number: dynamic,
+    version: (0, 1, 0),
+    declared: sysno::DECLARED_SYSCALLS,
+    syscalls: [
+        SYS_CreateSocket => socket::CreateSocket,
+        SYS_BindSocket => socket::BindSocket,
+        SYS_ListenSocket => socket::ListenSocket,
+        SYS_AcceptSocket => socket::AcceptSocket,
+        SYS_ConnectSocket => socket::ConnectSocket,
+        SYS_SendSocket => socket::SendSocket,
+        SYS_ReceiveSocket => socket::ReceiveSocket,
+        SYS_ShutdownSocket => socket::ShutdownSocket,
+        SYS_GetSocketOption => socket::GetSocketOption,
+        SYS_SetSocketOption => socket::SetSocketOption,
+        SYS_GetSocketAddress => socket::GetSocketAddress,
+        SYS_GetSocketPeerAddress => socket::GetSocketPeerAddress,
+        SYS_ResolveHostName => resolve::ResolveHostName,
+        SYS_ResolveAddress => resolve::ResolveAddress,
+    ],
+    init: register_sandbox_access,
+}
+
+fn register_sandbox_access() {
+    wl_impl::sandbox::register_runtime_access(&resolve::RESOLVER_FILES_ACCESS);
+}
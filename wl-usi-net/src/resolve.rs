@@ -0,0 +1,857 @@
+//! Host name resolution.
+//!
+//! Names are looked up in `/etc/hosts` first, and then by querying the DNS servers listed in `/etc/resolv.conf` over UDP.
+//! Both files are read from under `WL_SYSROOT`, and are reread on every lookup.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::kstr::KStrPtr,
+};
+use rustix::{
+    fs::{Mode, OFlags},
+    io::Errno,
+    net::{AddressFamily, SendFlags, SocketFlags, SocketType, sockopt},
+};
+use wl_impl::{
+    env::host_var,
+    export_syscall,
+    helpers::{ErrorContext, fill_str, linux_error_to_lilium_in, rustix_error_to_lilium},
+    libc,
+    ministd::AsRawFd,
+    rand::fast_rand,
+    sandbox::{ACCESS_FS_READ, RuntimeAccess},
+    user_ptr::{UserPtr, UserPtrMut, UserSliceMut, UserStr},
+};
+
+use crate::addr::{
+    ADDRESS_FAMILY_INET4, ADDRESS_FAMILY_INET6, ADDRESS_FAMILY_UNSPEC, SockaddrStorage,
+    SocketAddress,
+};
+
+const HOSTS_PATH: &str = "/etc/hosts";
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+/// The maximum number of `nameserver` entries that are used, like glibc
+const MAX_NAMESERVERS: usize = 3;
+const DNS_PORT: u16 = 53;
+/// The maximum size of a DNS message over UDP, without EDNS
+const MAX_UDP_MESSAGE: usize = 512;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const RCODE_NAME_ERROR: u16 = 3;
+
+/// Keeps name resolution working after the program drops `AccessFiles`
+pub(crate) static RESOLVER_FILES_ACCESS: RuntimeAccess = RuntimeAccess {
+    paths: || vec![sysroot_path(HOSTS_PATH), sysroot_path(RESOLV_CONF_PATH)],
+    access: ACCESS_FS_READ,
+};
+
+fn sysroot_path(path: &str) -> String {
+    let sysroot = host_var("WL_SYSROOT").unwrap_or("");
+    format!("{}{path}", sysroot.trim_end_matches('/'))
+}
+
+fn read_sysroot_file(path: &str) -> Option<Vec<u8>> {
+    let path = sysroot_path(path);
+
+    let fd = rustix::fs::open(&*path, OFlags::RDONLY | OFlags::CLOEXEC, Mode::empty()).ok()?;
+
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+
+    loop {
+        match rustix::io::read(&fd, &mut buf[..]) {
+            Ok(0) => break,
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+            Err(Errno::INTR) => {}
+            Err(_) => return None,
+        }
+    }
+
+    Some(data)
+}
+
+/// Iterates over the lines of a configuration file, with comments removed
+fn config_lines(data: &[u8]) -> impl Iterator<Item = &str> {
+    data.split(|&b| b == b'\n')
+        .filter_map(|line| core::str::from_utf8(line).ok())
+        .map(|line| {
+            line.split_once('#')
+                .map_or(line, |(line, _)| line)
+                .trim_ascii()
+        })
+        .filter(|line| !line.is_empty())
+}
+
+fn family_matches(family: u16, ip: IpAddr) -> bool {
+    match (family, ip) {
+        (ADDRESS_FAMILY_UNSPEC, _) => true,
+        (ADDRESS_FAMILY_INET4, IpAddr::V4(_)) => true,
+        (ADDRESS_FAMILY_INET6, IpAddr::V6(_)) => true,
+        _ => false,
+    }
+}
+
+fn socket_address(addr: SocketAddr) -> SocketAddress {
+    match addr {
+        SocketAddr::V4(addr) => SocketAddress::inet4(addr.ip().octets(), addr.port()),
+        SocketAddr::V6(addr) => {
+            let mut sa = SocketAddress::inet6(addr.ip().octets(), addr.port(), addr.scope_id());
+            sa.flow_info = addr.flowinfo();
+            sa
+        }
+    }
+}
+
+fn lookup_hosts(name: &str, family: u16, addrs: &mut Vec<SocketAddress>) {
+    let Some(data) = read_sysroot_file(HOSTS_PATH) else {
+        return;
+    };
+
+    for line in config_lines(&data) {
+        let mut fields = line.split_ascii_whitespace();
+        let Some(Ok(ip)) = fields.next().map(IpAddr::from_str) else {
+            continue;
+        };
+
+        if family_matches(family, ip) && fields.any(|host| host.eq_ignore_ascii_case(name)) {
+            addrs.push(socket_address(SocketAddr::new(ip, 0)));
+        }
+    }
+}
+
+/// Returns the canonical name (the first name) of the first line in `/etc/hosts` for `ip`
+fn reverse_lookup_hosts(ip: IpAddr) -> Option<String> {
+    let data = read_sysroot_file(HOSTS_PATH)?;
+
+    config_lines(&data).find_map(|line| {
+        let mut fields = line.split_ascii_whitespace();
+        if IpAddr::from_str(fields.next()?).ok()? != ip {
+            return None;
+        }
+        fields.next().map(String::from)
+    })
+}
+
+struct ResolvConf {
+    servers: Vec<SocketAddr>,
+    timeout: Duration,
+    attempts: u32,
+}
+
+fn read_resolv_conf() -> ResolvConf {
+    parse_resolv_conf(read_sysroot_file(RESOLV_CONF_PATH).as_deref())
+}
+
+/// Parses the contents of `/etc/resolv.conf`, or returns the defaults if it doesn't exist
+fn parse_resolv_conf(data: Option<&[u8]>) -> ResolvConf {
+    let mut conf = ResolvConf {
+        servers: Vec::new(),
+        timeout: Duration::from_secs(5),
+        attempts: 2,
+    };
+
+    if let Some(data) = data {
+        for line in config_lines(data) {
+            let mut fields = line.split_ascii_whitespace();
+            match fields.next() {
+                Some("nameserver") if conf.servers.len() < MAX_NAMESERVERS => {
+                    // As an extension, the server may include a port (`127.0.0.1:5353` or `[::1]:5353`)
+                    let server = fields.next().and_then(|server| {
+                        IpAddr::from_str(server)
+                            .map(|ip| SocketAddr::new(ip, DNS_PORT))
+                            .or_else(|_| SocketAddr::from_str(server))
+                            .ok()
+                    });
+                    conf.servers.extend(server);
+                }
+                Some("options") => {
+                    for opt in fields {
+                        if let Some(secs) = opt.strip_prefix("timeout:") {
+                            if let Ok(secs) = secs.parse::<u64>() {
+                                conf.timeout = Duration::from_secs(secs.clamp(1, 30));
+                            }
+                        } else if let Some(attempts) = opt.strip_prefix("attempts:") {
+                            if let Ok(attempts) = attempts.parse::<u32>() {
+                                conf.attempts = attempts.clamp(1, 5);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // With no servers configured, the local host is used (as in glibc)
+    if conf.servers.is_empty() {
+        conf.servers
+            .push(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), DNS_PORT));
+    }
+
+    conf
+}
+
+fn be16(msg: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        msg.get(pos..pos + 2)?.try_into().unwrap(),
+    ))
+}
+
+fn encode_name(msg: &mut Vec<u8>, name: &str) -> Result<()> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+
+    if name.is_empty() || name.len() > 253 {
+        return Err(Error::InvalidString);
+    }
+
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::InvalidString);
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+
+    Ok(())
+}
+
+fn build_query(id: u16, name: &str, qtype: u16) -> Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(MAX_UDP_MESSAGE);
+
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    // One question, and no answer, authority, or additional records
+    msg.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    encode_name(&mut msg, name)?;
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(msg)
+}
+
+/// Returns the position after the (possibly compressed) name at `pos`
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)? as usize;
+        match len & 0xC0 {
+            0 if len == 0 => return Some(pos + 1),
+            0 => pos += 1 + len,
+            0xC0 => return Some(pos + 2),
+            _ => return None,
+        }
+    }
+}
+
+/// Decodes the (possibly compressed) name at `pos`
+fn read_name(msg: &[u8], mut pos: usize) -> Option<String> {
+    let mut name = String::new();
+    let mut jumps = 0;
+
+    loop {
+        let len = *msg.get(pos)? as usize;
+        match len & 0xC0 {
+            0 if len == 0 => break,
+            0 => {
+                let label = core::str::from_utf8(msg.get(pos + 1..pos + 1 + len)?).ok()?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(label);
+                pos += 1 + len;
+            }
+            0xC0 => {
+                // Bounds the work done for malicious pointer loops
+                jumps += 1;
+                if jumps > 16 {
+                    return None;
+                }
+                pos = (be16(msg, pos)? & 0x3FFF) as usize;
+            }
+            _ => return None,
+        }
+
+        if name.len() > 253 {
+            return None;
+        }
+    }
+
+    Some(name)
+}
+
+/// A resource record in a response: its type, and the position and length of its data
+type Record = (u16, usize, usize);
+
+enum Response {
+    Answers(Vec<Record>),
+    NameError,
+    Failure,
+}
+
+/// Parses the response `msg` to the query for the `qtype` records of `name` with the id `id`.
+///
+/// Responses that don't answer that exact question, or that were truncated, are failures.
+fn parse_response(msg: &[u8], id: u16, name: &str, qtype: u16) -> Response {
+    let parse = || {
+        if be16(msg, 0)? != id {
+            return None;
+        }
+
+        let flags = be16(msg, 2)?;
+        if (flags & FLAG_RESPONSE) == 0 || (flags & FLAG_TRUNCATED) != 0 {
+            return None;
+        }
+
+        let questions = be16(msg, 4)?;
+        let answers = be16(msg, 6)?;
+
+        // The question is echoed back, which stops a response to a different query (with a guessed id) from being accepted
+        if questions != 1 {
+            return None;
+        }
+        let qname = read_name(msg, 12)?;
+        let mut pos = skip_name(msg, 12)?;
+        if !qname.eq_ignore_ascii_case(name.strip_suffix('.').unwrap_or(name))
+            || be16(msg, pos)? != qtype
+            || be16(msg, pos + 2)? != CLASS_IN
+        {
+            return None;
+        }
+        pos += 4;
+
+        match flags & 0xF {
+            0 => {}
+            RCODE_NAME_ERROR => return Some(Response::NameError),
+            _ => return Some(Response::Failure),
+        }
+
+        let mut records = Vec::new();
+        for _ in 0..answers {
+            pos = skip_name(msg, pos)?;
+            let ty = be16(msg, pos)?;
+            let class = be16(msg, pos + 2)?;
+            let len = be16(msg, pos + 8)? as usize;
+            pos += 10;
+
+            if pos + len > msg.len() {
+                break;
+            }
+            if class == CLASS_IN {
+                records.push((ty, pos, len));
+            }
+            pos += len;
+        }
+
+        Some(Response::Answers(records))
+    };
+
+    parse().unwrap_or(Response::Failure)
+}
+
+/// Sends `query` to `server`, and waits up to `timeout` for a response with the id `id`, which is stored in `resp`
+fn exchange(
+    server: SocketAddr,
+    query: &[u8],
+    id: u16,
+    timeout: Duration,
+    resp: &mut [u8; MAX_UDP_MESSAGE],
+) -> Result<usize> {
+    let family = match server {
+        SocketAddr::V4(_) => AddressFamily::INET,
+        SocketAddr::V6(_) => AddressFamily::INET6,
+    };
+
+    let fd = rustix::net::socket_with(family, SocketType::DGRAM, SocketFlags::CLOEXEC, None)
+        .map_err(|e| rustix_error_to_lilium(e, ErrorContext::Net))?;
+    sockopt::set_socket_timeout(&fd, sockopt::Timeout::Recv, Some(timeout))
+        .map_err(|e| rustix_error_to_lilium(e, ErrorContext::Net))?;
+
+    let fd = fd.as_raw_fd();
+    let addr = SockaddrStorage::from_lilium(&socket_address(server))?;
+
+    // Connecting filters out datagrams from anyone other than the server
+    unsafe { libc::connect(fd, addr.as_ptr(), addr.len) }
+        .and_then(|()| unsafe {
+            libc::sendto(
+                fd,
+                query.as_ptr().cast(),
+                query.len(),
+                SendFlags::NOSIGNAL.bits(),
+                core::ptr::null(),
+                0,
+            )
+        })
+        .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Net))?;
+
+    loop {
+        let n = unsafe {
+            libc::recvfrom(
+                fd,
+                resp.as_mut_ptr().cast(),
+                resp.len(),
+                0,
+                core::ptr::null_mut(),
+                core::ptr::null_mut(),
+            )
+        }
+        .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Net))?;
+
+        // Stale responses to earlier queries are skipped
+        if be16(&resp[..n], 0) == Some(id) {
+            return Ok(n);
+        }
+    }
+}
+
+/// Queries the configured DNS servers for the `qtype` records of `name`, returning the response and the records in it
+fn dns_query(conf: &ResolvConf, name: &str, qtype: u16) -> Result<(Vec<u8>, Vec<Record>)> {
+    let id = fast_rand() as u16;
+    let query = build_query(id, name, qtype)?;
+    let mut resp = [0u8; MAX_UDP_MESSAGE];
+    let mut err = Error::Timeout;
+
+    for _ in 0..conf.attempts {
+        for &server in &conf.servers {
+            let n = match exchange(server, &query, id, conf.timeout, &mut resp) {
+                Ok(n) => n,
+                // The server didn't respond in time (`WouldBlock`), or couldn't be reached
+                Err(_) => continue,
+            };
+
+            match parse_response(&resp[..n], id, name, qtype) {
+                Response::Answers(records) => return Ok((resp[..n].to_vec(), records)),
+                Response::NameError => return Err(Error::DoesNotExist),
+                // The server responded, but couldn't resolve the name. Another server might.
+                Response::Failure => err = Error::DoesNotExist,
+            }
+        }
+    }
+
+    Err(err)
+}
+
+/// Resolves `name` to addresses in `family` (or any family for `ADDRESS_FAMILY_UNSPEC`).
+///
+/// IP address literals resolve to themselves. The port of each address is 0.
+pub fn resolve_host(name: &str, family: u16) -> Result<Vec<SocketAddress>> {
+    let qtypes: &[u16] = match family {
+        ADDRESS_FAMILY_UNSPEC => &[TYPE_A, TYPE_AAAA],
+        ADDRESS_FAMILY_INET4 => &[TYPE_A],
+        ADDRESS_FAMILY_INET6 => &[TYPE_AAAA],
+        _ => return Err(Error::InvalidOption),
+    };
+
+    if let Ok(ip) = IpAddr::from_str(name) {
+        return if family_matches(family, ip) {
+            Ok(alloc::vec![socket_address(SocketAddr::new(ip, 0))])
+        } else {
+            Err(Error::DoesNotExist)
+        };
+    }
+
+    let mut addrs = Vec::new();
+
+    lookup_hosts(name.strip_suffix('.').unwrap_or(name), family, &mut addrs);
+    if !addrs.is_empty() {
+        return Ok(addrs);
+    }
+
+    let conf = read_resolv_conf();
+    let mut err = Error::DoesNotExist;
+
+    for &qtype in qtypes {
+        let (msg, records) = match dns_query(&conf, name, qtype) {
+            Ok(res) => res,
+            Err(e) => {
+                err = e;
+                continue;
+            }
+        };
+
+        // CNAME records in the answer are followed by the server, so only the addresses are needed
+        for (ty, pos, len) in records {
+            match (ty, len) {
+                (TYPE_A, 4) if qtype == TYPE_A => {
+                    let ip: [u8; 4] = msg[pos..pos + 4].try_into().unwrap();
+                    addrs.push(SocketAddress::inet4(ip, 0));
+                }
+                (TYPE_AAAA, 16) if qtype == TYPE_AAAA => {
+                    let ip: [u8; 16] = msg[pos..pos + 16].try_into().unwrap();
+                    addrs.push(SocketAddress::inet6(ip, 0, 0));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if addrs.is_empty() {
+        Err(err)
+    } else {
+        Ok(addrs)
+    }
+}
+
+/// Resolves the IPv4 or IPv6 address `addr` to a host name
+pub fn resolve_address(addr: &SocketAddress) -> Result<String> {
+    let ip = match addr.family {
+        ADDRESS_FAMILY_INET4 => IpAddr::V4(Ipv4Addr::from(
+            <[u8; 4]>::try_from(&addr.addr[..4]).unwrap(),
+        )),
+        ADDRESS_FAMILY_INET6 => IpAddr::V6(Ipv6Addr::from(addr.addr)),
+        _ => return Err(Error::InvalidOption),
+    };
+
+    if let Some(name) = reverse_lookup_hosts(ip) {
+        return Ok(name);
+    }
+
+    let qname = match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            format!("{d}.{c}.{b}.{a}.in-addr.arpa")
+        }
+        IpAddr::V6(ip) => {
+            let mut qname = String::with_capacity(72);
+            for byte in ip.octets().iter().rev() {
+                qname += &format!("{:x}.{:x}.", byte & 0xF, byte >> 4);
+            }
+            qname + "ip6.arpa"
+        }
+    };
+
+    let (msg, records) = dns_query(&read_resolv_conf(), &qname, TYPE_PTR)?;
+
+    records
+        .iter()
+        .find(|&&(ty, _, _)| ty == TYPE_PTR)
+        .and_then(|&(_, pos, _)| read_name(&msg, pos))
+        .ok_or(Error::DoesNotExist)
+}
+
+export_syscall! {
+    unsafe extern fn ResolveHostName(name: UserStr, family: u16, addrs_out: UserSliceMut<SocketAddress>) -> Result<usize> {
+        // Returns the total number of addresses, and writes up to `addrs_out.len` of them
+        let addrs = resolve_host(name.as_str()?, family)?;
+
+        let mut addrs_out = addrs_out;
+        for (out, addr) in addrs_out.iter_mut()?.zip(&addrs) {
+            *out? = *addr;
+        }
+
+        Ok(addrs.len())
+    }
+}
+
+export_syscall! {
+    unsafe extern fn ResolveAddress(addr: UserPtr<SocketAddress>, name_out: UserPtrMut<KStrPtr>) -> Result<()> {
+        // If `name_out` is too short, its length is still updated to the length of the name
+        let name = resolve_address(&addr.read()?)?;
+
+        let mut kstr = name_out.read()?;
+        let res = unsafe { fill_str(&mut kstr, &name) };
+        name_out.write(kstr)?;
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u16 = 0x1234;
+
+    /// Builds a response to the query for the `qtype` records of `name`, with the answers `answers` (each a type and its data)
+    fn response(flags: u16, name: &str, qtype: u16, answers: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut msg = build_query(ID, name, qtype).unwrap();
+        msg[2..4].copy_from_slice(&(FLAG_RESPONSE | flags).to_be_bytes());
+        msg[6..8].copy_from_slice(&(answers.len() as u16).to_be_bytes());
+
+        for &(ty, data) in answers {
+            // A pointer to the name in the question
+            msg.extend_from_slice(&[0xC0, 12]);
+            msg.extend_from_slice(&ty.to_be_bytes());
+            msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+            msg.extend_from_slice(&300u32.to_be_bytes());
+            msg.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            msg.extend_from_slice(data);
+        }
+
+        msg
+    }
+
+    fn answers(resp: Response) -> Vec<Record> {
+        match resp {
+            Response::Answers(records) => records,
+            Response::NameError => panic!("unexpected NameError"),
+            Response::Failure => panic!("unexpected Failure"),
+        }
+    }
+
+    fn is_failure(resp: Response) -> bool {
+        matches!(resp, Response::Failure)
+    }
+
+    #[test]
+    fn build_query_encodes_question() {
+        let msg = build_query(ID, "www.example.com.", TYPE_AAAA).unwrap();
+
+        assert_eq!(msg[..4], [0x12, 0x34, 0x01, 0x00]);
+        assert_eq!(msg[4..12], [0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&msg[12..29], b"\x03www\x07example\x03com\x00");
+        assert_eq!(msg[29..], [0, 28, 0, 1]);
+    }
+
+    #[test]
+    fn build_query_rejects_invalid_names() {
+        assert!(build_query(ID, "", TYPE_A).is_err());
+        assert!(build_query(ID, ".", TYPE_A).is_err());
+        assert!(build_query(ID, "a..b", TYPE_A).is_err());
+        assert!(build_query(ID, &"a".repeat(64), TYPE_A).is_err());
+        assert!(build_query(ID, &["a"; 128].join("."), TYPE_A).is_err());
+    }
+
+    #[test]
+    fn read_and_skip_plain_name() {
+        let msg = b"\x03www\x07example\x03com\x00rest";
+
+        assert_eq!(read_name(msg, 0).as_deref(), Some("www.example.com"));
+        assert_eq!(skip_name(msg, 0), Some(17));
+    }
+
+    #[test]
+    fn read_and_skip_compressed_name() {
+        // `www` followed by a pointer to `example.com` at offset 0
+        let msg = b"\x07example\x03com\x00\x03www\xC0\x00";
+
+        assert_eq!(read_name(msg, 13).as_deref(), Some("www.example.com"));
+        assert_eq!(skip_name(msg, 13), Some(19));
+    }
+
+    #[test]
+    fn pointer_loop_is_rejected() {
+        let msg = b"\x03www\xC0\x00";
+        assert_eq!(read_name(msg, 0), None);
+
+        let msg = b"\xC0\x02\xC0\x00";
+        assert_eq!(read_name(msg, 0), None);
+    }
+
+    #[test]
+    fn truncated_names_are_rejected() {
+        assert_eq!(read_name(b"\x03ww", 0), None);
+        assert_eq!(read_name(b"\x03www", 0), None);
+        assert_eq!(read_name(b"\xC0", 0), None);
+        assert_eq!(skip_name(b"\x03www", 0), None);
+        assert_eq!(skip_name(b"", 0), None);
+    }
+
+    #[test]
+    fn reserved_label_types_are_rejected() {
+        assert_eq!(read_name(b"\x40\x00", 0), None);
+        assert_eq!(skip_name(b"\x80\x00", 0), None);
+    }
+
+    #[test]
+    fn parse_answers() {
+        let msg = response(
+            0,
+            "example.com",
+            TYPE_A,
+            &[(TYPE_A, &[192, 0, 2, 1]), (TYPE_A, &[192, 0, 2, 2])],
+        );
+
+        let records = answers(parse_response(&msg, ID, "example.com", TYPE_A));
+        assert_eq!(records.len(), 2);
+        for ((ty, pos, len), ip) in records.into_iter().zip([[192, 0, 2, 1], [192, 0, 2, 2]]) {
+            assert_eq!((ty, len), (TYPE_A, 4));
+            assert_eq!(msg[pos..pos + len], ip);
+        }
+    }
+
+    #[test]
+    fn question_name_is_case_insensitive() {
+        let msg = response(0, "EXAMPLE.com", TYPE_A, &[(TYPE_A, &[192, 0, 2, 1])]);
+
+        assert_eq!(
+            answers(parse_response(&msg, ID, "example.com.", TYPE_A)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn name_error() {
+        let msg = response(RCODE_NAME_ERROR, "example.com", TYPE_A, &[]);
+
+        assert!(matches!(
+            parse_response(&msg, ID, "example.com", TYPE_A),
+            Response::NameError
+        ));
+    }
+
+    #[test]
+    fn server_failure() {
+        let msg = response(2, "example.com", TYPE_A, &[]);
+
+        assert!(is_failure(parse_response(&msg, ID, "example.com", TYPE_A)));
+    }
+
+    #[test]
+    fn mismatched_id_is_rejected() {
+        let msg = response(0, "example.com", TYPE_A, &[(TYPE_A, &[192, 0, 2, 1])]);
+
+        assert!(is_failure(parse_response(
+            &msg,
+            ID + 1,
+            "example.com",
+            TYPE_A
+        )));
+    }
+
+    #[test]
+    fn mismatched_question_is_rejected() {
+        let msg = response(0, "example.com", TYPE_A, &[(TYPE_A, &[192, 0, 2, 1])]);
+
+        assert!(is_failure(parse_response(&msg, ID, "example.org", TYPE_A)));
+        assert!(is_failure(parse_response(
+            &msg,
+            ID,
+            "example.com",
+            TYPE_AAAA
+        )));
+
+        let mut msg = msg;
+        let class = 12 + "\x07example\x03com\x00".len() + 2;
+        msg[class..class + 2].copy_from_slice(&3u16.to_be_bytes());
+        assert!(is_failure(parse_response(&msg, ID, "example.com", TYPE_A)));
+    }
+
+    #[test]
+    fn mismatched_question_is_rejected_for_name_errors() {
+        let msg = response(RCODE_NAME_ERROR, "example.org", TYPE_A, &[]);
+
+        assert!(is_failure(parse_response(&msg, ID, "example.com", TYPE_A)));
+    }
+
+    #[test]
+    fn truncated_response_is_rejected() {
+        let msg = response(
+            FLAG_TRUNCATED,
+            "example.com",
+            TYPE_A,
+            &[(TYPE_A, &[192, 0, 2, 1])],
+        );
+
+        assert!(is_failure(parse_response(&msg, ID, "example.com", TYPE_A)));
+    }
+
+    #[test]
+    fn query_is_not_a_response() {
+        let msg = build_query(ID, "example.com", TYPE_A).unwrap();
+
+        assert!(is_failure(parse_response(&msg, ID, "example.com", TYPE_A)));
+    }
+
+    #[test]
+    fn short_and_garbage_messages_are_rejected() {
+        let msg = response(0, "example.com", TYPE_A, &[(TYPE_A, &[192, 0, 2, 1])]);
+
+        // Cut off inside of the header, the question, and the header of the answer
+        for len in [0, 5, 11, 14, msg.len() - 8] {
+            assert!(is_failure(parse_response(
+                &msg[..len],
+                ID,
+                "example.com",
+                TYPE_A
+            )));
+        }
+
+        let mut garbage = [0xFFu8; 64];
+        garbage[..2].copy_from_slice(&ID.to_be_bytes());
+        assert!(is_failure(parse_response(
+            &garbage,
+            ID,
+            "example.com",
+            TYPE_A
+        )));
+    }
+
+    #[test]
+    fn records_past_the_end_are_ignored() {
+        let msg = response(0, "example.com", TYPE_A, &[(TYPE_A, &[192, 0, 2, 1])]);
+
+        // The data of the only answer is cut off
+        let records = answers(parse_response(
+            &msg[..msg.len() - 2],
+            ID,
+            "example.com",
+            TYPE_A,
+        ));
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn resolv_conf_defaults() {
+        let conf = parse_resolv_conf(None);
+
+        assert_eq!(
+            conf.servers,
+            [SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), DNS_PORT)]
+        );
+        assert_eq!(conf.timeout, Duration::from_secs(5));
+        assert_eq!(conf.attempts, 2);
+    }
+
+    #[test]
+    fn resolv_conf_nameservers() {
+        let conf = parse_resolv_conf(Some(
+            b"# comment\n\
+              nameserver 192.0.2.1\n\
+              nameserver 127.0.0.1:5353 # local stub\n\
+              nameserver not-an-address\n\
+              nameserver [::1]:5354\n\
+              nameserver 192.0.2.4\n",
+        ));
+
+        assert_eq!(
+            conf.servers,
+            [
+                SocketAddr::from_str("192.0.2.1:53").unwrap(),
+                SocketAddr::from_str("127.0.0.1:5353").unwrap(),
+                SocketAddr::from_str("[::1]:5354").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolv_conf_ipv6_nameserver_without_port() {
+        let conf = parse_resolv_conf(Some(b"nameserver ::1\n"));
+
+        assert_eq!(conf.servers, [SocketAddr::from_str("[::1]:53").unwrap()]);
+    }
+
+    #[test]
+    fn resolv_conf_options() {
+        let conf = parse_resolv_conf(Some(b"options ndots:2 timeout:3 attempts:9\n"));
+        assert_eq!(conf.timeout, Duration::from_secs(3));
+        assert_eq!(conf.attempts, 5);
+
+        let conf = parse_resolv_conf(Some(b"options timeout:0 attempts:x\n"));
+        assert_eq!(conf.timeout, Duration::from_secs(1));
+        assert_eq!(conf.attempts, 2);
+    }
+}
@@ -0,0 +1,498 @@
+use core::{ffi::c_void, time::Duration};
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::{
+        handle::{self, HandlePtr},
+        io::IOHandle,
+    },
+};
+use rustix::{
+    fd::{BorrowedFd, FromRawFd, IntoRawFd, OwnedFd},
+    net::{AddressFamily, RecvFlags, SendFlags, Shutdown, SocketFlags, SocketType, sockopt},
+};
+use wl_impl::{
+    export_syscall,
+    handle_base::{
+        HANDLE_RIGHT_CLOSE, HANDLE_RIGHT_READ, HANDLE_RIGHT_TRANSFER, HANDLE_RIGHT_WRITE, Handle,
+        insert_handle,
+    },
+    helpers::{ErrorContext, linux_error_to_lilium_in, rustix_error_to_lilium},
+    libc,
+    ministd::AsRawFd,
+    user_ptr::{UserPtr, UserPtrMut},
+};
+
+use crate::addr::{
+    ADDRESS_FAMILY_INET4, ADDRESS_FAMILY_INET6, ADDRESS_FAMILY_UNIX, SockaddrStorage, SocketAddress,
+};
+
+// The constants are generated from `socket.knum`
+pub use crate::knum::socket::{
+    RECEIVE_FLAG_DONT_WAIT, RECEIVE_FLAG_PEEK, RECEIVE_FLAG_WAIT_ALL, SHUTDOWN_BOTH, SHUTDOWN_READ,
+    SHUTDOWN_WRITE, SOCKET_FLAG_NONBLOCK, SOCKET_KIND_DATAGRAM, SOCKET_KIND_SEQPACKET,
+    SOCKET_KIND_STREAM, SOCKET_OPTION_BROADCAST, SOCKET_OPTION_IPV6_ONLY, SOCKET_OPTION_KEEP_ALIVE,
+    SOCKET_OPTION_NO_DELAY, SOCKET_OPTION_RECEIVE_BUFFER_SIZE, SOCKET_OPTION_RECEIVE_TIMEOUT,
+    SOCKET_OPTION_REUSE_ADDRESS, SOCKET_OPTION_SEND_BUFFER_SIZE, SOCKET_OPTION_SEND_TIMEOUT,
+};
+
+/// The rights of a newly created socket
+const SOCKET_RIGHTS: u32 =
+    HANDLE_RIGHT_READ | HANDLE_RIGHT_WRITE | HANDLE_RIGHT_TRANSFER | HANDLE_RIGHT_CLOSE;
+
+fn net_error(e: rustix::io::Errno) -> Error {
+    rustix_error_to_lilium(e, ErrorContext::Net)
+}
+
+fn socket_flags(flags: u32) -> Result<SocketFlags> {
+    if (flags & !SOCKET_FLAG_NONBLOCK) != 0 {
+        return Err(Error::InvalidOption);
+    }
+
+    let mut sflags = SocketFlags::CLOEXEC;
+    if (flags & SOCKET_FLAG_NONBLOCK) != 0 {
+        sflags |= SocketFlags::NONBLOCK;
+    }
+    Ok(sflags)
+}
+
+/// Returns the fd of the socket `sock`, after checking that it has `rights`
+pub(crate) fn socket_fd<'a>(sock: HandlePtr<IOHandle>, rights: u32) -> Result<BorrowedFd<'a>> {
+    let hdl = unsafe { Handle::try_deref(sock.cast())? };
+    hdl.check_type(handle::HANDLE_SUBTYPE_IO_SOCKET as usize, 0)?;
+    hdl.check_rights(rights)?;
+    hdl.borrow_fd().ok_or(Error::InvalidHandle)
+}
+
+/// Inserts a handle for the socket `fd` and stores it in `hdl_out`, closing the socket if it can't be stored
+pub(crate) fn store_socket(fd: OwnedFd, hdl_out: UserPtrMut<HandlePtr<IOHandle>>) -> Result<()> {
+    let hdl = Handle {
+        ty: handle::HANDLE_SUBTYPE_IO_SOCKET as usize,
+        blob1: core::ptr::null_mut(),
+        blob2: core::ptr::null_mut(),
+        fd: fd.as_raw_fd() as i64,
+        rights: SOCKET_RIGHTS,
+    };
+
+    let ptr = insert_handle(hdl)?;
+    let _ = fd.into_raw_fd();
+
+    hdl_out.write(ptr.cast()).inspect_err(|_| {
+        unsafe { Handle::deref_unchecked(ptr) }.close(false);
+    })
+}
+
+fn read_addr(addr: UserPtr<SocketAddress>) -> Result<SockaddrStorage> {
+    SockaddrStorage::from_lilium(&addr.read()?)
+}
+
+export_syscall! {
+    unsafe extern fn CreateSocket(hdl_out: UserPtrMut<HandlePtr<IOHandle>>, family: u16, kind: u32, flags: u32) -> Result<()> {
+        // Check that the handle can be stored before creating the socket
+        hdl_out.write(HandlePtr::null())?;
+
+        let family = match family {
+            ADDRESS_FAMILY_INET4 => AddressFamily::INET,
+            ADDRESS_FAMILY_INET6 => AddressFamily::INET6,
+            ADDRESS_FAMILY_UNIX => AddressFamily::UNIX,
+            _ => return Err(Error::InvalidOption),
+        };
+
+        let ty = match kind {
+            SOCKET_KIND_STREAM => SocketType::STREAM,
+            SOCKET_KIND_DATAGRAM => SocketType::DGRAM,
+            SOCKET_KIND_SEQPACKET => SocketType::SEQPACKET,
+            _ => return Err(Error::InvalidOption),
+        };
+
+        let fd = rustix::net::socket_with(family, ty, socket_flags(flags)?, None)
+            .map_err(net_error)?;
+
+        store_socket(fd, hdl_out)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn BindSocket(sock: HandlePtr<IOHandle>, addr: UserPtr<SocketAddress>) -> Result<()> {
+        let fd = socket_fd(sock, 0)?;
+        let addr = read_addr(addr)?;
+
+        unsafe { libc::bind(fd.as_raw_fd(), addr.as_ptr(), addr.len) }
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Net))
+    }
+}
+
+export_syscall! {
+    unsafe extern fn ListenSocket(sock: HandlePtr<IOHandle>, backlog: u32) -> Result<()> {
+        let fd = socket_fd(sock, 0)?;
+
+        rustix::net::listen(fd, backlog.min(i32::MAX as u32) as i32).map_err(net_error)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn AcceptSocket(hdl_out: UserPtrMut<HandlePtr<IOHandle>>, sock: HandlePtr<IOHandle>, peer_out: UserPtrMut<SocketAddress>, flags: u32) -> Result<()> {
+        hdl_out.write(HandlePtr::null())?;
+
+        let fd = socket_fd(sock, HANDLE_RIGHT_READ)?;
+        let sflags = socket_flags(flags)?;
+
+        let mut peer = SockaddrStorage::empty();
+
+        let conn = unsafe { libc::accept4(fd.as_raw_fd(), peer.as_mut_ptr(), &mut peer.len, sflags.bits()) }
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Net))?;
+        let conn = unsafe { OwnedFd::from_raw_fd(conn) };
+
+        if !peer_out.is_null() {
+            peer_out.write(peer.to_lilium()?)?;
+        }
+
+        store_socket(conn, hdl_out)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn ConnectSocket(sock: HandlePtr<IOHandle>, addr: UserPtr<SocketAddress>) -> Result<()> {
+        // A nonblocking socket returns `WouldBlock` while the connection is in progress, and can be waited on with `AwaitHandles`
+        let fd = socket_fd(sock, 0)?;
+        let addr = read_addr(addr)?;
+
+        unsafe { libc::connect(fd.as_raw_fd(), addr.as_ptr(), addr.len) }
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Net))
+    }
+}
+
+export_syscall! {
+    unsafe extern fn SendSocket(sock: HandlePtr<IOHandle>, buf: UserPtr<c_void>, len: usize, dest: UserPtr<SocketAddress>) -> Result<usize> {
+        let fd = socket_fd(sock, HANDLE_RIGHT_WRITE)?;
+        let dest = dest.read_opt()?.map(|dest| SockaddrStorage::from_lilium(&dest)).transpose()?;
+
+        let (addr, addrlen) = match &dest {
+            Some(dest) => (dest.as_ptr(), dest.len),
+            None => (core::ptr::null(), 0),
+        };
+
+        // The buffer is checked by the host, which fails with `EFAULT` (`InvalidMemory`)
+        unsafe { libc::sendto(fd.as_raw_fd(), buf.as_raw(), len, SendFlags::NOSIGNAL.bits(), addr, addrlen) }
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Net))
+    }
+}
+
+export_syscall! {
+    unsafe extern fn ReceiveSocket(sock: HandlePtr<IOHandle>, buf: UserPtrMut<c_void>, len: usize, src_out: UserPtrMut<SocketAddress>, flags: u32) -> Result<usize> {
+        let fd = socket_fd(sock, HANDLE_RIGHT_READ)?;
+
+        if (flags & !(RECEIVE_FLAG_PEEK | RECEIVE_FLAG_WAIT_ALL | RECEIVE_FLAG_DONT_WAIT)) != 0 {
+            return Err(Error::InvalidOption);
+        }
+
+        let mut rflags = RecvFlags::empty();
+        if (flags & RECEIVE_FLAG_PEEK) != 0 {
+            rflags |= RecvFlags::PEEK;
+        }
+        if (flags & RECEIVE_FLAG_WAIT_ALL) != 0 {
+            rflags |= RecvFlags::WAITALL;
+        }
+        if (flags & RECEIVE_FLAG_DONT_WAIT) != 0 {
+            rflags |= RecvFlags::DONTWAIT;
+        }
+
+        let mut src = SockaddrStorage::empty();
+
+        // The buffer is checked by the host, which fails with `EFAULT` (`InvalidMemory`)
+        let n = unsafe { libc::recvfrom(fd.as_raw_fd(), buf.as_raw(), len, rflags.bits(), src.as_mut_ptr(), &mut src.len) }
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Net))?;
+
+        if !src_out.is_null() {
+            src_out.write(src.to_lilium()?)?;
+        }
+
+        Ok(n)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn ShutdownSocket(sock: HandlePtr<IOHandle>, how: u32) -> Result<()> {
+        let fd = socket_fd(sock, 0)?;
+
+        let how = match how {
+            SHUTDOWN_READ => Shutdown::Read,
+            SHUTDOWN_WRITE => Shutdown::Write,
+            SHUTDOWN_BOTH => Shutdown::Both,
+            _ => return Err(Error::InvalidOption),
+        };
+
+        rustix::net::shutdown(fd, how).map_err(net_error)
+    }
+}
+
+fn timeout_ns(timeout: Option<Duration>) -> u64 {
+    timeout.map_or(0, |d| d.as_nanos().min(u64::MAX as u128) as u64)
+}
+
+fn ns_timeout(ns: u64) -> Option<Duration> {
+    (ns != 0).then(|| Duration::from_nanos(ns))
+}
+
+export_syscall! {
+    unsafe extern fn GetSocketOption(sock: HandlePtr<IOHandle>, opt: u32, val_out: UserPtrMut<u64>) -> Result<()> {
+        let fd = socket_fd(sock, 0)?;
+
+        let val = match opt {
+            SOCKET_OPTION_REUSE_ADDRESS => sockopt::socket_reuseaddr(fd).map(u64::from),
+            SOCKET_OPTION_KEEP_ALIVE => sockopt::socket_keepalive(fd).map(u64::from),
+            SOCKET_OPTION_NO_DELAY => sockopt::tcp_nodelay(fd).map(u64::from),
+            SOCKET_OPTION_BROADCAST => sockopt::socket_broadcast(fd).map(u64::from),
+            SOCKET_OPTION_RECEIVE_BUFFER_SIZE => sockopt::socket_recv_buffer_size(fd).map(|v| v as u64),
+            SOCKET_OPTION_SEND_BUFFER_SIZE => sockopt::socket_send_buffer_size(fd).map(|v| v as u64),
+            SOCKET_OPTION_RECEIVE_TIMEOUT => sockopt::socket_timeout(fd, sockopt::Timeout::Recv).map(timeout_ns),
+            SOCKET_OPTION_SEND_TIMEOUT => sockopt::socket_timeout(fd, sockopt::Timeout::Send).map(timeout_ns),
+            SOCKET_OPTION_IPV6_ONLY => sockopt::ipv6_v6only(fd).map(u64::from),
+            _ => return Err(Error::InvalidOption),
+        }
+        .map_err(net_error)?;
+
+        val_out.write(val)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn SetSocketOption(sock: HandlePtr<IOHandle>, opt: u32, val: u64) -> Result<()> {
+        let fd = socket_fd(sock, 0)?;
+        let size = usize::try_from(val).map_err(|_| Error::InvalidOption);
+
+        match opt {
+            SOCKET_OPTION_REUSE_ADDRESS => sockopt::set_socket_reuseaddr(fd, val != 0),
+            SOCKET_OPTION_KEEP_ALIVE => sockopt::set_socket_keepalive(fd, val != 0),
+            SOCKET_OPTION_NO_DELAY => sockopt::set_tcp_nodelay(fd, val != 0),
+            SOCKET_OPTION_BROADCAST => sockopt::set_socket_broadcast(fd, val != 0),
+            SOCKET_OPTION_RECEIVE_BUFFER_SIZE => sockopt::set_socket_recv_buffer_size(fd, size?),
+            SOCKET_OPTION_SEND_BUFFER_SIZE => sockopt::set_socket_send_buffer_size(fd, size?),
+            SOCKET_OPTION_RECEIVE_TIMEOUT => sockopt::set_socket_timeout(fd, sockopt::Timeout::Recv, ns_timeout(val)),
+            SOCKET_OPTION_SEND_TIMEOUT => sockopt::set_socket_timeout(fd, sockopt::Timeout::Send, ns_timeout(val)),
+            SOCKET_OPTION_IPV6_ONLY => sockopt::set_ipv6_v6only(fd, val != 0),
+            _ => return Err(Error::InvalidOption),
+        }
+        .map_err(net_error)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn GetSocketAddress(sock: HandlePtr<IOHandle>, addr_out: UserPtrMut<SocketAddress>) -> Result<()> {
+        let fd = socket_fd(sock, 0)?;
+        let mut addr = SockaddrStorage::empty();
+
+        unsafe { libc::getsockname(fd.as_raw_fd(), addr.as_mut_ptr(), &mut addr.len) }
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Net))?;
+
+        addr_out.write(addr.to_lilium()?)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn GetSocketPeerAddress(sock: HandlePtr<IOHandle>, addr_out: UserPtrMut<SocketAddress>) -> Result<()> {
+        let fd = socket_fd(sock, 0)?;
+        let mut addr = SockaddrStorage::empty();
+
+        unsafe { libc::getpeername(fd.as_raw_fd(), addr.as_mut_ptr(), &mut addr.len) }
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Net))?;
+
+        addr_out.write(addr.to_lilium()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lilium_sys::sys::result::SysResult;
+
+    use super::*;
+
+    const LOCALHOST: [u8; 4] = [127, 0, 0, 1];
+
+    fn check(res: SysResult) -> usize {
+        assert!(res >= 0, "syscall failed with {res}");
+        res as usize
+    }
+
+    fn create(family: u16, kind: u32) -> HandlePtr<IOHandle> {
+        let mut sock = HandlePtr::null();
+        check(unsafe { CreateSocket(UserPtrMut::from_raw(&mut sock), family, kind, 0) });
+        sock
+    }
+
+    fn close(sock: HandlePtr<IOHandle>) {
+        unsafe { Handle::try_deref(sock.cast()) }
+            .unwrap()
+            .close(false);
+    }
+
+    fn bind(sock: HandlePtr<IOHandle>, addr: &SocketAddress) {
+        check(unsafe { BindSocket(sock, UserPtr::from_raw(addr)) });
+    }
+
+    fn listen(sock: HandlePtr<IOHandle>) {
+        check(unsafe { ListenSocket(sock, 1) });
+    }
+
+    fn connect(sock: HandlePtr<IOHandle>, addr: &SocketAddress) {
+        check(unsafe { ConnectSocket(sock, UserPtr::from_raw(addr)) });
+    }
+
+    fn accept(sock: HandlePtr<IOHandle>, peer: &mut SocketAddress) -> HandlePtr<IOHandle> {
+        let mut conn = HandlePtr::null();
+        check(unsafe {
+            AcceptSocket(
+                UserPtrMut::from_raw(&mut conn),
+                sock,
+                UserPtrMut::from_raw(peer),
+                0,
+            )
+        });
+        conn
+    }
+
+    fn local_addr(sock: HandlePtr<IOHandle>) -> SocketAddress {
+        let mut addr = bytemuck::zeroed();
+        check(unsafe { GetSocketAddress(sock, UserPtrMut::from_raw(&mut addr)) });
+        addr
+    }
+
+    fn peer_addr(sock: HandlePtr<IOHandle>) -> SocketAddress {
+        let mut addr = bytemuck::zeroed();
+        check(unsafe { GetSocketPeerAddress(sock, UserPtrMut::from_raw(&mut addr)) });
+        addr
+    }
+
+    fn send(sock: HandlePtr<IOHandle>, data: &[u8], dest: Option<&SocketAddress>) -> usize {
+        let dest = dest.map_or(core::ptr::null(), core::ptr::from_ref);
+        check(unsafe {
+            SendSocket(
+                sock,
+                UserPtr::from_raw(data.as_ptr().cast()),
+                data.len(),
+                UserPtr::from_raw(dest),
+            )
+        })
+    }
+
+    fn receive(
+        sock: HandlePtr<IOHandle>,
+        buf: &mut [u8],
+        src: Option<&mut SocketAddress>,
+    ) -> usize {
+        let src = src.map_or(core::ptr::null_mut(), core::ptr::from_mut);
+        check(unsafe {
+            ReceiveSocket(
+                sock,
+                UserPtrMut::from_raw(buf.as_mut_ptr().cast()),
+                buf.len(),
+                UserPtrMut::from_raw(src),
+                0,
+            )
+        })
+    }
+
+    fn assert_same_addr(a: &SocketAddress, b: &SocketAddress) {
+        assert_eq!(bytemuck::bytes_of(a), bytemuck::bytes_of(b));
+    }
+
+    #[test]
+    fn tcp_loopback() {
+        let listener = create(ADDRESS_FAMILY_INET4, SOCKET_KIND_STREAM);
+        bind(listener, &SocketAddress::inet4(LOCALHOST, 0));
+        listen(listener);
+
+        let addr = local_addr(listener);
+        assert_eq!(addr.family, ADDRESS_FAMILY_INET4);
+        assert_eq!(addr.addr[..4], LOCALHOST);
+        assert_ne!(addr.port, 0);
+
+        let client = create(ADDRESS_FAMILY_INET4, SOCKET_KIND_STREAM);
+        connect(client, &addr);
+
+        let mut peer = bytemuck::zeroed();
+        let conn = accept(listener, &mut peer);
+        assert_same_addr(&peer, &local_addr(client));
+        assert_same_addr(&peer_addr(client), &addr);
+
+        assert_eq!(send(client, b"hello", None), 5);
+        let mut buf = [0u8; 16];
+        assert_eq!(receive(conn, &mut buf, None), 5);
+        assert_eq!(&buf[..5], b"hello");
+
+        check(unsafe { ShutdownSocket(client, SHUTDOWN_WRITE) });
+        assert_eq!(receive(conn, &mut buf, None), 0);
+
+        close(conn);
+        close(client);
+        close(listener);
+    }
+
+    #[test]
+    fn udp_loopback() {
+        let first = create(ADDRESS_FAMILY_INET4, SOCKET_KIND_DATAGRAM);
+        let second = create(ADDRESS_FAMILY_INET4, SOCKET_KIND_DATAGRAM);
+        bind(first, &SocketAddress::inet4(LOCALHOST, 0));
+        bind(second, &SocketAddress::inet4(LOCALHOST, 0));
+
+        let first_addr = local_addr(first);
+        let second_addr = local_addr(second);
+
+        assert_eq!(send(first, b"ping", Some(&second_addr)), 4);
+
+        let mut src = bytemuck::zeroed();
+        let mut buf = [0u8; 16];
+        assert_eq!(receive(second, &mut buf, Some(&mut src)), 4);
+        assert_eq!(&buf[..4], b"ping");
+        assert_same_addr(&src, &first_addr);
+
+        close(first);
+        close(second);
+    }
+
+    #[test]
+    fn unix_seqpacket_loopback() {
+        // An abstract socket, so that nothing is left behind in the file system
+        let name = alloc::format!("\0wl-usi-net-test-{}", std::process::id());
+        let mut addr: SocketAddress = bytemuck::zeroed();
+        addr.family = ADDRESS_FAMILY_UNIX;
+        addr.path[..name.len()].copy_from_slice(name.as_bytes());
+        addr.path_len = name.len() as u32;
+
+        let listener = create(ADDRESS_FAMILY_UNIX, SOCKET_KIND_SEQPACKET);
+        bind(listener, &addr);
+        listen(listener);
+        assert_same_addr(&local_addr(listener), &addr);
+
+        let client = create(ADDRESS_FAMILY_UNIX, SOCKET_KIND_SEQPACKET);
+        connect(client, &addr);
+
+        let mut peer = bytemuck::zeroed();
+        let conn = accept(listener, &mut peer);
+        assert_same_addr(&peer_addr(client), &addr);
+
+        // Message boundaries are preserved
+        assert_eq!(send(client, b"one", None), 3);
+        assert_eq!(send(client, b"two", None), 3);
+        let mut buf = [0u8; 16];
+        assert_eq!(receive(conn, &mut buf, None), 3);
+        assert_eq!(&buf[..3], b"one");
+        assert_eq!(receive(conn, &mut buf, None), 3);
+        assert_eq!(&buf[..3], b"two");
+
+        close(conn);
+        close(client);
+        close(listener);
+    }
+
+    #[test]
+    fn create_rejects_unknown_family_and_kind() {
+        let mut sock = HandlePtr::null();
+        let res =
+            unsafe { CreateSocket(UserPtrMut::from_raw(&mut sock), 99, SOCKET_KIND_STREAM, 0) };
+        assert!(res < 0);
+        let res =
+            unsafe { CreateSocket(UserPtrMut::from_raw(&mut sock), ADDRESS_FAMILY_INET4, 99, 0) };
+        assert!(res < 0);
+    }
+}
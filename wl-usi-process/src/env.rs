@@ -0,0 +1,75 @@
+use lilium_sys::{
+    result::{Error, Result},
+    sys::kstr::KStrPtr,
+};
+use wl_impl::{
+    env::{environment, is_hidden_var, is_valid_var},
+    export_syscall,
+    helpers::fill_str,
+    user_ptr::{UserPtrMut, UserStr},
+};
+
+/// Copies `st` into the string pointed to by `out`, updating its length to the length of `st`
+pub(crate) fn write_str(out: UserPtrMut<KStrPtr>, st: &str) -> Result<()> {
+    let mut kstr = out.read()?;
+    let res = unsafe { fill_str(&mut kstr, st) };
+    out.write(kstr)?;
+    res
+}
+
+export_syscall! {
+    unsafe extern fn GetEnvironmentVariable(key: UserStr, val: UserPtrMut<KStrPtr>) -> Result<()> {
+        let key = key.as_str()?;
+        let env = environment().read();
+        let st = env.var(key).ok_or(Error::DoesNotExist)?;
+
+        write_str(val, st)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn SetEnvironmentVariable(key: UserStr, val: UserStr) -> Result<()> {
+        let key = key.as_str()?;
+        let val = val.as_str()?;
+
+        if !is_valid_var(key, val) {
+            return Err(Error::InvalidString);
+        }
+
+        if is_hidden_var(key) {
+            return Err(Error::Permission);
+        }
+
+        environment().write().set_var(key, val);
+
+        Ok(())
+    }
+}
+
+export_syscall! {
+    unsafe extern fn ClearEnvironmentVariable(key: UserStr) -> Result<()> {
+        let key = key.as_str()?;
+
+        if is_hidden_var(key) {
+            return Err(Error::Permission);
+        }
+
+        environment().write().remove_var(key);
+
+        Ok(())
+    }
+}
+
+export_syscall! {
+    unsafe extern fn EnumerateEnvironment(pos: usize, key: UserPtrMut<KStrPtr>, val: UserPtrMut<KStrPtr>) -> Result<usize> {
+        // `pos` is 0 for the first variable, and otherwise the value returned by the previous call.
+        // If the strings are too short, both lengths are still updated, and the same `pos` can be used again.
+        let env = environment().read();
+        let (k, v, next) = env.entry_at(pos).ok_or(Error::DoesNotExist)?;
+
+        let res = write_str(key, k);
+        write_str(val, v).and(res)?;
+
+        Ok(next)
+    }
+}
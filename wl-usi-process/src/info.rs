@@ -0,0 +1,88 @@
+use lilium_sys::{
+    result::{Error, Result},
+    sys::{
+        handle::{HANDLE_TYPE_PROC, HandlePtr},
+        kstr::KStrPtr,
+        process::ProcessHandle,
+    },
+};
+use rustix::{
+    fd::IntoRawFd,
+    process::{Pid, PidfdFlags, getpid, getppid, pidfd_open},
+};
+use wl_impl::{
+    export_syscall,
+    handle_base::{HANDLE_RIGHTS_ALL, Handle, insert_handle},
+    helpers::{ErrorContext, fill_str, rustix_error_to_lilium},
+    proc_info::{args, exec_path},
+    user_ptr::{UserPtrMut, UserSliceMut},
+};
+
+use crate::env::write_str;
+
+/// Opens a process handle to `pid` and stores it in `hdl_out`
+fn open_process_handle(pid: Pid, hdl_out: UserPtrMut<HandlePtr<ProcessHandle>>) -> Result<()> {
+    // Check that the handle can be stored before opening the process
+    hdl_out.write(HandlePtr::null())?;
+
+    let pidfd = pidfd_open(pid, PidfdFlags::empty())
+        .map_err(|e| rustix_error_to_lilium(e, ErrorContext::Process))?;
+
+    let hdl = Handle {
+        ty: HANDLE_TYPE_PROC as usize,
+        blob1: core::ptr::null_mut(),
+        blob2: core::ptr::without_provenance_mut(pid.as_raw_nonzero().get() as usize),
+        fd: pidfd.into_raw_fd() as i64,
+        rights: HANDLE_RIGHTS_ALL,
+    };
+
+    let ptr = insert_handle(hdl)?;
+
+    hdl_out.write(ptr.cast()).inspect_err(|_| {
+        unsafe { Handle::deref_unchecked(ptr) }.close(false);
+    })
+}
+
+export_syscall! {
+    unsafe extern fn GetCurrentProcess(hdl_out: UserPtrMut<HandlePtr<ProcessHandle>>) -> Result<()> {
+        open_process_handle(getpid(), hdl_out)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn GetParentProcess(hdl_out: UserPtrMut<HandlePtr<ProcessHandle>>) -> Result<()> {
+        // The parent is outside of our pid namespace (or we are init)
+        let ppid = getppid().ok_or(Error::DoesNotExist)?;
+
+        open_process_handle(ppid, hdl_out)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn GetExecutablePath(path_out: UserPtrMut<KStrPtr>) -> Result<()> {
+        let path = exec_path();
+
+        if path.is_empty() {
+            return Err(Error::DoesNotExist);
+        }
+
+        write_str(path_out, path)
+    }
+}
+
+export_syscall! {
+    unsafe extern fn GetProcessArguments(args_out: UserSliceMut<KStrPtr>) -> Result<usize> {
+        // Returns the total number of arguments. Only the first `args_out.len` arguments are written.
+        // If a string is too short, the remaining arguments are still written, and the error is returned after.
+        let args = args();
+        let mut args_out = args_out;
+        let mut res = Ok(());
+
+        for (out, arg) in args_out.iter_mut()?.zip(args) {
+            let out = out?;
+            res = res.and(unsafe { fill_str(out, arg) });
+        }
+
+        res.map(|()| args.len())
+    }
+}
@@ -1,43 +1,50 @@
 #![no_std]
 #![feature(never_type, unwrap_infallible)]
-use exit::ExitProcess;
-use lilium_sys::uuid::parse_uuid;
-use mem::*;
-use wl_impl::{
-    erase,
-    helpers::insert_elems,
-    syscall_handler::{SubsysInfo, register_subsys},
-    syscall_helpers::SysCallTyErased,
-    wl_init_subsystem_name,
+use wl_impl::def_subsystem;
+
+/// Syscall numbers generated from the `.knum` files of the subsystem
+#[allow(non_upper_case_globals)]
+mod sysno {
+    include!(concat!(env!("OUT_DIR"), "/sysno.rs"));
+}
+
+use sysno::{
+    SYS_ChangeMappingAttributes, SYS_ClearEnvironmentVariable, SYS_CreateMapping,
+    SYS_CreateProcess, SYS_EnumerateEnvironment, SYS_ExitProcess, SYS_GetCurrentProcess,
+    SYS_GetEnvironmentVariable, SYS_GetExecutablePath, SYS_GetParentProcess,
+    SYS_GetProcessArguments, SYS_JoinProcess, SYS_RemoveMapping, SYS_ResizeMapping,
+    SYS_SetEnvironmentVariable,
 };
 
 extern crate alloc;
 
-static SYSCALLS: [Option<SysCallTyErased>; 4096] = insert_elems(
-    [None; 4096],
-    [
-        (0, erase!(ExitProcess)),
-        (0x30, erase!(CreateMapping)),
-        (0x31, erase!(ChangeMappingAttributes)),
-        (0x32, erase!(RemoveMapping)),
-        (0x33, erase!(ResizeMapping)),
-    ],
-);
-
-static INFO: SubsysInfo = SubsysInfo {
+def_subsystem! {
     name: "process",
-    uuid: parse_uuid("2bf86506-9b4a-5065-ac9e-ad6d21027460"),
-    subsys_version: 0,
-    max_sysno: 128,
-};
-
-#[unsafe(export_name = wl_init_subsystem_name!())]
-unsafe extern "C" fn init_subsystem() {
-    unsafe {
-        register_subsys(3, &SYSCALLS, &INFO);
-    }
+    uuid: "2bf86506-9b4a-5065-ac9e-ad6d21027460",
+    number: 3,
+    version: (1, 0, 0),
+    declared: sysno::DECLARED_SYSCALLS,
+    syscalls: [
+        SYS_ExitProcess => exit::ExitProcess,
+        SYS_CreateProcess => proc::CreateProcess,
+        SYS_JoinProcess => proc::JoinProcess,
+        SYS_CreateMapping => mem::CreateMapping,
+        SYS_ChangeMappingAttributes => mem::ChangeMappingAttributes,
+        SYS_RemoveMapping => mem::RemoveMapping,
+        SYS_ResizeMapping => mem::ResizeMapping,
+        SYS_GetEnvironmentVariable => env::GetEnvironmentVariable,
+        SYS_SetEnvironmentVariable => env::SetEnvironmentVariable,
+        SYS_ClearEnvironmentVariable => env::ClearEnvironmentVariable,
+        SYS_EnumerateEnvironment => env::EnumerateEnvironment,
+        SYS_GetCurrentProcess => info::GetCurrentProcess,
+        SYS_GetParentProcess => info::GetParentProcess,
+        SYS_GetExecutablePath => info::GetExecutablePath,
+        SYS_GetProcessArguments => info::GetProcessArguments,
+    ],
 }
 
+mod env;
 mod exit;
+mod info;
 mod mem;
 mod proc;
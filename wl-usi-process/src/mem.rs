@@ -7,13 +7,14 @@ use lilium_sys::{
 };
 use wl_impl::{
     export_syscall,
-    helpers::{linux_error_to_lilium, read_checked, write_checked},
+    helpers::{ErrorContext, linux_error_to_lilium_in},
     libc::{self, mmap, mprotect, mremap, munmap},
+    user_ptr::{UserPtr, UserPtrMut},
 };
 
 export_syscall! {
-    unsafe extern fn CreateMapping(base_addr: *mut *mut c_void, page_count: isize, map_attrs: u32, map_kind: u32, _map_ext: *const KCSlice<sys::MapExtendedAttr>) -> Result<()> {
-        let hint_addr = unsafe { base_addr.read() };
+    unsafe extern fn CreateMapping(base_addr: UserPtrMut<*mut c_void>, page_count: isize, map_attrs: u32, map_kind: u32, _map_ext: UserPtr<KCSlice<sys::MapExtendedAttr>>) -> Result<()> {
+        let hint_addr = base_addr.read()?;
 
         let mut linux_prot = 0;
         let mut linux_flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
@@ -47,38 +48,39 @@ export_syscall! {
             linux_prot |= libc::PROT_NONE;
         }
 
-        let ptr = unsafe { mmap(hint_addr, (page_count * 4095) as usize, linux_prot, linux_flags, -1, 0)}
-            .map_err(linux_error_to_lilium)?;
-        unsafe { core::ptr::write(base_addr, ptr); }
+        let ptr = unsafe { mmap(hint_addr, (page_count * 4096) as usize, linux_prot, linux_flags, -1, 0)}
+            .map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Memory))?;
+        if let Err(e) = base_addr.write(ptr) {
+            let _ = unsafe { munmap(ptr, (page_count * 4096) as usize) };
+            return Err(e);
+        }
         Ok(())
     }
 }
 
 export_syscall! {
-    unsafe extern fn RemoveMapping(base_addr: *mut c_void, page_count: isize) -> Result<()> {
-        unsafe { munmap(base_addr, (page_count * 4096) as usize).map_err(linux_error_to_lilium)?;}
+    unsafe extern fn RemoveMapping(base_addr: UserPtrMut<c_void>, page_count: isize) -> Result<()> {
+        unsafe { munmap(base_addr.as_raw(), (page_count * 4096) as usize).map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Memory))?;}
         Ok(())
     }
 }
 
 export_syscall! {
-    unsafe extern fn ResizeMapping(base_addr: *mut c_void, old_page_count: isize, new_addr: *mut *mut c_void, new_page_count: isize) -> Result<()> {
+    unsafe extern fn ResizeMapping(base_addr: UserPtrMut<c_void>, old_page_count: isize, new_addr: UserPtrMut<*mut c_void>, new_page_count: isize) -> Result<()> {
         let mut flags = 0;
         if !new_addr.is_null() {
             flags |= libc::MREMAP_MAYMOVE;
         }
 
-        let ptr = unsafe { mremap(base_addr, (old_page_count * 4096) as usize, (new_page_count * 4096) as usize, flags).map_err(linux_error_to_lilium)?};
-        if !new_addr.is_null() {
-            unsafe { write_checked(new_addr, ptr)?;}
-        }
+        let ptr = unsafe { mremap(base_addr.as_raw(), (old_page_count * 4096) as usize, (new_page_count * 4096) as usize, flags).map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Memory))?};
+        new_addr.write_opt(ptr)?;
 
         Ok(())
     }
 }
 
 export_syscall! {
-    unsafe extern fn ChangeMappingAttributes(base_addr: *mut c_void, page_count: isize, map_attrs: u32, _map_ext: *const KCSlice<sys::MapExtendedAttr>) -> Result<()> {
+    unsafe extern fn ChangeMappingAttributes(base_addr: UserPtrMut<c_void>, page_count: isize, map_attrs: u32, _map_ext: UserPtr<KCSlice<sys::MapExtendedAttr>>) -> Result<()> {
         let mut linux_prot = 0;
         if (map_attrs & !(sys::MAP_ATTR_READ | sys::MAP_ATTR_WRITE | sys::MAP_ATTR_EXEC | sys::MAP_ATTR_RESERVE)) != 0 {
             return Err(Error::InvalidOperation)
@@ -105,6 +107,6 @@ export_syscall! {
             linux_prot |= libc::PROT_NONE;
         }
 
-        unsafe { mprotect(base_addr, (page_count * 4096) as usize, linux_prot).map_err(linux_error_to_lilium) }
+        unsafe { mprotect(base_addr.as_raw(), (page_count * 4096) as usize, linux_prot).map_err(|e| linux_error_to_lilium_in(e, ErrorContext::Memory)) }
     }
 }
@@ -1,58 +1,156 @@
-use core::{
-    cell::{Cell, UnsafeCell},
-    ffi::c_char,
-    mem::MaybeUninit,
-};
-
 use alloc::{ffi::CString, string::ToString, vec::Vec};
 
 use rustix::{
-    fd::{AsFd, BorrowedFd, IntoRawFd},
-    net::{
-        AddressFamily, RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, SendAncillaryBuffer,
-        SendAncillaryMessage, SendFlags, SocketFlags, SocketType, recvmsg, sendmsg,
-    },
-    pipe::PipeFlags,
-    process::{PidfdFlags, WaitId, WaitIdOptions, getpid, pidfd_open, waitid},
-    thread::Pid,
+    fd::{BorrowedFd, OwnedFd},
+    io::fcntl_dupfd_cloexec,
+    pipe::{PipeFlags, pipe_with},
+    process::{WaitId, WaitIdOptions, waitid},
 };
 use wl_impl::{
     catch_signals::sig_to_except,
-    eprintln, export_syscall,
-    handle_base::{Handle, insert_handle},
-    helpers::{exit_unrecoverably, linux_error_to_lilium},
-    libc::{EINVAL, Error, close, execve, exit_group, fork},
+    env::child_environ,
+    export_syscall,
+    handle_base::{HANDLE_RIGHT_TRANSFER, HANDLE_RIGHTS_ALL, Handle, insert_handle},
+    helpers::{ErrorContext, exit_unrecoverably, linux_error_to_lilium_in, rustix_error_to_lilium},
+    libc::{
+        CLONE_PIDFD, CLONE_VFORK, CLONE_VM, Error, SIG_SETMASK, SIGCHLD, TIOCSCTTY, c_char, c_void,
+        clone_args, clone3_with_entry, close, dup3, execve, exit_group, ioctl, rt_sigprocmask,
+        setsid, write,
+    },
     ministd::AsRawFd,
-    println,
+    user_ptr::{UserPtr, UserPtrMut, UserSlice, UserStr},
 };
 
 use lilium_sys::{
     result::{Error as LiliumError, Result},
     sys::{
-        except::{self, ExceptionStatusInfo},
+        except::ExceptionStatusInfo,
         fs::FileHandle,
         handle::{self, HANDLE_TYPE_PROC, HandlePtr},
         kstr::{KCSlice, KStrCPtr},
-        option::OPTION_FLAG_IGNORE,
+        option::{ExtendedOptionHead, OPTION_FLAG_IGNORE},
         process::{self as sys, CreateProcessOption, ProcessHandle},
         thread::{JoinStatus, JoinStatusExit},
     },
+    uuid::{Uuid, parse_uuid},
 };
 
-unsafe extern "C" {
-    safe static __environ: Cell<*const *const c_char>;
+/// Starts the process in a new session, with init handle 0 (which must be a console) as its controlling terminal.
+///
+/// Without this, a process started on a pseudo-console receives keys like Ctrl+C as input, rather than as signals.
+pub const CREATE_PROCESS_OPTION_WL_CONTROLLING_CONSOLE: Uuid =
+    parse_uuid("225756c5-6e51-50e2-91b8-d68c5ec755a5");
+
+#[repr(C)]
+pub struct CreateProcessOptionWlControllingConsole {
+    pub head: ExtendedOptionHead,
 }
 
-#[repr(C, align(16))]
-struct Align16<T>(T);
+const _: () = assert!(
+    core::mem::size_of::<CreateProcessOptionWlControllingConsole>()
+        <= core::mem::size_of::<CreateProcessOption>()
+);
+
+/// The maximum number of init handles that can be passed to a process, which is the number the loader accepts
+const MAX_INIT_HANDLES: usize = 64;
+
+/// The size of the stack that `spawn_child` runs on
+const CHILD_STACK_SIZE: usize = 64 * 1024;
+
+/// Everything the child needs between `clone3` and `execve`.
+///
+/// This is prepared before the process is cloned, so that the child does not need to allocate or take any locks.
+struct SpawnPlan {
+    exec_path: CString,
+    argv: Vec<*const c_char>,
+    envp: Vec<*const c_char>,
+    /// Pairs of (source, target) fds. The sources are `CLOEXEC` duplicates that don't overlap any target.
+    remaps: Vec<(OwnedFd, i32)>,
+    /// Standard streams (fds 0-2) that aren't targets of `remaps` and must be closed in the child
+    close_std: [bool; 3],
+    /// Whether the child starts a new session, and makes fd 0 its controlling terminal
+    controlling_console: bool,
+    /// The write end of the pipe that the child reports errors on
+    err_pipe: i32,
+    /// The signal mask of the parent, which the child restores before `execve`
+    sigmask: u64,
+}
+
+/// Runs in the child after `clone3`, on its own stack. Only raw syscalls may be used here.
+///
+/// The child shares memory with the parent (which is suspended until the child calls `execve` or exits),
+///  so it must not write to anything but its own stack. `plan` points to a [`SpawnPlan`] that is only read.
+///
+/// If this fails, the errno is written to `err_pipe`, and the child exits.
+unsafe extern "C" fn spawn_child(plan: *mut c_void) -> ! {
+    let plan = unsafe { &*plan.cast::<SpawnPlan>() };
+    let err_pipe = plan.err_pipe;
+
+    let fail = |e: Error| -> ! {
+        let errno = e.get();
+        let _ = unsafe { write(err_pipe, (&raw const errno).cast(), size_of_val(&errno)) };
+        let _ = unsafe { exit_group(127) };
+        exit_unrecoverably(None)
+    };
+
+    for (src, target) in &plan.remaps {
+        // dup3 clears `CLOEXEC` on the new fd, so only the remapped fd is inherited
+        if let Err(e) = unsafe { dup3(src.as_raw_fd(), *target, 0) } {
+            fail(e)
+        }
+    }
+
+    for (fd, close) in plan.close_std.into_iter().enumerate() {
+        if close {
+            let _ = unsafe { close(fd as i32) };
+        }
+    }
+
+    if plan.controlling_console {
+        // Only the leader of a session without a controlling terminal can acquire one
+        if let Err(e) = unsafe { setsid() } {
+            fail(e)
+        }
+
+        if let Err(e) = unsafe { ioctl(0, TIOCSCTTY, 0) } {
+            fail(e)
+        }
+    }
+
+    // Signals stay blocked until just before `execve`, so that handlers (which share memory with the parent) almost never run in the child
+    if let Err(e) = unsafe {
+        rt_sigprocmask(
+            SIG_SETMASK as i32,
+            &plan.sigmask,
+            core::ptr::null_mut(),
+            size_of::<u64>(),
+        )
+    } {
+        fail(e)
+    }
+
+    let err = unsafe {
+        execve(
+            plan.exec_path.as_ptr(),
+            plan.argv.as_ptr(),
+            plan.envp.as_ptr(),
+        )
+    }
+    .into_err();
+
+    fail(err)
+}
 
 export_syscall! {
-    unsafe extern fn CreateProcess(hdl_out: *mut HandlePtr<ProcessHandle>, resolution_base: HandlePtr<FileHandle>, path: *const KStrCPtr, options: *const KCSlice<CreateProcessOption>) -> Result<()> {
+    unsafe extern fn CreateProcess(hdl_out: UserPtrMut<HandlePtr<ProcessHandle>>, resolution_base: HandlePtr<FileHandle>, path: UserPtr<KStrCPtr>, options: UserPtr<KCSlice<CreateProcessOption>>) -> Result<()> {
+        // Check that the handle can be stored before starting the process
+        hdl_out.write(HandlePtr::null())?;
 
-        let path = unsafe { (*path).as_str()};
+        let path = unsafe { UserStr::from_raw(path.read()?) };
+        let path = path.as_str()?;
 
         let exec_path = if resolution_base == HandlePtr::null() || path.starts_with('/') {
-            unsafe { path.to_string() }
+            path.to_string()
         } else {
             let fhdl = unsafe { Handle::try_deref(resolution_base.cast())? };
             fhdl.check_type(handle::HANDLE_SUBTYPE_IO_FILE as usize, 0)?;
@@ -70,15 +168,46 @@ export_syscall! {
 
         let mut args_specified = false;
 
-        for opt in unsafe{ (*options).as_slice() } {
+        let mut init_fds = None::<Vec<(BorrowedFd, u32)>>;
+
+        let mut controlling_console = false;
+
+        let options = match options.read_opt()? {
+            Some(options) => unsafe { UserSlice::from_raw(options) },
+            None => UserSlice::empty(),
+        };
+
+        for opt in options.iter()? {
+            let opt = opt?;
             match unsafe { opt.head.ty } {
                 sys::CREATE_PROCESS_OPTION_ARGS => {
-                    let provided_args = unsafe {&opt.args};
+                    let provided_args = unsafe { UserSlice::from_raw(opt.args.arguments) };
                     args_specified = true;
 
-                    for arg in unsafe { provided_args.arguments.as_slice() } {
-                        args.push(CString::new(unsafe { arg.as_str() }).map_err(|_| LiliumError::InvalidString)?)
+                    for arg in provided_args.iter()? {
+                        let arg = unsafe { UserStr::from_raw(*arg?) };
+                        args.push(CString::new(arg.as_str()?).map_err(|_| LiliumError::InvalidString)?)
+                    }
+                }
+                sys::CREATE_PROCESS_OPTION_INIT_HANDLES => {
+                    let provided_handles = unsafe { UserSlice::from_raw(opt.init_handles.handles) };
+
+                    if provided_handles.len() > MAX_INIT_HANDLES {
+                        return Err(LiliumError::ResourceLimitExhausted)
+                    }
+
+                    let mut fds = Vec::with_capacity(provided_handles.len());
+
+                    for hdl in provided_handles.iter()? {
+                        let hdl = unsafe { Handle::try_deref((*hdl?).cast())? };
+                        hdl.check_rights(HANDLE_RIGHT_TRANSFER)?;
+                        fds.push((hdl.borrow_fd().ok_or(LiliumError::UnsupportedOperation)?, hdl.rights));
                     }
+
+                    init_fds = Some(fds);
+                }
+                CREATE_PROCESS_OPTION_WL_CONTROLLING_CONSOLE => {
+                    controlling_console = true;
                 }
                 _ => {
                     if (unsafe { opt.head.flags } & OPTION_FLAG_IGNORE) == 0 {
@@ -92,96 +221,142 @@ export_syscall! {
             args.push(CString::new(path).unwrap())
         }
 
-
         let mut argv = args.iter()
             .map(|v| v.as_ptr())
             .collect::<Vec<_>>();
         argv.push(core::ptr::null());
 
-        let (read, write) = rustix::net::socketpair(AddressFamily::UNIX, SocketType::SEQPACKET, SocketFlags::CLOEXEC, None)
-            .unwrap();
-        let _ = rustix::net::shutdown(&read, rustix::net::Shutdown::Write);
-        let _ = rustix::net::shutdown(&write, rustix::net::Shutdown::Read);
-        const SPACE_NEEDED: usize = rustix::cmsg_space!(ScmRights(1));
-        let mut buf = Align16([const { MaybeUninit::uninit() }; SPACE_NEEDED]);
+        let mut env = child_environ();
 
-        let hdl = Handle {ty: HANDLE_TYPE_PROC as usize, blob1: core::ptr::null_mut(), blob2: core::ptr::null_mut(), fd: -1};
+        // Init handles after the standard streams are found by the child's loader through `WL_INIT_HANDLES`.
+        // This process's own value must not be inherited, since the fds it names are not passed on.
+        env.retain(|var| !var.as_bytes().starts_with(b"WL_INIT_HANDLES="));
 
-        let ptr = insert_handle(hdl)?;
-
-        let mut hdl = unsafe { Handle::deref_unchecked(ptr) };
-        match unsafe { fork() } {
-            Ok(0) => {
-                let fd = pidfd_open(getpid(), PidfdFlags::empty()).unwrap_or_else(|e| { let _ = rustix::io::write(&write, bytemuck::bytes_of(&(e.raw_os_error() as u16))); exit_unrecoverably(None)});
-                let msg = SendAncillaryMessage::ScmRights(&[fd.as_fd()]);
-                let mut buf = SendAncillaryBuffer::new(&mut buf.0);
-                if !buf.push(msg) {
-                    let _ = rustix::io::write(&write, bytemuck::bytes_of(&EINVAL.get()));
-                    exit_unrecoverably(None)
+        if let Some(init_fds) = &init_fds && init_fds.len() > 3 {
+            let mut var = "WL_INIT_HANDLES=".to_string();
+            for fd in 3..init_fds.len() {
+                if fd != 3 {
+                    var.push(':');
                 }
-                sendmsg(&write, &[], &mut buf, SendFlags::empty()).unwrap_or_else(|e| { let _ = rustix::io::write(&write, bytemuck::bytes_of(&(e.raw_os_error() as u16))); exit_unrecoverably(None)});
-                let err = unsafe { execve(exec_path.as_ptr(), argv.as_ptr(), __environ.get())}.into_err();
+                var += &fd.to_string();
+            }
+            env.push(CString::new(var).unwrap());
+        }
 
-                let errno = err.get();
+        // The child derives the rights of its init handles from the fds, so it also needs the rights of the handles given here.
+        // If no init handles are given, the standard streams are inherited, and so are their rights from this process's own value.
+        if let Some(init_fds) = &init_fds {
+            env.retain(|var| !var.as_bytes().starts_with(b"WL_INIT_HANDLE_RIGHTS="));
 
-                let _ = rustix::io::write(write, bytemuck::bytes_of(&errno));
-                exit_unrecoverably(None)
+            let mut var = "WL_INIT_HANDLE_RIGHTS=".to_string();
+            for (n, (_, rights)) in init_fds.iter().enumerate() {
+                if n != 0 {
+                    var.push(':');
+                }
+                var += &alloc::format!("{rights:x}");
             }
-            Ok(pid) => {
-                let mut n = 0u16;
-                let mut buf = RecvAncillaryBuffer::new(&mut buf.0);
-                let mut waittarg = WaitId::Pid(unsafe { Pid::from_raw_unchecked(pid) });
-                let _ = recvmsg(&read, &mut [], &mut buf, RecvFlags::CMSG_CLOEXEC)
-                    .map_err(|e| {hdl.close(false); let _ = waitid(waittarg.clone(), WaitIdOptions::EXITED); linux_error_to_lilium(unsafe { Error::new_unchecked(e.raw_os_error() as u16) })})?;
-
-                let msg = buf.drain().next();
-
-
-                let pidfd = match msg {
-                    Some(RecvAncillaryMessage::ScmRights(mut fd)) => {
-                        let pidfd = fd.next().unwrap();
-                        pidfd
-                    },
-                    _ => {hdl.close(false); let _ = waitid(waittarg, WaitIdOptions::EXITED); lilium_sys::result::Error::from_code(-0x802)?; unreachable!()}
-                };
-                let rawfd = pidfd.into_raw_fd();
-                waittarg = WaitId::PidFd(unsafe { BorrowedFd::borrow_raw(rawfd)});
-                hdl.fd = rawfd as i64;
-                hdl.blob2 = core::ptr::without_provenance_mut(pid as usize);
-                drop(write);
-                match rustix::io::read(read, bytemuck::bytes_of_mut(&mut n)) {
-                    Ok(1..) => {
-                        hdl.close(false);
-                        let _ = waitid(waittarg, WaitIdOptions::EXITED);
-                        return Err(Error::new(n).map_or(lilium_sys::result::Error::ResourceLimitExhausted, linux_error_to_lilium))
-                    }
-                    Ok(0) | Err(_) => {
+            env.push(CString::new(var).unwrap());
+        }
 
-                        unsafe { hdl_out.write(ptr.cast()); }
-                        Ok(())
-                    }
-                }
+        let mut envp = env.iter()
+            .map(|v| v.as_ptr())
+            .collect::<Vec<_>>();
+        envp.push(core::ptr::null());
+
+        // Init handle `n` becomes fd `n` in the child. If no init handles are given, the standard streams are inherited as-is.
+        let mut remaps = Vec::new();
+        let mut close_std = [false; 3];
+
+        if let Some(init_fds) = init_fds {
+            // Duplicate every source above the highest target first, so that no dup3 in the child clobbers a later source
+            let min_fd = init_fds.len().max(3) as i32;
+            for (target, (fd, _)) in init_fds.into_iter().enumerate() {
+                let src = fcntl_dupfd_cloexec(fd, min_fd)
+                    .map_err(|e| rustix_error_to_lilium(e, ErrorContext::Process))?;
+                remaps.push((src, target as i32));
             }
-            Err(e) => {
-                Err(linux_error_to_lilium(e))
+
+            for close in close_std.iter_mut().skip(remaps.len()) {
+                *close = true;
             }
         }
 
+        // Written to by the child with the errno if it fails before `execve` succeeds. Closed by `execve` otherwise.
+        let (err_read, err_write) = pipe_with(PipeFlags::CLOEXEC)
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::Process))?;
+
+        let mut plan = SpawnPlan { exec_path, argv, envp, remaps, close_std, controlling_console, err_pipe: err_write.as_raw_fd(), sigmask: 0 };
+
+        let mut stack = Vec::<u8>::with_capacity(CHILD_STACK_SIZE);
+
+        let hdl = Handle {ty: HANDLE_TYPE_PROC as usize, blob1: core::ptr::null_mut(), blob2: core::ptr::null_mut(), fd: -1, rights: HANDLE_RIGHTS_ALL};
+
+        let ptr = insert_handle(hdl)?;
+
+        let hdl = unsafe { Handle::deref_unchecked(ptr) };
+
+        let mut pidfd: i32 = -1;
+
+        let mut clone_args: clone_args = bytemuck::zeroed();
+        clone_args.flags = (CLONE_VM | CLONE_VFORK | CLONE_PIDFD) as u64;
+        clone_args.pidfd = (&raw mut pidfd).addr() as u64;
+        clone_args.exit_signal = SIGCHLD as u64;
+        clone_args.stack = stack.as_mut_ptr().addr() as u64;
+        clone_args.stack_size = CHILD_STACK_SIZE as u64;
+
+        // Block every signal until the child has restored the mask, since a handler in the child would run on memory shared with this process
+        let all_signals = !0u64;
+        if let Err(e) = unsafe { rt_sigprocmask(SIG_SETMASK as i32, &all_signals, &mut plan.sigmask, size_of::<u64>()) } {
+            hdl.close(false);
+            return Err(linux_error_to_lilium_in(e, ErrorContext::Process))
+        }
+
+        // With `CLONE_VFORK`, this thread doesn't resume until the child has either called `execve` or exited
+        let res = unsafe { clone3_with_entry(&mut clone_args, size_of::<clone_args>(), spawn_child, (&raw mut plan).cast()) };
+
+        let _ = unsafe { rt_sigprocmask(SIG_SETMASK as i32, &plan.sigmask, core::ptr::null_mut(), size_of::<u64>()) };
+
+        let pid = match res {
+            Ok(pid) => pid,
+            Err(e) => {
+                hdl.close(false);
+                return Err(linux_error_to_lilium_in(e, ErrorContext::Process))
+            }
+        };
+
+        hdl.fd = pidfd as i64;
+        hdl.blob2 = core::ptr::without_provenance_mut(pid as usize);
+
+        drop(plan);
+        drop(stack);
+        drop(err_write);
+
+        let mut n = 0u16;
+        match rustix::io::read(err_read, bytemuck::bytes_of_mut(&mut n)) {
+            Ok(1..) => {
+                let _ = waitid(WaitId::PidFd(unsafe { BorrowedFd::borrow_raw(pidfd) }), WaitIdOptions::EXITED);
+                hdl.close(false);
+                Err(Error::new(n).map_or(LiliumError::ResourceLimitExhausted, |e| linux_error_to_lilium_in(e, ErrorContext::Process)))
+            }
+            Ok(0) | Err(_) => {
+                hdl_out.write(ptr.cast())
+            }
+        }
     }
 }
 
 export_syscall! {
-    unsafe extern fn JoinProcess(hdl: HandlePtr<ProcessHandle>, status_out: *mut JoinStatus) -> Result<()> {
+    unsafe extern fn JoinProcess(hdl: HandlePtr<ProcessHandle>, status_out: UserPtrMut<JoinStatus>) -> Result<()> {
         let mut hdl = unsafe { Handle::try_deref(hdl.cast())? };
 
         hdl.check_type(HANDLE_TYPE_PROC as usize, 0)?;
 
 
 
-        let fd = hdl.borrow_fd().unwrap();
+        let fd = hdl.borrow_fd().ok_or(LiliumError::InvalidHandle)?;
 
         let status = waitid(WaitId::PidFd(fd), WaitIdOptions::EXITED)
-            .map_err(|e| linux_error_to_lilium(unsafe { Error::new_unchecked(e.raw_os_error() as u16) }))?
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::Process))?
             .unwrap();
         hdl.close(false);
         let status = if let Some(sig) = status.terminating_signal() {
@@ -196,8 +371,6 @@ export_syscall! {
             JoinStatus{exit_code: JoinStatusExit{exit_code: status as u64, ..bytemuck::zeroed()}}
         };
 
-        unsafe { status_out.write(status); }
-
-        Ok(())
+        status_out.write(status)
     }
 }
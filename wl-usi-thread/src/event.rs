@@ -11,16 +11,16 @@ use lilium_sys::{
     },
 };
 use rustix::thread::futex::{self, Flags};
-use wl_impl::{export_syscall, helpers::linux_error_to_lilium};
+use wl_impl::{export_syscall, helpers::linux_error_to_lilium, user_ptr::UserPtrMut};
 
 export_syscall! {
-    unsafe extern fn AwaitAddress(addr: *mut usize, current: *mut usize, ignore_mask: usize, options: KCSlice<AwaitAddrOption>) -> Result<()> {
+    unsafe extern fn AwaitAddress(addr: UserPtrMut<usize>, current: UserPtrMut<usize>, ignore_mask: usize, options: KCSlice<AwaitAddrOption>) -> Result<()> {
         todo!()
     }
 }
 
 export_syscall! {
-    unsafe extern fn NotifyAddress(addr: *mut usize, count: usize, wake_mask: usize, options: KCSlice<NotifyAddressOption>) -> Result<usize> {
+    unsafe extern fn NotifyAddress(addr: UserPtrMut<usize>, count: usize, wake_mask: usize, options: KCSlice<NotifyAddressOption>) -> Result<usize> {
         todo!()
     }
 }
@@ -1,29 +1,30 @@
 #![feature(never_type, pointer_is_aligned_to)]
 #![no_std]
-use lilium_sys::uuid::parse_uuid;
-use wl_impl::{
-    erase,
-    helpers::insert_elems,
-    syscall_handler::{SubsysInfo, register_subsys},
-    syscall_helpers::SysCallTyErased,
-    wl_init_subsystem_name,
-};
+use wl_impl::def_subsystem;
 
-static SYSCALLS: [Option<SysCallTyErased>; 4096] = insert_elems([None; 4096], []);
+/// Syscall numbers generated from the `.knum` files of the subsystem
+#[allow(non_upper_case_globals)]
+mod sysno {
+    include!(concat!(env!("OUT_DIR"), "/sysno.rs"));
+}
 
-static INFO: SubsysInfo = SubsysInfo {
-    name: "thread",
-    uuid: parse_uuid("f8ee4381-7db2-5c4b-bdd9-dad7f83412a4"),
-    subsys_version: 0,
-    max_sysno: 128,
-};
+use sysno::{SYS_AwaitAddress, SYS_AwaitHandles, SYS_ExitThread, SYS_NotifyAddress};
 
-#[unsafe(export_name = wl_init_subsystem_name!())]
-unsafe extern "C" fn init_subsystem() {
-    unsafe {
-        register_subsys(1, &SYSCALLS, &INFO);
-    }
+extern crate alloc;
+
+def_subsystem! {
+    name: "thread",
+    uuid: "f8ee4381-7db2-5c4b-bdd9-dad7f83412a4",
+    number: 1,
+    version: (1, 0, 0),
+    declared: sysno::DECLARED_SYSCALLS,
+    syscalls: [
+        SYS_ExitThread => exit::ExitThread,
+        SYS_AwaitHandles => wait::AwaitHandles,
+    ],
+    stubs: [SYS_AwaitAddress, SYS_NotifyAddress],
 }
 
 mod event;
 mod exit;
+mod wait;
@@ -0,0 +1,74 @@
+use alloc::vec::Vec;
+
+use lilium_sys::{
+    result::{Error, Result},
+    sys::handle::{Handle as SysHandle, HandlePtr},
+};
+use rustix::{
+    event::{Timespec, epoll},
+    io::Errno,
+};
+use wl_impl::{
+    export_syscall,
+    handle_base::Handle,
+    helpers::{ErrorContext, rustix_error_to_lilium},
+    user_ptr::{UserSlice, UserSliceMut},
+};
+
+/// Passed as the `timeout_ns` of [`AwaitHandles`] to wait without a timeout
+pub const TIMEOUT_INFINITE: u64 = u64::MAX;
+
+export_syscall! {
+    /// Blocks until at least one handle in `handles` is ready (a process or thread has exited, or an IO handle can be read or has hung up),
+    ///  or `timeout_ns` nanoseconds have passed, in which case `Timeout` is returned. A timeout of 0 polls the handles without blocking.
+    /// The indices of up to `ready_out.len` ready handles are written to `ready_out`, and the number of indices written is returned.
+    ///
+    /// Any handle that refers to a linux fd (pidfds, sockets, pipes, eventfds, timerfds) can be waited on, and other handles fail with `UnsupportedOperation`.
+    /// Fails with `InvalidOperation` if `handles` or `ready_out` is empty, since no ready handle could be reported.
+    unsafe extern fn AwaitHandles(handles: UserSlice<HandlePtr<SysHandle>>, ready_out: UserSliceMut<usize>, timeout_ns: u64) -> Result<usize> {
+        if handles.is_empty() || ready_out.is_empty() {
+            return Err(Error::InvalidOperation);
+        }
+
+        let epfd = epoll::create(epoll::CreateFlags::CLOEXEC)
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::General))?;
+
+        for (idx, hdl) in handles.iter()?.enumerate() {
+            let hdl = unsafe { Handle::try_deref((*hdl?).cast())? };
+            let fd = hdl.borrow_fd().ok_or(Error::UnsupportedOperation)?;
+
+            match epoll::add(&epfd, fd, epoll::EventData::new_u64(idx as u64), epoll::EventFlags::IN) {
+                // The same handle was given more than once, only the first index is reported
+                Ok(()) | Err(Errno::EXIST) => {}
+                Err(e) => return Err(rustix_error_to_lilium(e, ErrorContext::General)),
+            }
+        }
+
+        let timeout = match timeout_ns {
+            TIMEOUT_INFINITE => None,
+            ns => Some(Timespec {
+                tv_sec: (ns / 1_000_000_000) as i64,
+                tv_nsec: (ns % 1_000_000_000) as i64,
+            }),
+        };
+
+        let mut ready_out = ready_out;
+        let mut events = Vec::<epoll::Event>::with_capacity(ready_out.len().min(handles.len()));
+
+        let (events, _) = epoll::wait(&epfd, events.spare_capacity_mut(), timeout.as_ref())
+            .map_err(|e| rustix_error_to_lilium(e, ErrorContext::General))?;
+
+        if events.is_empty() {
+            return Err(Error::Timeout);
+        }
+
+        let mut count = 0;
+
+        for (out, ev) in ready_out.iter_mut()?.zip(events.iter()) {
+            *out? = ev.data.u64() as usize;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}